@@ -0,0 +1,22 @@
+//! Regenerates include/sonica.h from the exported functions and types when
+//! SONICA_GENERATE_HEADER is set, so ordinary builds leave the checked-in header alone
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=SONICA_GENERATE_HEADER");
+    if std::env::var_os("SONICA_GENERATE_HEADER").is_none() {
+        return;
+    }
+
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("cbindgen.toml is valid");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("C header can be generated")
+        .write_to_file(format!("{}/include/sonica.h", crate_dir));
+}
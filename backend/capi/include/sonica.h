@@ -0,0 +1,126 @@
+#ifndef SONICA_H
+#define SONICA_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of a call; the message of a failure is in `sonica_last_error`
+typedef enum SonicaStatus {
+  SONICA_STATUS_OK = 0,
+  // A null pointer, invalid UTF-8 or an out of range argument
+  SONICA_STATUS_INVALID_ARGUMENT = 1,
+  // Database, audio or internal failure
+  SONICA_STATUS_ERROR = 2,
+} SonicaStatus;
+
+// An open song database and its config
+// Safe to share between threads; calls on one engine are serialized by the database lock
+typedef struct SonicaEngine SonicaEngine;
+
+// Result of `sonica_index_pcm`
+typedef struct SonicaIndexed {
+  // The new song, or the existing song the audio duplicates
+  int64_t song_id;
+  // The audio was already indexed and was linked to `song_id` instead
+  bool duplicate;
+  // Fingerprints stored, 0 for duplicates
+  size_t hash_count;
+} SonicaIndexed;
+
+// One ranked candidate of a recognition
+typedef struct SonicaCandidate {
+  int64_t song_id;
+  int64_t raw_score;
+} SonicaCandidate;
+
+// Result of `sonica_recognize_pcm`, released with `sonica_recognition_free`
+typedef struct SonicaRecognition {
+  // The best candidate passed the match threshold
+  bool matched;
+  // Matched song, 0 without a match
+  int64_t song_id;
+  // Title and artist of the matched song, NULL without a match
+  char *title;
+  char *artist;
+  // Aligned hashes of the best candidate, matched or not
+  int64_t raw_score;
+  // Confidence of the best candidate (0-1)
+  float confidence;
+  uint64_t query_duration_ms;
+  size_t hash_count;
+  // Best candidates, best first, including those below the threshold
+  struct SonicaCandidate *candidates;
+  size_t candidate_count;
+} SonicaRecognition;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message of the last failed call on this thread, or NULL
+// Valid until the next failing call on the same thread
+const char *sonica_last_error(void);
+
+// Version of the fingerprints this library computes and indexes
+uint16_t sonica_fingerprint_version(void);
+
+// Open (or create) the song database at `db_path`
+// `config_path` is a `sonica.json`; NULL uses the server's default lookup
+//
+// # Safety
+// The strings must be NUL terminated and `engine_out` writable
+enum SonicaStatus sonica_engine_open(const char *db_path,
+                                     const char *config_path,
+                                     struct SonicaEngine **engine_out);
+
+// Close an engine; NULL is ignored
+//
+// # Safety
+// `engine` must come from `sonica_engine_open` and not be used afterwards
+void sonica_engine_free(struct SonicaEngine *engine);
+
+// Fingerprint and store `frame_count` frames of interleaved float32 PCM
+// `source` identifies the song uniquely, e.g. its file path or catalogue id;
+// `title` and `artist` may be NULL
+//
+// # Safety
+// `samples` must hold `frame_count * channels` floats, the strings must be
+// NUL terminated and `indexed_out` writable
+enum SonicaStatus sonica_index_pcm(const struct SonicaEngine *engine,
+                                   const char *source,
+                                   const char *title,
+                                   const char *artist,
+                                   const float *samples,
+                                   size_t frame_count,
+                                   uint32_t sample_rate,
+                                   uint32_t channels,
+                                   struct SonicaIndexed *indexed_out);
+
+// Recognize `frame_count` frames of interleaved float32 PCM
+// On success `recognition_out` owns memory to release with `sonica_recognition_free`
+//
+// # Safety
+// `samples` must hold `frame_count * channels` floats and `recognition_out` be writable
+enum SonicaStatus sonica_recognize_pcm(const struct SonicaEngine *engine,
+                                       const float *samples,
+                                       size_t frame_count,
+                                       uint32_t sample_rate,
+                                       uint32_t channels,
+                                       struct SonicaRecognition *recognition_out);
+
+// Release the strings and candidates of a recognition and reset it; NULL is ignored
+//
+// # Safety
+// `recognition` must have been filled by `sonica_recognize_pcm` and not freed since
+void sonica_recognition_free(struct SonicaRecognition *recognition);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SONICA_H */
@@ -0,0 +1,496 @@
+//! C ABI for embedding the indexer and recognizer in native apps
+//!
+//! An engine is an opaque handle on a song database and its config. Audio comes
+//! in as interleaved float32 PCM at any sample rate, so no FFmpeg is involved.
+//! Every fallible call returns a `SonicaStatus`; on failure `sonica_last_error`
+//! describes it. `include/sonica.h` is generated by cbindgen; rebuild with
+//! `SONICA_GENERATE_HEADER=1` after changing the exported API.
+
+use sonica_backend::config::Config;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::fingerprint::FINGERPRINT_VERSION;
+use sonica_backend::indexer::{index_samples, IndexOutcome};
+use sonica_backend::metadata::ExtractedMetadata;
+use sonica_backend::recognizer::recognize_samples;
+use sonica_backend::storage::Database;
+use sonica_backend::types::TrackDetails;
+use sonica_fingerprint::{downmix, resample};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Outcome of a call; the message of a failure is in `sonica_last_error`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SonicaStatus {
+    Ok = 0,
+    /// A null pointer, invalid UTF-8 or an out of range argument
+    InvalidArgument = 1,
+    /// Database, audio or internal failure
+    Error = 2,
+}
+
+/// An open song database and its config
+/// Safe to share between threads; calls on one engine are serialized by the database lock
+pub struct SonicaEngine {
+    db: Database,
+    config: Config,
+}
+
+/// Result of `sonica_index_pcm`
+#[repr(C)]
+pub struct SonicaIndexed {
+    /// The new song, or the existing song the audio duplicates
+    pub song_id: i64,
+    /// The audio was already indexed and was linked to `song_id` instead
+    pub duplicate: bool,
+    /// Fingerprints stored, 0 for duplicates
+    pub hash_count: usize,
+}
+
+/// One ranked candidate of a recognition
+#[repr(C)]
+pub struct SonicaCandidate {
+    pub song_id: i64,
+    pub raw_score: i64,
+}
+
+/// Result of `sonica_recognize_pcm`, released with `sonica_recognition_free`
+#[repr(C)]
+pub struct SonicaRecognition {
+    /// The best candidate passed the match threshold
+    pub matched: bool,
+    /// Matched song, 0 without a match
+    pub song_id: i64,
+    /// Title and artist of the matched song, NULL without a match
+    pub title: *mut c_char,
+    pub artist: *mut c_char,
+    /// Aligned hashes of the best candidate, matched or not
+    pub raw_score: i64,
+    /// Confidence of the best candidate (0-1)
+    pub confidence: f32,
+    pub query_duration_ms: u64,
+    pub hash_count: usize,
+    /// Best candidates, best first, including those below the threshold
+    pub candidates: *mut SonicaCandidate,
+    pub candidate_count: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run an exported call, turning errors and panics into a status
+fn ffi_call(call: impl FnOnce() -> Result<()>) -> SonicaStatus {
+    match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => SonicaStatus::Ok,
+        Ok(Err(e)) => {
+            let status = match e {
+                AppError::InvalidRequest(_) => SonicaStatus::InvalidArgument,
+                _ => SonicaStatus::Error,
+            };
+            set_last_error(e.to_string());
+            status
+        }
+        Err(_) => {
+            set_last_error("Internal error: panic in the recognition engine".to_string());
+            SonicaStatus::Error
+        }
+    }
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(message.to_string())
+}
+
+/// A required UTF-8 string argument
+unsafe fn string_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str> {
+    optional_string_arg(value, name)?.ok_or_else(|| invalid(&format!("{} is NULL", name)))
+}
+
+unsafe fn optional_string_arg<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map(Some)
+        .map_err(|_| invalid(&format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn engine_arg<'a>(engine: *const SonicaEngine) -> Result<&'a SonicaEngine> {
+    engine.as_ref().ok_or_else(|| invalid("engine is NULL"))
+}
+
+/// Interleaved PCM to 16kHz mono
+unsafe fn pcm_arg(
+    samples: *const f32,
+    frame_count: usize,
+    sample_rate: u32,
+    channels: u32,
+) -> Result<Vec<f32>> {
+    if samples.is_null() {
+        return Err(invalid("samples is NULL"));
+    }
+    if sample_rate == 0 || channels == 0 {
+        return Err(invalid("sample_rate and channels must be positive"));
+    }
+    let len = frame_count
+        .checked_mul(channels as usize)
+        .ok_or_else(|| invalid("frame_count is too large"))?;
+    let interleaved = std::slice::from_raw_parts(samples, len);
+    Ok(resample(
+        &downmix(interleaved, channels as usize),
+        sample_rate,
+    ))
+}
+
+fn c_string(value: String) -> *mut c_char {
+    CString::new(value.replace('\0', " "))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// Message of the last failed call on this thread, or NULL
+/// Valid until the next failing call on the same thread
+#[no_mangle]
+pub extern "C" fn sonica_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Version of the fingerprints this library computes and indexes
+#[no_mangle]
+pub extern "C" fn sonica_fingerprint_version() -> u16 {
+    FINGERPRINT_VERSION
+}
+
+/// Open (or create) the song database at `db_path`
+/// `config_path` is a `sonica.json`; NULL uses the server's default lookup
+///
+/// # Safety
+/// The strings must be NUL terminated and `engine_out` writable
+#[no_mangle]
+pub unsafe extern "C" fn sonica_engine_open(
+    db_path: *const c_char,
+    config_path: *const c_char,
+    engine_out: *mut *mut SonicaEngine,
+) -> SonicaStatus {
+    ffi_call(|| {
+        if engine_out.is_null() {
+            return Err(invalid("engine_out is NULL"));
+        }
+        let db_path = string_arg(db_path, "db_path")?;
+        let config = match optional_string_arg(config_path, "config_path")? {
+            Some(path) => Config::load(path)?,
+            None => Config::load(Config::path())?,
+        };
+        let db = Database::new(db_path)?;
+        db.adopt_fingerprint_settings(&config.fingerprint)?;
+        let engine = SonicaEngine { db, config };
+        *engine_out = Box::into_raw(Box::new(engine));
+        Ok(())
+    })
+}
+
+/// Close an engine; NULL is ignored
+///
+/// # Safety
+/// `engine` must come from `sonica_engine_open` and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn sonica_engine_free(engine: *mut SonicaEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Fingerprint and store `frame_count` frames of interleaved float32 PCM
+/// `source` identifies the song uniquely, e.g. its file path or catalogue id;
+/// `title` and `artist` may be NULL
+///
+/// # Safety
+/// `samples` must hold `frame_count * channels` floats, the strings must be
+/// NUL terminated and `indexed_out` writable
+#[no_mangle]
+pub unsafe extern "C" fn sonica_index_pcm(
+    engine: *const SonicaEngine,
+    source: *const c_char,
+    title: *const c_char,
+    artist: *const c_char,
+    samples: *const f32,
+    frame_count: usize,
+    sample_rate: u32,
+    channels: u32,
+    indexed_out: *mut SonicaIndexed,
+) -> SonicaStatus {
+    ffi_call(|| {
+        let engine = engine_arg(engine)?;
+        let indexed_out = indexed_out
+            .as_mut()
+            .ok_or_else(|| invalid("indexed_out is NULL"))?;
+        let source = string_arg(source, "source")?;
+        let metadata = ExtractedMetadata {
+            title: optional_string_arg(title, "title")?
+                .unwrap_or("Unknown")
+                .to_string(),
+            artist: optional_string_arg(artist, "artist")?
+                .unwrap_or("Unknown")
+                .to_string(),
+            details: TrackDetails::default(),
+            artwork: None,
+        };
+        let samples = pcm_arg(samples, frame_count, sample_rate, channels)?;
+
+        *indexed_out = match index_samples(&engine.db, source, &metadata, &samples)? {
+            IndexOutcome::Indexed {
+                song_id,
+                hash_count,
+            } => SonicaIndexed {
+                song_id,
+                duplicate: false,
+                hash_count,
+            },
+            IndexOutcome::Duplicate { song_id, .. } => SonicaIndexed {
+                song_id,
+                duplicate: true,
+                hash_count: 0,
+            },
+        };
+        Ok(())
+    })
+}
+
+/// Recognize `frame_count` frames of interleaved float32 PCM
+/// On success `recognition_out` owns memory to release with `sonica_recognition_free`
+///
+/// # Safety
+/// `samples` must hold `frame_count * channels` floats and `recognition_out` be writable
+#[no_mangle]
+pub unsafe extern "C" fn sonica_recognize_pcm(
+    engine: *const SonicaEngine,
+    samples: *const f32,
+    frame_count: usize,
+    sample_rate: u32,
+    channels: u32,
+    recognition_out: *mut SonicaRecognition,
+) -> SonicaStatus {
+    ffi_call(|| {
+        let engine = engine_arg(engine)?;
+        let recognition_out = recognition_out
+            .as_mut()
+            .ok_or_else(|| invalid("recognition_out is NULL"))?;
+        let samples = pcm_arg(samples, frame_count, sample_rate, channels)?;
+
+        let recognition = recognize_samples(&engine.db, &engine.config, &samples, None)?;
+
+        let candidates: Box<[SonicaCandidate]> = recognition
+            .candidates
+            .iter()
+            .map(|&(song_id, raw_score)| SonicaCandidate { song_id, raw_score })
+            .collect();
+        let candidate_count = candidates.len();
+        let raw_score = recognition.raw_score();
+
+        *recognition_out = SonicaRecognition {
+            matched: recognition.song.is_some(),
+            song_id: recognition.song.as_ref().map_or(0, |song| song.id),
+            title: recognition
+                .song
+                .as_ref()
+                .map_or(ptr::null_mut(), |song| c_string(song.title.clone())),
+            artist: recognition
+                .song
+                .as_ref()
+                .map_or(ptr::null_mut(), |song| c_string(song.artist.clone())),
+            raw_score,
+            confidence: recognition.confidence,
+            query_duration_ms: recognition.query_duration_ms,
+            hash_count: recognition.hash_count,
+            candidates: Box::into_raw(candidates) as *mut SonicaCandidate,
+            candidate_count,
+        };
+        Ok(())
+    })
+}
+
+/// Release the strings and candidates of a recognition and reset it; NULL is ignored
+///
+/// # Safety
+/// `recognition` must have been filled by `sonica_recognize_pcm` and not freed since
+#[no_mangle]
+pub unsafe extern "C" fn sonica_recognition_free(recognition: *mut SonicaRecognition) {
+    let Some(recognition) = recognition.as_mut() else {
+        return;
+    };
+    if !recognition.title.is_null() {
+        drop(CString::from_raw(recognition.title));
+    }
+    if !recognition.artist.is_null() {
+        drop(CString::from_raw(recognition.artist));
+    }
+    if !recognition.candidates.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            recognition.candidates,
+            recognition.candidate_count,
+        )));
+    }
+    *recognition = SonicaRecognition {
+        matched: false,
+        song_id: 0,
+        title: ptr::null_mut(),
+        artist: ptr::null_mut(),
+        raw_score: 0,
+        confidence: 0.0,
+        query_duration_ms: 0,
+        hash_count: 0,
+        candidates: ptr::null_mut(),
+        candidate_count: 0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonica_backend::test_support::song;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn open_engine() -> *mut SonicaEngine {
+        let db_path = CString::new(":memory:").unwrap();
+        // A config that doesn't exist, so the defaults are used whatever is in the working directory
+        let config_path = CString::new("missing/sonica.json").unwrap();
+        let mut engine = ptr::null_mut();
+        let status =
+            unsafe { sonica_engine_open(db_path.as_ptr(), config_path.as_ptr(), &mut engine) };
+        assert_eq!(status, SonicaStatus::Ok);
+        assert!(!engine.is_null());
+        engine
+    }
+
+    fn last_error() -> String {
+        let message = sonica_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn indexes_and_recognizes_pcm() {
+        let engine = open_engine();
+        let samples = song(7, 30.0);
+        let source = CString::new("synthetic/7").unwrap();
+        let title = CString::new("Song 7").unwrap();
+        let mut indexed = SonicaIndexed {
+            song_id: 0,
+            duplicate: true,
+            hash_count: 0,
+        };
+        let status = unsafe {
+            sonica_index_pcm(
+                engine,
+                source.as_ptr(),
+                title.as_ptr(),
+                ptr::null(),
+                samples.as_ptr(),
+                samples.len(),
+                SAMPLE_RATE,
+                1,
+                &mut indexed,
+            )
+        };
+        assert_eq!(status, SonicaStatus::Ok);
+        assert!(!indexed.duplicate);
+        assert!(indexed.hash_count > 0);
+
+        // A stereo clip from the middle of the song, so it's downmixed on the way in
+        let clip = &samples[10 * SAMPLE_RATE as usize..20 * SAMPLE_RATE as usize];
+        let stereo: Vec<f32> = clip.iter().flat_map(|&sample| [sample, sample]).collect();
+        let mut recognition: SonicaRecognition = unsafe { std::mem::zeroed() };
+        let status = unsafe {
+            sonica_recognize_pcm(
+                engine,
+                stereo.as_ptr(),
+                clip.len(),
+                SAMPLE_RATE,
+                2,
+                &mut recognition,
+            )
+        };
+        assert_eq!(status, SonicaStatus::Ok);
+        assert!(recognition.matched);
+        assert_eq!(recognition.song_id, indexed.song_id);
+        let title = unsafe { CStr::from_ptr(recognition.title) };
+        assert_eq!(title.to_str().unwrap(), "Song 7");
+        let artist = unsafe { CStr::from_ptr(recognition.artist) };
+        assert_eq!(artist.to_str().unwrap(), "Unknown");
+        assert!(recognition.candidate_count > 0);
+        let best = unsafe { &*recognition.candidates };
+        assert_eq!(best.song_id, indexed.song_id);
+        assert_eq!(best.raw_score, recognition.raw_score);
+
+        unsafe { sonica_recognition_free(&mut recognition) };
+        assert!(recognition.title.is_null());
+        assert!(recognition.artist.is_null());
+        assert!(recognition.candidates.is_null());
+        assert_eq!(recognition.candidate_count, 0);
+        // Freeing twice is harmless once the recognition is reset
+        unsafe { sonica_recognition_free(&mut recognition) };
+
+        unsafe { sonica_engine_free(engine) };
+    }
+
+    #[test]
+    fn invalid_arguments_set_the_last_error() {
+        let mut engine = ptr::null_mut();
+        let status = unsafe { sonica_engine_open(ptr::null(), ptr::null(), &mut engine) };
+        assert_eq!(status, SonicaStatus::InvalidArgument);
+        assert!(engine.is_null());
+        assert!(last_error().contains("db_path is NULL"));
+
+        let engine = open_engine();
+        let samples = [0.0f32; 16];
+        let mut recognition: SonicaRecognition = unsafe { std::mem::zeroed() };
+        let status =
+            unsafe { sonica_recognize_pcm(engine, samples.as_ptr(), 16, 0, 1, &mut recognition) };
+        assert_eq!(status, SonicaStatus::InvalidArgument);
+        assert!(last_error().contains("sample_rate and channels must be positive"));
+
+        let status = unsafe {
+            sonica_recognize_pcm(
+                ptr::null(),
+                samples.as_ptr(),
+                16,
+                SAMPLE_RATE,
+                1,
+                &mut recognition,
+            )
+        };
+        assert_eq!(status, SonicaStatus::InvalidArgument);
+        assert!(last_error().contains("engine is NULL"));
+
+        let status = unsafe {
+            sonica_index_pcm(
+                engine,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                samples.as_ptr(),
+                16,
+                SAMPLE_RATE,
+                1,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, SonicaStatus::InvalidArgument);
+        assert!(last_error().contains("indexed_out is NULL"));
+
+        unsafe { sonica_engine_free(engine) };
+    }
+}
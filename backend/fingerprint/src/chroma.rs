@@ -0,0 +1,117 @@
+//! Chroma features for cover identification
+//!
+//! A chroma vector folds the spectrum onto the 12 pitch classes, keeping the
+//! harmony and melody of a song while discarding octave, timbre and most of the
+//! mix. Covers and live versions share it even when they share no spectral peaks.
+
+use crate::{bin_to_hz, spectrogram, HOP_SIZE, SAMPLE_RATE};
+
+/// Energy per pitch class, C first
+pub type Chroma = [f32; 12];
+
+/// Spectrogram frames averaged into one chroma step, about half a second
+pub const CHROMA_STEP_FRAMES: usize = 4;
+/// Lowest frequency folded in; below it a bin spans more than a semitone
+const MIN_HZ: f32 = 100.0;
+/// Highest frequency folded in, past most fundamentals
+const MAX_HZ: f32 = 2000.0;
+
+/// Milliseconds of audio per chroma step
+pub fn chroma_step_ms() -> u64 {
+    (CHROMA_STEP_FRAMES * HOP_SIZE) as u64 * 1000 / SAMPLE_RATE as u64
+}
+
+/// Chroma of 16kHz mono samples, one vector per `CHROMA_STEP_FRAMES` frames,
+/// each scaled so its loudest pitch class is 1 (silence stays all zero)
+pub fn chroma_sequence(samples: &[f32]) -> Vec<Chroma> {
+    let spec = spectrogram(samples);
+    let classes = pitch_classes(spec.first().map_or(0, Vec::len));
+
+    spec.chunks_exact(CHROMA_STEP_FRAMES)
+        .map(|frames| {
+            let mut chroma = [0.0; 12];
+            for frame in frames {
+                for &(bin, class) in &classes {
+                    // Only spectral peaks: tones have them, drums and noise mostly don't
+                    if frame[bin] > frame[bin - 1] && frame[bin] >= frame[bin + 1] {
+                        // Log compression, so loud partials don't drown the rest of the chord
+                        chroma[class] += frame[bin].ln_1p();
+                    }
+                }
+            }
+            let max = chroma.iter().copied().fold(0.0, f32::max);
+            if max > 0.0 {
+                chroma.iter_mut().for_each(|value| *value /= max);
+            }
+            chroma
+        })
+        .collect()
+}
+
+/// Rotate a chroma vector up by `semitones`, so C becomes the class `semitones` above it
+pub fn transpose(chroma: &Chroma, semitones: usize) -> Chroma {
+    let mut rotated = [0.0; 12];
+    for (class, &value) in chroma.iter().enumerate() {
+        rotated[(class + semitones) % 12] = value;
+    }
+    rotated
+}
+
+/// (bin, pitch class) of every bin between `MIN_HZ` and `MAX_HZ`
+fn pitch_classes(bins: usize) -> Vec<(usize, usize)> {
+    (0..bins)
+        .filter(|&bin| (MIN_HZ..=MAX_HZ).contains(&bin_to_hz(bin)))
+        .map(|bin| {
+            // MIDI note 69 is A4 = 440 Hz, and note 0 is a C
+            let note = 69.0 + 12.0 * (bin_to_hz(bin) / 440.0).log2();
+            (bin, note.round() as usize % 12)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(SAMPLE_RATE as f32 * seconds) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    fn loudest_class(chroma: &Chroma) -> usize {
+        (0..12)
+            .max_by(|&a, &b| chroma[a].total_cmp(&chroma[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn tones_fold_onto_their_pitch_class() {
+        // A4 and A3 are both class 9; C5 is class 0
+        for (frequency, class) in [(440.0, 9), (220.0, 9), (523.25, 0)] {
+            let sequence = chroma_sequence(&tone(frequency, 3.0));
+            assert!(!sequence.is_empty());
+            for chroma in &sequence {
+                assert_eq!(loudest_class(chroma), class);
+                assert_eq!(chroma[class], 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn transposing_rotates_classes() {
+        let mut chroma = [0.0; 12];
+        chroma[9] = 1.0;
+        assert_eq!(loudest_class(&transpose(&chroma, 3)), 0);
+        assert_eq!(transpose(&transpose(&chroma, 5), 7), chroma);
+    }
+
+    #[test]
+    fn silence_has_empty_chroma() {
+        let sequence = chroma_sequence(&vec![0.0; SAMPLE_RATE as usize * 2]);
+        assert!(sequence
+            .iter()
+            .all(|chroma| chroma.iter().all(|&v| v == 0.0)));
+        assert_eq!(chroma_step_ms(), 512);
+    }
+}
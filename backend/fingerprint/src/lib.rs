@@ -0,0 +1,382 @@
+//! Fingerprint DSP core: spectrogram, constellation peaks and combinatorial hashes
+//!
+//! Pure Rust with no FFmpeg, filesystem or database access, so the same code runs
+//! in the backend and, built for `wasm32-unknown-unknown` with the `wasm` feature,
+//! in the browser. Input is 16kHz mono; `downmix` and `resample` turn raw capture
+//! audio into that without FFmpeg.
+
+use rustfft::{num_complex::Complex, FftPlanner};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+mod chroma;
+mod preprocess;
+mod triplets;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use chroma::{chroma_sequence, chroma_step_ms, transpose, Chroma, CHROMA_STEP_FRAMES};
+pub use preprocess::{Normalization, Preprocessing};
+pub use triplets::{hash_triplets, triplets, Triplet};
+
+/// Version of the hash layout and DSP parameters below
+/// Bump it whenever a change makes new fingerprints incompatible with indexed ones
+pub const FINGERPRINT_VERSION: u16 = 1;
+
+pub const SAMPLE_RATE: u32 = 16000;
+const WINDOW_SIZE: usize = 4096; // Better frequency resolution
+const HOP_SIZE: usize = 2048; // 50% overlap
+/// Frequency bins per spectrogram frame
+pub const FREQUENCY_BINS: usize = WINDOW_SIZE / 2;
+
+/// Upper edges in bins of the bands `find_peaks` picks a peak from each frame, the last
+/// band running to the top; low, low-mid and high-mid so peaks cover the spectrum
+pub(crate) const PEAK_BAND_EDGES: [usize; 3] = [50, 200, 500];
+
+/// Zero crossings of the resampling filter on each side of a sample
+const RESAMPLE_ZERO_CROSSINGS: usize = 16;
+
+/// Duration in milliseconds of samples decoded from a preprocessed file
+pub fn duration_ms(samples: &[f32]) -> u64 {
+    samples.len() as u64 * 1000 / SAMPLE_RATE as u64
+}
+
+/// Number of samples in `ms` milliseconds of preprocessed audio
+pub fn ms_to_samples(ms: u64) -> usize {
+    (ms * SAMPLE_RATE as u64 / 1000) as usize
+}
+
+/// Duration in milliseconds of `frames` spectrogram frames, the unit of fingerprint offsets
+pub fn frames_to_ms(frames: i64) -> i64 {
+    frames * HOP_SIZE as i64 * 1000 / SAMPLE_RATE as i64
+}
+
+/// Centre frequency in Hz of spectrogram bin `bin`
+pub fn bin_to_hz(bin: usize) -> f32 {
+    bin as f32 * SAMPLE_RATE as f32 / WINDOW_SIZE as f32
+}
+
+/// Average interleaved channels into mono
+pub fn downmix(interleaved: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return interleaved.to_vec();
+    }
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Resample mono audio to 16kHz with a Hann-windowed sinc filter,
+/// low-passed below the lower Nyquist frequency like FFmpeg's resampler
+pub fn resample(samples: &[f32], from_rate: u32) -> Vec<f32> {
+    if from_rate == SAMPLE_RATE || from_rate == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = SAMPLE_RATE as f64 / from_rate as f64;
+    // Cutoff as a fraction of the input Nyquist frequency
+    let cutoff = ratio.min(1.0);
+    let half_width = (RESAMPLE_ZERO_CROSSINGS as f64 / cutoff).ceil() as isize;
+    let last_input = samples.len() as isize - 1;
+    let output_len = (samples.len() as f64 * ratio) as usize;
+
+    (0..output_len)
+        .map(|n| {
+            let center = n as f64 / ratio;
+            let first = (center.floor() as isize - half_width + 1).max(0);
+            let last = (center.floor() as isize + half_width).min(last_input);
+
+            let mut sum = 0.0;
+            for i in first..=last {
+                let distance = i as f64 - center;
+                let window =
+                    0.5 + 0.5 * (std::f64::consts::PI * distance / half_width as f64).cos();
+                sum += samples[i as usize] as f64 * cutoff * sinc(distance * cutoff) * window;
+            }
+            sum as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Generate spectrogram (STFT)
+pub fn spectrogram(samples: &[f32]) -> Vec<Vec<f32>> {
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(WINDOW_SIZE);
+
+    if samples.len() < WINDOW_SIZE {
+        return Vec::new();
+    }
+
+    let num_frames = (samples.len() - WINDOW_SIZE) / HOP_SIZE;
+    let mut spectrogram = Vec::with_capacity(num_frames);
+
+    // Hanning window
+    let window: Vec<f32> = (0..WINDOW_SIZE)
+        .map(|i| {
+            0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / (WINDOW_SIZE - 1) as f32).cos())
+        })
+        .collect();
+
+    for i in 0..num_frames {
+        let start = i * HOP_SIZE;
+        let end = start + WINDOW_SIZE;
+        let chunk = &samples[start..end];
+
+        let mut buffer: Vec<Complex<f32>> = chunk
+            .iter()
+            .zip(&window)
+            .map(|(&s, &w)| Complex::new(s * w, 0.0))
+            .collect();
+
+        fft.process(&mut buffer);
+
+        // Keep magnitude of first half (Nyquist)
+        let magnitude: Vec<f32> = buffer[0..FREQUENCY_BINS].iter().map(|c| c.norm()).collect();
+
+        spectrogram.push(magnitude);
+    }
+
+    spectrogram
+}
+
+/// Find peaks in spectrogram (Constellation Map)
+#[allow(clippy::needless_range_loop)]
+pub fn find_peaks(spectrogram: &[Vec<f32>]) -> Vec<(usize, usize)> {
+    let rows = spectrogram.len();
+    if rows == 0 {
+        return Vec::new();
+    }
+    let cols = spectrogram[0].len();
+    let mut peaks = Vec::new();
+
+    // Divide into frequency bands to ensure peaks across spectrum
+    // e.g., Low, Mid, High
+    let [low, mid, high] = PEAK_BAND_EDGES;
+    let bands = [(0, low), (low, mid), (mid, high), (high, cols)];
+
+    for (start_bin, end_bin) in bands {
+        for t in 0..rows {
+            let mut max_val = 0.0;
+            let mut max_freq = 0;
+
+            // Find max in this band for this time frame
+            for f in start_bin..end_bin.min(cols) {
+                if spectrogram[t][f] > max_val {
+                    max_val = spectrogram[t][f];
+                    max_freq = f;
+                }
+            }
+
+            // Simple local maximum check (time axis)
+            // Check if it's a peak compared to neighbors
+            if max_val > 1.0 {
+                // Noise threshold
+                let mut is_peak = true;
+                // Check +/- 2 frames
+                for dt in 1..=2 {
+                    if t >= dt && spectrogram[t - dt][max_freq] > max_val {
+                        is_peak = false;
+                        break;
+                    }
+                    if t + dt < rows && spectrogram[t + dt][max_freq] > max_val {
+                        is_peak = false;
+                        break;
+                    }
+                }
+
+                if is_peak {
+                    peaks.push((t, max_freq));
+                }
+            }
+        }
+    }
+
+    peaks
+}
+
+/// How an index turns audio into fingerprints
+/// Queries must use the settings the index was built with, or their hashes won't line up
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct FingerprintSettings {
+    pub scheme: HashScheme,
+    pub preprocessing: Preprocessing,
+}
+
+/// What the hashes of an index encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum HashScheme {
+    /// Absolute frequencies and time difference of peak pairs, see `hash_peaks`;
+    /// the most selective, but only matches audio at its original pitch and tempo
+    #[default]
+    Pairs,
+    /// Frequency and time ratios of peak triplets, see `hash_triplets`; also
+    /// matches pitch-shifted, time-stretched and sped-up audio
+    Triplets,
+}
+
+impl HashScheme {
+    /// Generate this scheme's hashes from peaks
+    /// Returns: (hash, time_offset)
+    pub fn hash(&self, peaks: Vec<(usize, usize)>) -> Vec<(u32, u32)> {
+        match self {
+            Self::Pairs => hash_peaks(peaks),
+            Self::Triplets => hash_triplets(peaks),
+        }
+    }
+}
+
+/// Generate hashes from 16kHz mono samples with the default settings
+/// Returns: (hash, time_offset)
+pub fn generate_fingerprints(samples: &[f32]) -> Vec<(u32, u32)> {
+    fingerprint(samples, &FingerprintSettings::default())
+}
+
+/// Generate hashes from 16kHz mono samples with an index's settings
+/// Returns: (hash, time_offset)
+pub fn fingerprint(samples: &[f32], settings: &FingerprintSettings) -> Vec<(u32, u32)> {
+    let peaks = find_peaks(&conditioned_spectrogram(samples, &settings.preprocessing));
+    settings.scheme.hash(peaks)
+}
+
+/// The spectrogram peaks are picked from: `spectrogram` with the preprocessing stages applied
+pub fn conditioned_spectrogram(samples: &[f32], preprocessing: &Preprocessing) -> Vec<Vec<f32>> {
+    if !preprocessing.is_enabled() {
+        return spectrogram(samples);
+    }
+    let mut spec = spectrogram(&preprocessing.filter(samples));
+    preprocessing.condition(&mut spec);
+    spec
+}
+
+/// Generate hashes from peaks (Combinatorial Hashing)
+/// Returns: (hash, time_offset)
+#[allow(clippy::needless_range_loop)]
+pub fn hash_peaks(mut peaks: Vec<(usize, usize)>) -> Vec<(u32, u32)> {
+    // Sort peaks by time (t) to ensure t2 > t1 in the loop
+    peaks.sort_by_key(|k| k.0);
+
+    let mut fingerprints = Vec::new();
+
+    // Target zone: look ahead in time
+    let target_zone_start = 5; // frames ahead
+    let target_zone_end = 50; // frames ahead
+
+    for i in 0..peaks.len() {
+        let (t1, f1) = peaks[i];
+
+        for j in (i + 1)..peaks.len() {
+            let (t2, f2) = peaks[j];
+            let dt = t2 - t1;
+
+            if dt < target_zone_start {
+                continue;
+            }
+            if dt > target_zone_end {
+                break;
+            } // Peaks are sorted by time usually
+
+            // Hash: [f1: 9 bits] [f2: 9 bits] [dt: 14 bits]
+            // f1, f2 are bin indices. WINDOW=4096, bins=2048.
+            // We care mostly about lower 512 bins (0-2kHz) where music energy is.
+            // Let's mask to 9 bits (511).
+
+            if f1 < 512 && f2 < 512 {
+                let hash = ((f1 as u32) << 23) | ((f2 as u32) << 14) | (dt as u32);
+                fingerprints.push((hash, t1 as u32));
+            }
+        }
+    }
+
+    fingerprints
+}
+
+/// The two peaks a fingerprint was hashed from, as ((t1, f1), (t2, f2))
+/// Inverse of the hash layout in `hash_peaks`
+pub fn hash_peak_pair(hash: u32, offset: u32) -> ((usize, usize), (usize, usize)) {
+    let f1 = (hash >> 23) as usize;
+    let f2 = ((hash >> 14) & 0x1FF) as usize;
+    let dt = (hash & 0x3FFF) as usize;
+    let t1 = offset as usize;
+    ((t1, f1), (t1 + dt, f2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, rate: u32, seconds: f32) -> Vec<f32> {
+        (0..(rate as f32 * seconds) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    fn loudest_bin(samples: &[f32]) -> usize {
+        let spec = spectrogram(samples);
+        let frame = &spec[spec.len() / 2];
+        (0..frame.len())
+            .max_by(|&a, &b| frame[a].total_cmp(&frame[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn tone_peaks_at_its_bin() {
+        let bin = loudest_bin(&sine(1000.0, SAMPLE_RATE, 2.0));
+        assert_eq!(bin, 256);
+        assert_eq!(bin_to_hz(bin), 1000.0);
+    }
+
+    #[test]
+    fn resampling_keeps_pitch_and_duration() {
+        let resampled = resample(&sine(1000.0, 44100, 2.0), 44100);
+        assert_eq!(resampled.len(), 2 * SAMPLE_RATE as usize);
+        assert_eq!(loudest_bin(&resampled), 256);
+    }
+
+    #[test]
+    fn downmix_averages_channels() {
+        assert_eq!(downmix(&[1.0, 0.0, 0.5, 0.5], 2), vec![0.5, 0.5]);
+        assert_eq!(downmix(&[0.25, 0.5], 1), vec![0.25, 0.5]);
+    }
+
+    #[test]
+    fn hash_peak_pair_inverts_hash_peaks() {
+        let peaks = vec![(0, 40), (7, 300), (20, 120), (3, 600)];
+        let fingerprints = hash_peaks(peaks.clone());
+        assert!(!fingerprints.is_empty());
+        for (hash, offset) in fingerprints {
+            let (first, second) = hash_peak_pair(hash, offset);
+            assert!(peaks.contains(&first));
+            assert!(peaks.contains(&second));
+            assert!(second.0 - first.0 >= 5);
+        }
+    }
+
+    #[test]
+    fn short_audio_has_no_fingerprints() {
+        assert!(spectrogram(&[0.5; WINDOW_SIZE - 1]).is_empty());
+        assert!(generate_fingerprints(&[0.0; SAMPLE_RATE as usize]).is_empty());
+    }
+
+    #[test]
+    fn time_conversions_round_trip() {
+        assert_eq!(ms_to_samples(1000), SAMPLE_RATE as usize);
+        assert_eq!(duration_ms(&vec![0.0; ms_to_samples(2500)]), 2500);
+        assert_eq!(frames_to_ms(125), 16000);
+    }
+}
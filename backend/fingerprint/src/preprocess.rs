@@ -0,0 +1,262 @@
+//! Optional conditioning for noisy captures, around the STFT
+//!
+//! High-pass and pre-emphasis filter the samples; noise reduction and
+//! normalisation rework the magnitude spectrogram before `find_peaks`. The
+//! spectral stages estimate noise and level over a sliding window of frames, so
+//! a clip is treated like the same stretch of the full song at index time.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Frames on each side of a frame in the noise and level estimates, about 2 seconds
+const CONTEXT_FRAMES: usize = 16;
+/// Percentile of a bin's magnitude over the context taken as its noise floor
+const NOISE_PERCENTILE: f32 = 0.2;
+/// Share of the original magnitude kept where subtraction would go below it,
+/// which avoids the "musical noise" of bins zeroed at random
+const NOISE_FLOOR: f32 = 0.05;
+/// `find_peaks` ignores peaks below 1.0; normalised magnitudes are scaled so
+/// that means a peak must stand this far above its surroundings
+const NORMALIZED_PEAK_RATIO: f32 = 2.0;
+/// Magnitudes at or below this, some 90 dB under a full-scale tone, stay at
+/// zero when normalised, so silence and dither don't turn into peaks. Lower than
+/// `find_peaks`' threshold since the filters and noise reduction lower quiet input further
+const MIN_MAGNITUDE: f32 = 0.01;
+
+/// Every stage is off by default, which leaves fingerprints unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct Preprocessing {
+    /// Second-order Butterworth high-pass cutoff in Hz, against rumble, wind and engine noise
+    pub high_pass_hz: Option<f32>,
+    /// Pre-emphasis coefficient, e.g. 0.97: `y[n] = x[n] - a * x[n-1]` lifts the highs
+    /// that music keeps and broadband room noise buries
+    pub pre_emphasis: Option<f32>,
+    /// Spectral subtraction strength: this many times each bin's noise floor is removed
+    pub noise_reduction: Option<f32>,
+    /// Level normalisation of the magnitudes before peak picking
+    pub normalization: Option<Normalization>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Normalization {
+    /// Divide each bin by its mean over the surrounding frames, flattening steady
+    /// hums and tones so changes in the music stand out
+    Whitening,
+    /// Divide each peak band of a frame by the band's mean, so every band
+    /// competes on its own contrast rather than absolute level
+    Bands,
+}
+
+impl Preprocessing {
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// The time-domain stages: high-pass, then pre-emphasis
+    pub fn filter(&self, samples: &[f32]) -> Vec<f32> {
+        let mut samples = match self.high_pass_hz {
+            Some(cutoff_hz) => high_pass(samples, cutoff_hz),
+            None => samples.to_vec(),
+        };
+        if let Some(coefficient) = self.pre_emphasis {
+            pre_emphasis(&mut samples, coefficient);
+        }
+        samples
+    }
+
+    /// The spectral stages: noise reduction, then normalisation
+    pub fn condition(&self, spectrogram: &mut [Vec<f32>]) {
+        if let Some(strength) = self.noise_reduction {
+            subtract_noise(spectrogram, strength);
+        }
+        match self.normalization {
+            Some(Normalization::Whitening) => whiten(spectrogram),
+            Some(Normalization::Bands) => normalize_bands(spectrogram),
+            None => {}
+        }
+    }
+}
+
+/// Biquad high-pass with Q = 1/sqrt(2)
+fn high_pass(samples: &[f32], cutoff_hz: f32) -> Vec<f32> {
+    let omega = 2.0 * std::f32::consts::PI * cutoff_hz / crate::SAMPLE_RATE as f32;
+    let alpha = omega.sin() / std::f32::consts::SQRT_2;
+    let cos = omega.cos();
+    let a0 = 1.0 + alpha;
+    let b0 = (1.0 + cos) / 2.0 / a0;
+    let b1 = -(1.0 + cos) / a0;
+    let a1 = -2.0 * cos / a0;
+    let a2 = (1.0 - alpha) / a0;
+
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    samples
+        .iter()
+        .map(|&x| {
+            let y = b0 * x + b1 * x1 + b0 * x2 - a1 * y1 - a2 * y2;
+            x2 = x1;
+            x1 = x;
+            y2 = y1;
+            y1 = y;
+            y
+        })
+        .collect()
+}
+
+fn pre_emphasis(samples: &mut [f32], coefficient: f32) {
+    let mut previous = 0.0;
+    for sample in samples.iter_mut() {
+        let current = *sample;
+        *sample = current - coefficient * previous;
+        previous = current;
+    }
+}
+
+/// Frames within `CONTEXT_FRAMES` of frame `t`
+fn context(t: usize, frames: usize) -> std::ops::Range<usize> {
+    t.saturating_sub(CONTEXT_FRAMES)..(t + CONTEXT_FRAMES + 1).min(frames)
+}
+
+fn subtract_noise(spectrogram: &mut [Vec<f32>], strength: f32) {
+    let frames = spectrogram.len();
+    let Some(bins) = spectrogram.first().map(Vec::len) else {
+        return;
+    };
+
+    let mut window = Vec::with_capacity(2 * CONTEXT_FRAMES + 1);
+    let mut column = vec![0.0; frames];
+    for bin in 0..bins {
+        for (t, frame) in spectrogram.iter().enumerate() {
+            column[t] = frame[bin];
+        }
+        for (t, frame) in spectrogram.iter_mut().enumerate() {
+            window.clear();
+            window.extend_from_slice(&column[context(t, frames)]);
+            let rank = ((window.len() - 1) as f32 * NOISE_PERCENTILE) as usize;
+            let noise = *window.select_nth_unstable_by(rank, f32::total_cmp).1;
+
+            let magnitude = column[t];
+            frame[bin] = (magnitude - strength * noise).max(NOISE_FLOOR * magnitude);
+        }
+    }
+}
+
+fn whiten(spectrogram: &mut [Vec<f32>]) {
+    let frames = spectrogram.len();
+    let Some(bins) = spectrogram.first().map(Vec::len) else {
+        return;
+    };
+
+    // Running sums per bin, so each mean is two lookups
+    let mut prefix = vec![vec![0.0f64; bins]; frames + 1];
+    for (t, frame) in spectrogram.iter().enumerate() {
+        for bin in 0..bins {
+            prefix[t + 1][bin] = prefix[t][bin] + frame[bin] as f64;
+        }
+    }
+
+    for (t, frame) in spectrogram.iter_mut().enumerate() {
+        let range = context(t, frames);
+        let count = range.len() as f64;
+        for (bin, magnitude) in frame.iter_mut().enumerate() {
+            let mean = (prefix[range.end][bin] - prefix[range.start][bin]) / count;
+            *magnitude = scale_to_threshold(*magnitude, mean as f32);
+        }
+    }
+}
+
+fn normalize_bands(spectrogram: &mut [Vec<f32>]) {
+    for frame in spectrogram.iter_mut() {
+        let bins = frame.len();
+        let mut start = 0;
+        for end in crate::PEAK_BAND_EDGES.into_iter().chain([bins]) {
+            let end = end.min(bins);
+            if start < end {
+                let band = &mut frame[start..end];
+                let mean = band.iter().sum::<f32>() / band.len() as f32;
+                for magnitude in band.iter_mut() {
+                    *magnitude = scale_to_threshold(*magnitude, mean);
+                }
+            }
+            start = end;
+        }
+    }
+}
+
+/// `magnitude / reference`, scaled so `NORMALIZED_PEAK_RATIO` lands on the peak threshold of 1.0
+fn scale_to_threshold(magnitude: f32, reference: f32) -> f32 {
+    if magnitude <= MIN_MAGNITUDE || reference <= f32::EPSILON {
+        0.0
+    } else {
+        magnitude / (reference * NORMALIZED_PEAK_RATIO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_peaks, spectrogram, SAMPLE_RATE};
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(SAMPLE_RATE as f32 * seconds) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn defaults_leave_the_spectrogram_unchanged() {
+        let samples = sine(440.0, 1.0);
+        let preprocessing = Preprocessing::default();
+        assert!(!preprocessing.is_enabled());
+        assert_eq!(
+            crate::conditioned_spectrogram(&samples, &preprocessing),
+            spectrogram(&samples)
+        );
+    }
+
+    #[test]
+    fn high_pass_removes_rumble_and_keeps_music() {
+        let preprocessing = Preprocessing {
+            high_pass_hz: Some(150.0),
+            ..Default::default()
+        };
+        let rumble = preprocessing.filter(&sine(30.0, 1.0));
+        let tone = preprocessing.filter(&sine(1000.0, 1.0));
+        assert!(rms(&rumble[1600..]) < 0.05);
+        assert!(rms(&tone[1600..]) > 0.65);
+    }
+
+    #[test]
+    fn noise_reduction_removes_a_steady_hum() {
+        let mut spec = spectrogram(&sine(1000.0, 4.0));
+        let before = spec[spec.len() / 2][256];
+        Preprocessing {
+            noise_reduction: Some(1.0),
+            ..Default::default()
+        }
+        .condition(&mut spec);
+        assert!(spec[spec.len() / 2][256] <= NOISE_FLOOR * before + 1e-3);
+    }
+
+    #[test]
+    fn normalization_finds_no_peaks_in_silence() {
+        for normalization in [Normalization::Whitening, Normalization::Bands] {
+            let mut spec = spectrogram(&vec![0.0; SAMPLE_RATE as usize * 2]);
+            Preprocessing {
+                normalization: Some(normalization),
+                ..Default::default()
+            }
+            .condition(&mut spec);
+            assert!(find_peaks(&spec).is_empty());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Pitch- and tempo-invariant hashes of peak triplets
+//!
+//! A pitch shift multiplies every frequency by the same factor and a tempo
+//! change every time difference, so the hash of an anchor peak and two later
+//! peaks keeps only ratios: each later peak's frequency over the anchor's, and
+//! the first time difference over the second. Sped-up edits, which do both,
+//! hash the same as the original; the matcher then finds the time scale along
+//! with the offset.
+
+/// Later peaks each anchor is combined with, in time order; every two of them make a triplet
+const FAN_OUT: usize = 5;
+/// Frames the later peaks must be past the anchor and each other, so the time
+/// ratio isn't dominated by rounding to whole frames
+const MIN_DT: usize = 2;
+/// Frames the last peak of a triplet may be past the anchor, about 5 seconds
+const MAX_SPAN: usize = 40;
+/// Lowest bin hashed; a bin's rounding error makes ratios of lower ones unstable
+const MIN_BIN: usize = 16;
+/// Bins from here up aren't hashed, like `hash_peaks`
+const MAX_BIN: usize = 512;
+/// Frequency ratios are rounded to this many steps per octave
+const STEPS_PER_OCTAVE: f32 = 12.0;
+/// Steps the time ratio, which lies between 0 and 1, is rounded to
+const TIME_RATIO_STEPS: f32 = 8.0;
+
+/// A triplet hash with the peaks it was computed from, each (t, f)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Triplet {
+    pub hash: u32,
+    pub peaks: [(usize, usize); 3],
+}
+
+/// Generate triplet hashes from peaks
+/// Returns: (hash, time_offset), the offset being the anchor's frame like `hash_peaks`
+pub fn hash_triplets(peaks: Vec<(usize, usize)>) -> Vec<(u32, u32)> {
+    triplets(peaks)
+        .into_iter()
+        .map(|triplet| (triplet.hash, triplet.peaks[0].0 as u32))
+        .collect()
+}
+
+/// Every triplet `hash_triplets` hashes, with its peaks
+pub fn triplets(mut peaks: Vec<(usize, usize)>) -> Vec<Triplet> {
+    peaks.retain(|&(_, f)| (MIN_BIN..MAX_BIN).contains(&f));
+    peaks.sort_unstable();
+
+    let mut triplets = Vec::new();
+    for (i, &anchor) in peaks.iter().enumerate() {
+        let targets: Vec<(usize, usize)> = peaks[i + 1..]
+            .iter()
+            .copied()
+            .filter(|&(t, _)| t >= anchor.0 + MIN_DT)
+            .take_while(|&(t, _)| t <= anchor.0 + MAX_SPAN)
+            .take(FAN_OUT)
+            .collect();
+
+        for (j, &second) in targets.iter().enumerate() {
+            for &third in &targets[j + 1..] {
+                if third.0 >= second.0 + MIN_DT {
+                    triplets.push(Triplet {
+                        hash: triplet_hash(anchor, second, third),
+                        peaks: [anchor, second, third],
+                    });
+                }
+            }
+        }
+    }
+    triplets
+}
+
+/// Hash: [log2(f2/f1): 8 bits] [log2(f3/f1): 8 bits] [(t2-t1)/(t3-t1): 3 bits]
+/// Frequency ratios are in semitones, offset by 128 to stay positive
+fn triplet_hash(anchor: (usize, usize), second: (usize, usize), third: (usize, usize)) -> u32 {
+    let interval = |f: usize| {
+        let steps = (f as f32 / anchor.1 as f32).log2() * STEPS_PER_OCTAVE;
+        (steps.round() as i32 + 128).clamp(0, 255) as u32
+    };
+    let time_ratio = (second.0 - anchor.0) as f32 / (third.0 - anchor.0) as f32;
+    let time_step = ((time_ratio * TIME_RATIO_STEPS) as u32).min(TIME_RATIO_STEPS as u32 - 1);
+    (interval(second.1) << 11) | (interval(third.1) << 3) | time_step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A made-up constellation of `count` peaks, one every other frame
+    fn constellation(count: usize) -> Vec<(usize, usize)> {
+        (0..count).map(|i| (2 * i, 20 + (i * 37) % 200)).collect()
+    }
+
+    #[test]
+    fn hashes_ignore_pitch_and_tempo_scaling() {
+        let peaks = constellation(60);
+        // Half the tempo and an octave up, so every ratio is exact
+        let scaled = peaks.iter().map(|&(t, f)| (2 * t, 2 * f)).collect();
+
+        let hashes = |peaks| -> Vec<u32> { triplets(peaks).iter().map(|t| t.hash).collect() };
+        let original = hashes(peaks);
+        assert!(!original.is_empty());
+        assert_eq!(original, hashes(scaled));
+    }
+
+    #[test]
+    fn offsets_are_anchor_frames() {
+        let peaks = constellation(30);
+        for (triplet, (hash, offset)) in triplets(peaks.clone()).iter().zip(hash_triplets(peaks)) {
+            assert_eq!(triplet.hash, hash);
+            assert_eq!(triplet.peaks[0].0 as u32, offset);
+            let [first, second, third] = triplet.peaks;
+            assert!(first.0 + MIN_DT <= second.0 && second.0 + MIN_DT <= third.0);
+            assert!(third.0 - first.0 <= MAX_SPAN);
+        }
+    }
+
+    #[test]
+    fn out_of_range_bins_are_skipped() {
+        let peaks = vec![(0, 8), (3, 100), (6, 600), (9, 120), (12, 140)];
+        let triplets = triplets(peaks);
+        assert_eq!(triplets.len(), 1);
+        assert_eq!(triplets[0].peaks, [(3, 100), (9, 120), (12, 140)]);
+    }
+}
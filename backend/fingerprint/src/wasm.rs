@@ -0,0 +1,31 @@
+//! wasm-bindgen exports for fingerprinting in the browser
+//!
+//! The output feeds `POST /recognize/fingerprints`, so only hashes leave the device.
+
+use wasm_bindgen::prelude::*;
+
+/// Fingerprint version the hashes below belong to, sent as `version`
+#[wasm_bindgen(js_name = fingerprintVersion)]
+pub fn fingerprint_version() -> u16 {
+    crate::FINGERPRINT_VERSION
+}
+
+/// Fingerprint mono audio at any sample rate, e.g. the Web Audio context's
+/// Returns a flat array of `hash, offset` pairs
+#[wasm_bindgen]
+pub fn fingerprint(samples: &[f32], sample_rate: u32) -> Vec<u32> {
+    let samples = crate::resample(samples, sample_rate);
+    crate::generate_fingerprints(&samples)
+        .into_iter()
+        .flat_map(|(hash, offset)| [hash, offset])
+        .collect()
+}
+
+/// Duration in milliseconds of mono audio at `sample_rate`, sent as `duration_ms`
+#[wasm_bindgen(js_name = durationMs)]
+pub fn duration_ms(sample_count: usize, sample_rate: u32) -> f64 {
+    if sample_rate == 0 {
+        return 0.0;
+    }
+    (sample_count as u64 * 1000 / sample_rate as u64) as f64
+}
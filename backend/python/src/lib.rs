@@ -0,0 +1,277 @@
+//! Python bindings: fingerprint numpy arrays, index files and recognize clips
+//!
+//! Built with maturin into a `sonica` extension module. Heavy calls release the GIL,
+//! so a notebook can index or recognize from several threads.
+
+use numpy::ndarray::Array2;
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use sonica_backend::config::Config;
+use sonica_backend::error::AppError;
+use sonica_backend::fingerprint::{load_audio, FINGERPRINT_VERSION, SAMPLE_RATE};
+use sonica_backend::indexer::{index_song, IndexOutcome};
+use sonica_backend::metadata::extract_metadata;
+use sonica_backend::recognizer::{recognize_fingerprints, recognize_samples, Recognition};
+use sonica_backend::storage;
+use sonica_backend::types::SongMetadata;
+use sonica_fingerprint::resample;
+use std::path::Path;
+
+fn py_err(e: AppError) -> PyErr {
+    match e {
+        AppError::InvalidRequest(_) | AppError::Audio(_) => PyValueError::new_err(e.to_string()),
+        AppError::Io(_) => PyOSError::new_err(e.to_string()),
+        _ => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+/// Samples as 16kHz mono, resampling from `sample_rate` if needed
+fn samples_16k(samples: PyReadonlyArray1<f32>, sample_rate: u32) -> Vec<f32> {
+    let samples = samples.as_array().to_vec();
+    if sample_rate == SAMPLE_RATE {
+        samples
+    } else {
+        resample(&samples, sample_rate)
+    }
+}
+
+/// `generate_fingerprints(samples, sample_rate=16000)`
+/// Mono float32 samples to a (n, 2) uint32 array of (hash, offset) rows
+#[pyfunction]
+#[pyo3(signature = (samples, sample_rate = SAMPLE_RATE))]
+fn generate_fingerprints<'py>(
+    py: Python<'py>,
+    samples: PyReadonlyArray1<f32>,
+    sample_rate: u32,
+) -> Bound<'py, PyArray2<u32>> {
+    let samples = samples_16k(samples, sample_rate);
+    let fingerprints = py.detach(|| sonica_fingerprint::generate_fingerprints(&samples));
+    fingerprint_array(py, &fingerprints)
+}
+
+fn fingerprint_array<'py>(
+    py: Python<'py>,
+    fingerprints: &[(u32, u32)],
+) -> Bound<'py, PyArray2<u32>> {
+    let flat = fingerprints
+        .iter()
+        .flat_map(|&(hash, offset)| [hash, offset])
+        .collect();
+    Array2::from_shape_vec((fingerprints.len(), 2), flat)
+        .expect("two columns per fingerprint")
+        .into_pyarray(py)
+}
+
+/// `load_audio(path)`: decode any file FFmpeg reads to 16kHz mono float32 samples
+#[pyfunction(name = "load_audio")]
+fn load_audio_file<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyArray1<f32>>> {
+    let samples = py.detach(|| load_audio(path)).map_err(py_err)?;
+    Ok(samples.into_pyarray(py))
+}
+
+/// A song of the library, as a dict
+#[derive(IntoPyObject)]
+struct Song {
+    id: i64,
+    title: String,
+    artist: String,
+    album: Option<String>,
+    year: Option<u32>,
+    duration_ms: Option<u64>,
+    path: String,
+}
+
+impl From<SongMetadata> for Song {
+    fn from(song: SongMetadata) -> Self {
+        Song {
+            id: song.id,
+            title: song.title,
+            artist: song.artist,
+            album: song.details.album,
+            year: song.details.year,
+            duration_ms: song.details.duration_ms,
+            path: song.path,
+        }
+    }
+}
+
+/// Result of `Database.index_file`, as a dict
+#[derive(IntoPyObject)]
+struct Indexed {
+    song_id: i64,
+    /// `indexed`, or `duplicate` when the file was linked to an existing song
+    status: &'static str,
+    hash_count: Option<usize>,
+    /// `content_hash` or `fingerprint` for duplicates
+    duplicate_kind: Option<&'static str>,
+}
+
+/// Result of the `Database.recognize*` methods, as a dict
+#[derive(IntoPyObject)]
+struct Recognized {
+    /// The matched song, `None` below the match threshold
+    song: Option<Song>,
+    raw_score: i64,
+    confidence: f32,
+    hash_count: usize,
+    query_duration_ms: u64,
+    /// Best candidates as (song_id, raw score), including those below the threshold
+    candidates: Vec<(i64, i64)>,
+}
+
+impl From<Recognition> for Recognized {
+    fn from(recognition: Recognition) -> Self {
+        Recognized {
+            raw_score: recognition.raw_score(),
+            song: recognition.song.map(Song::from),
+            confidence: recognition.confidence,
+            hash_count: recognition.hash_count,
+            query_duration_ms: recognition.query_duration_ms,
+            candidates: recognition.candidates,
+        }
+    }
+}
+
+/// `Database(path="songs.db", config=None)`
+/// A song library; `config` is a `sonica.json` path, defaulting to the server's
+#[pyclass(frozen)]
+struct Database {
+    db: storage::Database,
+    config: Config,
+}
+
+#[pymethods]
+impl Database {
+    #[new]
+    #[pyo3(signature = (path = "songs.db", config = None))]
+    fn new(path: &str, config: Option<&str>) -> PyResult<Self> {
+        let config = match config {
+            Some(config_path) => Config::load(config_path),
+            None => Config::load(Config::path()),
+        }
+        .map_err(py_err)?;
+        let db = storage::Database::new(path).map_err(py_err)?;
+        db.adopt_fingerprint_settings(&config.fingerprint)
+            .map_err(py_err)?;
+        Ok(Database { db, config })
+    }
+
+    /// Fingerprint and store an audio file, reading title and artist from its tags
+    fn index_file(&self, py: Python<'_>, path: &str) -> PyResult<Indexed> {
+        let outcome = py
+            .detach(|| {
+                let metadata = extract_metadata(Path::new(path));
+                index_song(&self.db, path, &metadata)
+            })
+            .map_err(py_err)?;
+
+        Ok(match outcome {
+            IndexOutcome::Indexed {
+                song_id,
+                hash_count,
+            } => Indexed {
+                song_id,
+                status: "indexed",
+                hash_count: Some(hash_count),
+                duplicate_kind: None,
+            },
+            IndexOutcome::Duplicate { song_id, kind } => Indexed {
+                song_id,
+                status: "duplicate",
+                hash_count: None,
+                duplicate_kind: Some(kind.as_str()),
+            },
+        })
+    }
+
+    /// Recognize an audio file of any format FFmpeg reads
+    fn recognize_file(&self, py: Python<'_>, path: &str) -> PyResult<Recognized> {
+        py.detach(|| {
+            let samples = load_audio(path)?;
+            recognize_samples(&self.db, &self.config, &samples, None)
+        })
+        .map(Recognized::from)
+        .map_err(py_err)
+    }
+
+    /// Recognize mono float32 samples
+    #[pyo3(signature = (samples, sample_rate = SAMPLE_RATE))]
+    fn recognize(
+        &self,
+        py: Python<'_>,
+        samples: PyReadonlyArray1<f32>,
+        sample_rate: u32,
+    ) -> PyResult<Recognized> {
+        let samples = samples_16k(samples, sample_rate);
+        py.detach(|| recognize_samples(&self.db, &self.config, &samples, None))
+            .map(Recognized::from)
+            .map_err(py_err)
+    }
+
+    /// Recognize a (n, 2) uint32 array from `generate_fingerprints`
+    fn recognize_fingerprints(
+        &self,
+        py: Python<'_>,
+        fingerprints: PyReadonlyArray2<u32>,
+    ) -> PyResult<Recognized> {
+        if self.db.fingerprint_settings() != Default::default() {
+            return Err(PyValueError::new_err(
+                "This index uses custom fingerprint settings, pass samples to recognize instead",
+            ));
+        }
+        let fingerprints = fingerprints.as_array();
+        if fingerprints.ncols() != 2 {
+            return Err(PyValueError::new_err(
+                "fingerprints must have two columns, hash and offset",
+            ));
+        }
+        let fingerprints: Vec<(u32, u32)> = fingerprints
+            .rows()
+            .into_iter()
+            .map(|row| (row[0], row[1]))
+            .collect();
+        let query_duration_ms = fingerprints
+            .iter()
+            .map(|&(_, offset)| offset)
+            .max()
+            .map_or(0, |offset| {
+                sonica_fingerprint::frames_to_ms(offset as i64 + 1) as u64
+            });
+
+        py.detach(|| {
+            recognize_fingerprints(
+                &self.db,
+                &self.config,
+                &fingerprints,
+                query_duration_ms,
+                None,
+            )
+        })
+        .map(Recognized::from)
+        .map_err(py_err)
+    }
+
+    /// Every song of the library as a list of dicts, e.g. for `pandas.DataFrame`
+    fn songs(&self, py: Python<'_>) -> PyResult<Vec<Song>> {
+        py.detach(|| self.db.get_all_songs())
+            .map(|songs| songs.into_iter().map(Song::from).collect())
+            .map_err(py_err)
+    }
+
+    /// The match threshold recognitions are judged against
+    #[getter]
+    fn match_threshold(&self) -> i64 {
+        self.config.match_threshold
+    }
+}
+
+#[pymodule]
+fn sonica(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("FINGERPRINT_VERSION", FINGERPRINT_VERSION)?;
+    m.add("SAMPLE_RATE", SAMPLE_RATE)?;
+    m.add_function(wrap_pyfunction!(generate_fingerprints, m)?)?;
+    m.add_function(wrap_pyfunction!(load_audio_file, m)?)?;
+    m.add_class::<Database>()?;
+    Ok(())
+}
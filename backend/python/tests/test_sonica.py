@@ -0,0 +1,98 @@
+"""Smoke tests of the extension module; run `pytest` in python/ after `maturin develop`"""
+
+import shutil
+import wave
+
+import numpy as np
+import pytest
+
+import sonica
+
+
+def melody(seconds, seed=7, sample_rate=sonica.SAMPLE_RATE):
+    """A chord every quarter second, so every part of the clip has peaks to hash"""
+    rng = np.random.default_rng(seed)
+    beat = sample_rate // 4
+    t = np.arange(beat) / sample_rate
+    chords = []
+    for _ in range(int(seconds * 4)):
+        notes = rng.integers(48, 84, size=3)
+        hz = 440.0 * 2.0 ** ((notes - 69) / 12.0)
+        chords.append(np.sin(2 * np.pi * hz[:, None] * t).sum(axis=0) * 0.25)
+    return np.concatenate(chords).astype(np.float32)
+
+
+@pytest.fixture
+def db(tmp_path):
+    return sonica.Database(str(tmp_path / "songs.db"), str(tmp_path / "sonica.json"))
+
+
+def test_fingerprints_are_hash_offset_rows():
+    samples = melody(5)
+    fps = sonica.generate_fingerprints(samples)
+
+    assert fps.dtype == np.uint32
+    assert fps.ndim == 2 and fps.shape[1] == 2 and len(fps) > 0
+    assert np.array_equal(fps, sonica.generate_fingerprints(samples))
+    # Offsets are frames from the start of the clip, so a longer clip reaches further
+    longer = sonica.generate_fingerprints(melody(10))
+    assert longer[:, 1].max() > fps[:, 1].max()
+
+
+def test_other_sample_rates_are_resampled():
+    fps = sonica.generate_fingerprints(melody(5, sample_rate=44100), sample_rate=44100)
+    assert fps.dtype == np.uint32 and len(fps) > 0
+
+
+@pytest.mark.parametrize(
+    "samples",
+    [
+        np.zeros(16000, dtype=np.float64),
+        np.zeros(16000, dtype=np.int16),
+        np.zeros((16000, 2), dtype=np.float32),
+        [0.0] * 16000,
+    ],
+    ids=["float64", "int16", "stereo", "list"],
+)
+def test_samples_must_be_mono_float32(samples):
+    with pytest.raises(TypeError):
+        sonica.generate_fingerprints(samples)
+
+
+def test_fingerprints_must_have_hash_and_offset_columns(db):
+    with pytest.raises(ValueError):
+        db.recognize_fingerprints(np.zeros((4, 3), dtype=np.uint32))
+    with pytest.raises(TypeError):
+        db.recognize_fingerprints(np.zeros((4, 2), dtype=np.int64))
+
+
+def test_empty_library_matches_nothing(db):
+    fps = sonica.generate_fingerprints(melody(5))
+    result = db.recognize_fingerprints(fps)
+
+    assert result["song"] is None
+    assert result["candidates"] == []
+    assert result["hash_count"] > 0
+    assert db.songs() == []
+
+
+@pytest.mark.skipif(shutil.which("ffmpeg") is None, reason="index_file decodes with FFmpeg")
+def test_indexed_song_is_recognized_from_samples_and_fingerprints(db, tmp_path):
+    song = melody(30)
+    path = tmp_path / "song.wav"
+    with wave.open(str(path), "wb") as out:
+        out.setnchannels(1)
+        out.setsampwidth(2)
+        out.setframerate(sonica.SAMPLE_RATE)
+        out.writeframes((song * 32767).astype("<i2").tobytes())
+
+    indexed = db.index_file(str(path))
+    assert indexed["status"] == "indexed"
+
+    clip = song[10 * sonica.SAMPLE_RATE : 18 * sonica.SAMPLE_RATE]
+    for result in [
+        db.recognize(clip),
+        db.recognize_fingerprints(sonica.generate_fingerprints(clip)),
+    ]:
+        assert result["song"]["id"] == indexed["song_id"]
+        assert result["raw_score"] > db.match_threshold
@@ -1,45 +1,122 @@
+use crate::artwork::thumbnail;
+use crate::batch::{collect_files, BatchFile};
+use crate::config::Config;
+use crate::cover::{recognize_cover, CoverRecognition};
 use crate::error::{AppError, Result};
-use crate::fingerprint::{generate_fingerprints, preprocess_audio};
+use crate::explain::explain_samples;
+use crate::indexer::{index_song, IndexOutcome};
+use crate::metadata::{extract_metadata, write_tags};
+use crate::recognizer::{decode_upload, recognize_audio, recognize_fingerprints, Recognition};
 use crate::storage::Database;
-use crate::types::{MatchResult, RecognitionResponse, SongMetadata};
+use crate::timeline::{recognize_timeline_file, TimelineOptions};
+use crate::types::{
+    Artwork, BatchResponse, BatchResult, DuplicateSong, Explanation, FeedbackRequest,
+    FingerprintPayload, LibraryStats, MatchResult, MisidentifiedSong, NewRecognition,
+    RecognitionListQuery, RecognitionPage, RecognitionRecord, RecognitionResponse,
+    RecognitionSource, RecognitionStats, RecognitionStatsQuery, RecognizeQuery, SongListQuery,
+    SongMetadata, SongMetadataUpdate, SongPage, SongSort, SpectrogramQuery, Timeline,
+    TimelineQuery, Verdict,
+};
+use crate::visualize::{self, ExportFormat};
+use crate::wire;
 use axum::{
+    body::Bytes,
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Multipart, State,
+        DefaultBodyLimit, Multipart, Path as UrlPath, Query, State,
     },
+    http::{header, HeaderMap},
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{get, patch, post},
     Router,
 };
-use std::collections::HashMap;
+use futures::StreamExt;
+use serde::Deserialize;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use tracing::{info, warn};
 use uuid::Uuid;
 
-const MATCH_THRESHOLD: i64 = 8;
+const MAX_METADATA_LEN: usize = 256;
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+const MAX_CLIENT_ID_LEN: usize = 128;
+const DEFAULT_STATS_TOP: u32 = 10;
+const MAX_STATS_TOP: u32 = 100;
+const MAX_BATCH_UPLOAD_BYTES: usize = 512 * 1024 * 1024;
+/// JSON fingerprints take about 25 bytes each, three times the binary form
+const MAX_FINGERPRINT_BODY_BYTES: usize = 8 * 1024 * 1024;
+/// Whole songs, to compare with their clips
+const MAX_DEBUG_UPLOAD_BYTES: usize = 100 * 1024 * 1024;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<Database>,
+    pub config: Arc<Config>,
 }
 
 pub fn create_router(state: AppState) -> Router {
     Router::new()
         .route("/health", get(health))
         .route("/songs", get(list_songs))
+        .route("/songs/:id", patch(update_song))
+        .route("/songs/:id/artwork", get(get_artwork))
+        .route("/duplicates", get(list_duplicates))
         .route("/recognize", post(recognize))
+        .route("/recognize/explain", post(explain))
+        .route(
+            "/recognize/fingerprints",
+            post(recognize_client_fingerprints)
+                .layer(DefaultBodyLimit::max(MAX_FINGERPRINT_BODY_BYTES)),
+        )
+        .route(
+            "/recognize/batch",
+            post(recognize_batch).layer(DefaultBodyLimit::max(MAX_BATCH_UPLOAD_BYTES)),
+        )
+        .route(
+            "/recognize/timeline",
+            post(timeline).layer(DefaultBodyLimit::max(
+                state.config.max_timeline_upload_mb * 1024 * 1024,
+            )),
+        )
+        .route("/recognitions", get(list_recognitions))
+        .route("/recognitions/:id/feedback", post(recognition_feedback))
+        .route("/stats/recognitions", get(recognition_stats))
+        .route("/stats/library", get(library_stats))
+        .route("/stats/misidentified", get(misidentified_songs))
         .route("/upload", post(upload))
+        .route(
+            "/debug/spectrogram",
+            post(debug_spectrogram).layer(DefaultBodyLimit::max(MAX_DEBUG_UPLOAD_BYTES)),
+        )
         .route("/ws", get(ws_handler))
         .with_state(state)
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+#[derive(Deserialize)]
+struct WsParams {
+    client_id: Option<String>,
 }
 
-async fn handle_socket(mut socket: WebSocket, state: AppState) {
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<WsParams>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    // Browsers can't set headers on WebSocket requests, so also accept a query param
+    let client_id = params
+        .client_id
+        .as_deref()
+        .and_then(valid_client_id)
+        .or_else(|| client_id(&headers));
+    ws.on_upgrade(|socket| handle_socket(socket, state, client_id))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, client_id: Option<String>) {
     info!("New WebSocket connection");
 
     while let Some(msg) = socket.recv().await {
@@ -61,102 +138,34 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                 }
 
                 let state_clone = state.clone();
+                let client_id = client_id.clone();
+                let started = Instant::now();
 
-                let task_result =
-                    tokio::task::spawn_blocking(move || -> Result<Option<MatchResult>> {
-                        // 1. Save to temp
-                        let temp_id = Uuid::new_v4();
-                        let temp_path =
-                            std::env::temp_dir().join(format!("upload_{}.wav", temp_id));
-                        std::fs::write(&temp_path, &data)
-                            .map_err(|e| AppError::Internal(e.to_string()))?;
-
-                        // 2. Preprocess
-                        let processed_path =
-                            std::env::temp_dir().join(format!("processed_{}.wav", temp_id));
-
-                        if let Err(e) = preprocess_audio(
-                            temp_path.to_str().unwrap(),
-                            processed_path.to_str().unwrap(),
-                        ) {
-                            warn!("FFmpeg error: {}", e);
-                            let _ = std::fs::remove_file(&temp_path);
-                            return Err(e);
-                        }
-
-                        // Now decode the processed file
-                        let samples = match crate::fingerprint::decode_audio(
-                            processed_path.to_str().unwrap(),
-                        ) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                warn!("Decode error: {}", e);
-                                let _ = std::fs::remove_file(&temp_path);
-                                let _ = std::fs::remove_file(&processed_path);
-                                return Err(e);
-                            }
-                        };
-
-                        let _ = std::fs::remove_file(temp_path);
-                        let _ = std::fs::remove_file(processed_path);
-
-                        // 3. Fingerprint
-                        let fingerprints = generate_fingerprints(&samples);
-                        info!("Generated {} fingerprints", fingerprints.len());
-
-                        if fingerprints.is_empty() {
-                            return Ok(None);
-                        }
-
-                        // 4. Match
-                        let matches = state_clone.db.find_matches(&fingerprints)?;
-
-                        // Histogram logic
-                        let mut best_song_id = -1;
-                        let mut best_score = 0;
-
-                        for (song_id, offsets) in matches {
-                            let mut histogram = HashMap::new();
-                            let mut max_count = 0;
-                            for (db_offset, query_offset) in offsets {
-                                let relative_offset = (db_offset as i64) - (query_offset as i64);
-                                let count = histogram.entry(relative_offset).or_insert(0);
-                                *count += 1;
-                                if *count > max_count {
-                                    max_count = *count;
-                                }
-                            }
-                            if max_count > best_score {
-                                best_score = max_count;
-                                best_song_id = song_id;
-                            }
-                        }
-
-                        info!("Best score: {}", best_score);
-
-                        if best_score > MATCH_THRESHOLD {
-                            if let Some(metadata) =
-                                state_clone.db.get_song_metadata(best_song_id)?
-                            {
-                                let scale_factor = 15.0;
-                                let normalized_score =
-                                    1.0 - (-(best_score as f32) / scale_factor).exp();
-                                let clamped_score = normalized_score.min(1.0).max(0.0);
-
-                                return Ok(Some(MatchResult {
-                                    title: metadata.title,
-                                    artist: metadata.artist,
-                                    score: clamped_score,
-                                }));
-                            }
-                        }
+                let task_result = tokio::task::spawn_blocking(
+                    move || -> Result<(Option<MatchResult>, Option<i64>)> {
+                        let deadline = state_clone.config.recognition_deadline(started);
+                        let recognition =
+                            recognize_audio(&state_clone.db, &state_clone.config, &data, deadline)?;
+                        info!(
+                            "Generated {} fingerprints, best score: {}",
+                            recognition.hash_count,
+                            recognition.raw_score()
+                        );
 
-                        Ok(None)
-                    })
-                    .await;
+                        let recognition_id = log_recognition(
+                            &state_clone.db,
+                            RecognitionSource::Ws,
+                            client_id.as_deref(),
+                            &recognition,
+                            started,
+                        );
+                        Ok((match_result(&state_clone.db, recognition)?, recognition_id))
+                    },
+                )
+                .await;
 
                 match task_result {
-                    Ok(Ok(Some(match_result))) => {
+                    Ok(Ok((Some(match_result), recognition_id))) => {
                         info!(
                             "Match found: {} - {}",
                             match_result.artist, match_result.title
@@ -164,7 +173,8 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                         // Found a match! Send it back
                         let response = serde_json::json!({
                             "type": "match",
-                            "match": match_result
+                            "match": match_result,
+                            "recognition_id": recognition_id
                         });
                         if let Err(e) = socket.send(Message::Text(response.to_string())).await {
                             warn!("Failed to send match: {}", e);
@@ -189,18 +199,224 @@ async fn health() -> Json<serde_json::Value> {
     Json(serde_json::json!({ "status": "ok" }))
 }
 
-async fn list_songs(State(state): State<AppState>) -> Result<Json<Vec<SongMetadata>>> {
+async fn list_songs(
+    State(state): State<AppState>,
+    Query(query): Query<SongListQuery>,
+) -> Result<Json<SongPage>> {
     info!("List songs request received");
-    let songs = state.db.get_all_songs()?;
+    let limit = page_limit(query.limit)?;
+    let after = parse_song_cursor(query.cursor.as_deref())?;
+
+    // Fetch one extra row to know whether another page follows
+    let mut songs = state.db.list_songs(&query, after, limit + 1)?;
+    let next_cursor = if songs.len() > limit as usize {
+        songs.truncate(limit as usize);
+        songs.last().map(|song| song_cursor(song, query.sort))
+    } else {
+        None
+    };
+
     info!("Returning {} songs", songs.len());
-    Ok(Json(songs))
+    Ok(Json(SongPage { songs, next_cursor }))
+}
+
+fn page_limit(limit: Option<u32>) -> Result<u32> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::InvalidRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    Ok(limit)
+}
+
+/// Recognition cursors are the id of the last row of the previous page
+fn parse_cursor(cursor: Option<&str>) -> Result<Option<i64>> {
+    cursor
+        .map(|cursor| {
+            cursor
+                .parse::<i64>()
+                .map_err(|_| AppError::InvalidRequest("Invalid cursor".to_string()))
+        })
+        .transpose()
+}
+
+/// Song cursors are `<id>:<sort key>` of the last song of the previous page
+fn song_cursor(song: &SongMetadata, sort: SongSort) -> String {
+    let key = match sort {
+        SongSort::Title => &song.title,
+        SongSort::Artist => &song.artist,
+        SongSort::Date => &song.created_at,
+    };
+    format!("{}:{}", song.id, key)
+}
+
+fn parse_song_cursor(cursor: Option<&str>) -> Result<Option<(i64, &str)>> {
+    cursor
+        .map(|cursor| {
+            cursor
+                .split_once(':')
+                .and_then(|(id, key)| Some((id.parse::<i64>().ok()?, key)))
+                .ok_or_else(|| AppError::InvalidRequest("Invalid cursor".to_string()))
+        })
+        .transpose()
+}
+
+async fn list_duplicates(State(state): State<AppState>) -> Result<Json<Vec<DuplicateSong>>> {
+    let duplicates = state.db.get_duplicates()?;
+    info!("Returning {} duplicates", duplicates.len());
+    Ok(Json(duplicates))
+}
+
+async fn update_song(
+    State(state): State<AppState>,
+    UrlPath(song_id): UrlPath<i64>,
+    Json(update): Json<SongMetadataUpdate>,
+) -> Result<Json<SongMetadata>> {
+    info!("Update metadata request received for song {}", song_id);
+    let title = validate_metadata_field("title", update.title)?;
+    let artist = validate_metadata_field("artist", update.artist)?;
+
+    if title.is_none() && artist.is_none() {
+        return Err(AppError::InvalidRequest(
+            "Nothing to update: provide title and/or artist".to_string(),
+        ));
+    }
+
+    let song = state
+        .db
+        .get_song_metadata(song_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Song {} not found", song_id)))?;
+
+    // Write tags first so a failed write leaves the database untouched
+    if update.write_tags {
+        write_tags(Path::new(&song.path), title.as_deref(), artist.as_deref())?;
+        info!("Wrote tags to {}", song.path);
+    }
+
+    state
+        .db
+        .update_song_metadata(song_id, title.as_deref(), artist.as_deref())?;
+
+    let song = state
+        .db
+        .get_song_metadata(song_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Song {} not found", song_id)))?;
+    info!("Updated song {}: {} - {}", song_id, song.artist, song.title);
+    Ok(Json(song))
+}
+
+#[derive(Deserialize)]
+struct ArtworkQuery {
+    size: Option<u32>,
+}
+
+async fn get_artwork(
+    State(state): State<AppState>,
+    UrlPath(song_id): UrlPath<i64>,
+    Query(query): Query<ArtworkQuery>,
+) -> Result<impl IntoResponse> {
+    let cached = match query.size {
+        Some(size) => state.db.get_artwork_thumbnail(song_id, size)?,
+        None => None,
+    };
+
+    let (mime_type, data) = match cached {
+        Some(thumbnail) => thumbnail,
+        None => {
+            let artwork = state
+                .db
+                .get_artwork(song_id)?
+                .ok_or_else(|| AppError::NotFound(format!("No artwork for song {}", song_id)))?;
+
+            match query.size {
+                Some(size) => resized_artwork(&state.db, song_id, artwork, size).await?,
+                None => (artwork.mime_type, artwork.data),
+            }
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime_type),
+            (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+        ],
+        data,
+    ))
+}
+
+/// Resize artwork off the async runtime and cache the result for the next request
+async fn resized_artwork(
+    db: &Database,
+    song_id: i64,
+    artwork: Artwork,
+    size: u32,
+) -> Result<(String, Vec<u8>)> {
+    // Artwork that already fits is served as it is, so there's nothing to cache
+    let resized = artwork.width > size || artwork.height > size;
+    let (mime_type, data) = tokio::task::spawn_blocking(move || thumbnail(artwork, size))
+        .await
+        .map_err(|e| AppError::Internal(format!("Thumbnail task failed: {}", e)))??;
+
+    if resized {
+        db.insert_artwork_thumbnail(song_id, size, &mime_type, &data)?;
+    }
+    Ok((mime_type, data))
+}
+
+fn artwork_url(db: &Database, song_id: i64) -> Result<Option<String>> {
+    Ok(db
+        .has_artwork(song_id)?
+        .then(|| format!("/songs/{}/artwork", song_id)))
+}
+
+fn validate_metadata_field(name: &str, value: Option<String>) -> Result<Option<String>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::InvalidRequest(format!(
+            "{} must not be empty",
+            name
+        )));
+    }
+    if value.chars().count() > MAX_METADATA_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "{} must be at most {} characters",
+            name, MAX_METADATA_LEN
+        )));
+    }
+    if value.chars().any(|c| c.is_control()) {
+        return Err(AppError::InvalidRequest(format!(
+            "{} must not contain control characters",
+            name
+        )));
+    }
+
+    Ok(Some(value.to_string()))
 }
 
 async fn recognize(
     State(state): State<AppState>,
+    Query(query): Query<RecognizeQuery>,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> Result<Json<RecognitionResponse>> {
     info!("Recognition request received");
+    let started = Instant::now();
+    let cover = match query.mode.as_deref() {
+        None | Some("landmark") => false,
+        Some("cover") => true,
+        Some(mode) => {
+            return Err(AppError::InvalidRequest(format!(
+                "Unknown mode {}, expected landmark or cover",
+                mode
+            )))
+        }
+    };
     let mut audio_data: Option<Vec<u8>> = None;
 
     while let Some(field) = multipart
@@ -224,99 +440,658 @@ async fn recognize(
     if audio_data.len() < 1000 {
         return Err(AppError::InvalidRequest("Audio file too small".to_string()));
     }
+    if cover {
+        return recognize_cover_upload(state, audio_data).await;
+    }
 
-    // Save to temp file
-    let temp_input = format!("temp/{}", Uuid::new_v4());
-    let temp_output = format!("temp/{}_processed.wav", Uuid::new_v4());
+    // Fingerprinting and scoring are CPU bound, keep them off the async executor
+    let client_id = client_id(&headers);
+    let task_state = state.clone();
+    let (recognition_id, best_score, deadline_exceeded, result) =
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let deadline = task_state.config.recognition_deadline(started);
+            let recognition =
+                recognize_audio(&task_state.db, &task_state.config, &audio_data, deadline)?;
+            let recognition_id = log_recognition(
+                &task_state.db,
+                RecognitionSource::Http,
+                client_id.as_deref(),
+                &recognition,
+                started,
+            );
 
-    fs::create_dir_all("temp").await?;
-    fs::write(&temp_input, &audio_data).await?;
+            let best_score = recognition.raw_score();
+            let deadline_exceeded = recognition.deadline_exceeded;
+            let result = match_result(&task_state.db, recognition)?;
+            Ok((recognition_id, best_score, deadline_exceeded, result))
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Recognition task failed: {}", e)))??;
+
+    if deadline_exceeded {
+        warn!("Recognition deadline exceeded, returning best result so far");
+    }
 
-    // Preprocess with FFmpeg
-    preprocess_audio(&temp_input, &temp_output)?;
+    match result {
+        Some(result) => {
+            info!(
+                "Match found: {} - {} (raw score: {}, confidence: {:.1}%)",
+                result.title,
+                result.artist,
+                best_score,
+                result.score * 100.0
+            );
+            Ok(Json(RecognitionResponse {
+                r#match: Some(result),
+                recognition_id,
+                deadline_exceeded,
+            }))
+        }
+        None => {
+            warn!(
+                "No match found (best score: {}, threshold: {})",
+                best_score, state.config.match_threshold
+            );
+            Ok(Json(RecognitionResponse {
+                r#match: None,
+                recognition_id,
+                deadline_exceeded,
+            }))
+        }
+    }
+}
 
-    // Decode and Fingerprint
-    let samples = crate::fingerprint::decode_audio(&temp_output)?;
-    let fingerprints = generate_fingerprints(&samples);
+/// `POST /recognize?mode=cover`: match the clip's chroma against every song's
+/// Not written to the history log, whose scores and feedback are landmark hash counts
+async fn recognize_cover_upload(
+    state: AppState,
+    audio_data: Vec<u8>,
+) -> Result<Json<RecognitionResponse>> {
+    let task_state = state.clone();
+    let (best, result) = tokio::task::spawn_blocking(move || -> Result<_> {
+        let samples = decode_upload(&audio_data)?;
+        let recognition = recognize_cover(&task_state.db, &task_state.config, &samples)?;
+        let best = recognition
+            .top_candidate()
+            .map_or(0.0, |candidate| candidate.similarity);
+        Ok((best, cover_result(&task_state.db, recognition)?))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Recognition task failed: {}", e)))??;
 
-    // Clean up temp files
-    let _ = fs::remove_file(&temp_input).await;
-    let _ = fs::remove_file(&temp_output).await;
+    match &result {
+        Some(result) => info!(
+            "Cover match found: {} - {} (similarity: {:.2})",
+            result.title, result.artist, best
+        ),
+        None => warn!(
+            "No cover match found (best similarity: {:.2}, threshold: {})",
+            best, state.config.cover_threshold
+        ),
+    }
+    Ok(Json(RecognitionResponse {
+        r#match: result,
+        recognition_id: None,
+        deadline_exceeded: false,
+    }))
+}
+
+/// Recognize a clip like `/recognize` but return the pipeline's internals instead:
+/// peak and hash counts, index hits per hash, candidate histograms and the decision
+/// Nothing is written to the history log
+async fn explain(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<Explanation>> {
+    let mut audio_data: Option<Vec<u8>> = None;
 
-    if fingerprints.is_empty() {
-        return Ok(Json(RecognitionResponse { r#match: None }));
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Multipart error: {}", e)))?
+    {
+        let field_name = field.name().unwrap_or("");
+        if field_name == "audio" || field_name == "file" {
+            let data = field
+                .bytes()
+                .await
+                .map_err(|e| AppError::InvalidRequest(format!("Failed to read file: {}", e)))?;
+            audio_data = Some(data.to_vec());
+        }
     }
 
-    // Find matches in DB
-    let matches = state.db.find_matches(&fingerprints)?;
+    let audio_data =
+        audio_data.ok_or_else(|| AppError::InvalidRequest("No audio file provided".to_string()))?;
+    info!("Explain request received ({} bytes)", audio_data.len());
+
+    let explanation = tokio::task::spawn_blocking(move || -> Result<Explanation> {
+        let samples = decode_upload(&audio_data)?;
+        explain_samples(&state.db, &state.config, &samples)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Explain task failed: {}", e)))??;
+
+    Ok(Json(explanation))
+}
+
+/// Match fingerprints the client computed itself, skipping upload and decoding of audio
+/// Accepts the JSON form of `FingerprintPayload` or its binary encoding from `wire`
+async fn recognize_client_fingerprints(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<RecognitionResponse>> {
+    let started = Instant::now();
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+
+    let payload: FingerprintPayload = if is_json {
+        serde_json::from_slice(&body)?
+    } else {
+        wire::decode(&body)?
+    };
+    wire::validate(&payload, &state.db.fingerprint_settings())?;
+    info!(
+        "Fingerprint recognition request received ({} hashes)",
+        payload.fingerprints.len()
+    );
+
+    let client_id = client_id(&headers);
+    let task_state = state.clone();
+    let (recognition_id, deadline_exceeded, result) =
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let deadline = task_state.config.recognition_deadline(started);
+            let recognition = recognize_fingerprints(
+                &task_state.db,
+                &task_state.config,
+                &payload.fingerprints,
+                wire::query_duration_ms(&payload),
+                deadline,
+            )?;
+            let recognition_id = log_recognition(
+                &task_state.db,
+                RecognitionSource::Fingerprints,
+                client_id.as_deref(),
+                &recognition,
+                started,
+            );
+
+            let deadline_exceeded = recognition.deadline_exceeded;
+            let result = match_result(&task_state.db, recognition)?;
+            Ok((recognition_id, deadline_exceeded, result))
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Recognition task failed: {}", e)))??;
 
-    // Histogram of Offsets Algorithm
-    let mut best_song_id = -1;
-    let mut best_score = 0;
+    if let Some(result) = &result {
+        info!("Match found: {} - {}", result.title, result.artist);
+    }
+    Ok(Json(RecognitionResponse {
+        r#match: result,
+        recognition_id,
+        deadline_exceeded,
+    }))
+}
 
-    for (song_id, offsets) in matches {
-        let mut histogram = HashMap::new();
-        let mut max_count = 0;
+/// Recognize many clips, or the audio files in zip/tar archives, in one request
+async fn recognize_batch(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<BatchResponse>> {
+    let mut uploads = Vec::new();
 
-        for (db_offset, query_offset) in offsets {
-            // relative_offset = db_offset - query_offset
-            // We use wrapping arithmetic or offset to avoid negative numbers if needed,
-            // but here we can just use i64.
-            let relative_offset = (db_offset as i64) - (query_offset as i64);
-            let count = histogram.entry(relative_offset).or_insert(0);
-            *count += 1;
-            if *count > max_count {
-                max_count = *count;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Multipart error: {}", e)))?
+    {
+        let field_name = field.name().unwrap_or("");
+        if field_name == "audio" || field_name == "file" || field_name == "files" {
+            let name = field
+                .file_name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("file{}", uploads.len() + 1));
+            let data = field
+                .bytes()
+                .await
+                .map_err(|e| AppError::InvalidRequest(format!("Failed to read file: {}", e)))?;
+            uploads.push(BatchFile {
+                name,
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    let files = tokio::task::spawn_blocking(move || collect_files(uploads))
+        .await
+        .map_err(|e| AppError::Internal(format!("Unpacking task failed: {}", e)))??;
+    if files.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "No audio files provided".to_string(),
+        ));
+    }
+    info!("Batch recognition of {} files", files.len());
+
+    // FFmpeg and fingerprinting are CPU bound, so only a few files run at once
+    let client_id = client_id(&headers);
+    let workers = state.config.batch_workers.max(1);
+    let results: Vec<BatchResult> = futures::stream::iter(files)
+        .map(|file| {
+            let state = state.clone();
+            let client_id = client_id.clone();
+            async move {
+                let name = file.name;
+                let task = tokio::task::spawn_blocking(move || {
+                    recognize_batch_file(&state, &file.data, client_id.as_deref())
+                })
+                .await;
+
+                match task {
+                    Ok(Ok((r#match, recognition_id))) => BatchResult {
+                        name,
+                        r#match,
+                        recognition_id,
+                        error: None,
+                    },
+                    Ok(Err(e)) => batch_error(name, e.to_string()),
+                    Err(e) => batch_error(name, format!("Recognition task failed: {}", e)),
+                }
             }
+        })
+        .buffered(workers)
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    let matched = results.iter().filter(|r| r.r#match.is_some()).count();
+    info!(
+        "Batch done: {} matched, {} failed of {}",
+        matched,
+        failed,
+        results.len()
+    );
+
+    Ok(Json(BatchResponse {
+        matched,
+        unmatched: results.len() - matched - failed,
+        failed,
+        results,
+    }))
+}
+
+/// Recognize and log one file of a batch
+fn recognize_batch_file(
+    state: &AppState,
+    audio_data: &[u8],
+    client_id: Option<&str>,
+) -> Result<(Option<MatchResult>, Option<i64>)> {
+    if audio_data.len() < 1000 {
+        return Err(AppError::InvalidRequest("Audio file too small".to_string()));
+    }
+
+    let started = Instant::now();
+    let deadline = state.config.recognition_deadline(started);
+    let recognition = recognize_audio(&state.db, &state.config, audio_data, deadline)?;
+    let recognition_id = log_recognition(
+        &state.db,
+        RecognitionSource::Batch,
+        client_id,
+        &recognition,
+        started,
+    );
+    Ok((match_result(&state.db, recognition)?, recognition_id))
+}
+
+fn batch_error(name: String, error: String) -> BatchResult {
+    BatchResult {
+        name,
+        r#match: None,
+        recognition_id: None,
+        error: Some(error),
+    }
+}
+
+/// Identify every song in a long recording, window by window
+async fn timeline(
+    State(state): State<AppState>,
+    Query(query): Query<TimelineQuery>,
+    mut multipart: Multipart,
+) -> Result<Json<Timeline>> {
+    let options = TimelineOptions::from_query(&query)?;
+
+    // Recordings can run to hours, so they go to disk as they arrive and are
+    // decoded window by window rather than held in memory
+    fs::create_dir_all("temp").await?;
+    let temp_input = format!("temp/{}", Uuid::new_v4());
+    let size = match save_audio_field(&mut multipart, &temp_input).await {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_input).await;
+            return Err(e);
+        }
+    };
+    info!("Timeline request received ({} bytes)", size);
+
+    let started = Instant::now();
+    let task_input = temp_input.clone();
+    let timeline = tokio::task::spawn_blocking(move || -> Result<Timeline> {
+        recognize_timeline_file(&state.db, &state.config, &task_input, &options)
+    })
+    .await;
+    let _ = fs::remove_file(&temp_input).await;
+    let timeline =
+        timeline.map_err(|e| AppError::Internal(format!("Timeline task failed: {}", e)))??;
+
+    info!(
+        "Timeline of {}s: {} segments in {}ms",
+        timeline.duration_ms / 1000,
+        timeline.segments.len(),
+        started.elapsed().as_millis()
+    );
+    Ok(Json(timeline))
+}
+
+/// Write the `audio` or `file` field of a multipart upload to `path` chunk by chunk
+/// Returns the number of bytes written
+async fn save_audio_field(multipart: &mut Multipart, path: &str) -> Result<u64> {
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Multipart error: {}", e)))?
+    {
+        let field_name = field.name().unwrap_or("");
+        if field_name != "audio" && field_name != "file" {
+            continue;
         }
 
-        if max_count > best_score {
-            best_score = max_count;
-            best_song_id = song_id;
+        let mut file = fs::File::create(path).await?;
+        let mut size = 0;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| AppError::InvalidRequest(format!("Failed to read file: {}", e)))?
+        {
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
         }
+        file.flush().await?;
+        return Ok(size);
     }
 
-    // Threshold for a match
-    // Need at least X matching points aligned in time
-    if best_score > MATCH_THRESHOLD {
-        if let Some(metadata) = state.db.get_song_metadata(best_song_id)? {
-            // Normalize score to 0-1 range (frontend will multiply by 100 for percentage)
-            // The score is the count of matching fingerprint points aligned in time
-            // Use a logarithmic scale to map to 0-1 range more naturally
-            // Formula: normalized = 1 - exp(-score / scale_factor)
-            // This ensures scores are always between 0 and 1
+    Err(AppError::InvalidRequest(
+        "No audio file provided".to_string(),
+    ))
+}
 
-            // Scale factor: higher values = slower growth toward 1.0
-            // For score of 25, we want ~0.85, so: 1 - exp(-25/15) ≈ 0.81
-            // For score of 40, we want ~0.95, so: 1 - exp(-40/15) ≈ 0.93
-            let scale_factor = 15.0;
-            let normalized_score = 1.0 - (-(best_score as f32) / scale_factor).exp();
+/// Render a clip's spectrogram and constellation map, or export them as JSON/NPY/NPZ
+async fn debug_spectrogram(
+    State(state): State<AppState>,
+    Query(query): Query<SpectrogramQuery>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse> {
+    let format = ExportFormat::parse(query.format.as_deref().unwrap_or("png"))?;
+    let max_bin = visualize::max_bin(query.max_bin)?;
+    let mut audio_data: Option<Vec<u8>> = None;
 
-            // Clamp to ensure it's always between 0 and 1
-            let clamped_score = normalized_score.min(1.0).max(0.0);
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Multipart error: {}", e)))?
+    {
+        let field_name = field.name().unwrap_or("");
+        if field_name == "audio" || field_name == "file" {
+            let data = field
+                .bytes()
+                .await
+                .map_err(|e| AppError::InvalidRequest(format!("Failed to read file: {}", e)))?;
+            audio_data = Some(data.to_vec());
+        }
+    }
 
-            info!(
-                "Match found: {} - {} (raw score: {}, confidence: {:.1}%)",
-                metadata.title,
-                metadata.artist,
-                best_score,
-                clamped_score * 100.0
-            );
-            return Ok(Json(RecognitionResponse {
-                r#match: Some(MatchResult {
-                    title: metadata.title,
-                    artist: metadata.artist,
-                    score: clamped_score, // Return as 0-1 range (frontend multiplies by 100)
-                }),
-            }));
+    let audio_data =
+        audio_data.ok_or_else(|| AppError::InvalidRequest("No audio file provided".to_string()))?;
+
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let samples = decode_upload(&audio_data)?;
+        let reference = query.reference.map(|song_id| (state.db.as_ref(), song_id));
+        let settings = state.db.fingerprint_settings();
+        let constellation = visualize::constellation(&samples, &settings, reference)?;
+        visualize::export(&constellation, format, max_bin)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Spectrogram task failed: {}", e)))??;
+
+    Ok(([(header::CONTENT_TYPE, format.mime_type())], data))
+}
+
+/// Build the client-facing result for a recognition that passed the threshold
+fn match_result(db: &Database, recognition: Recognition) -> Result<Option<MatchResult>> {
+    let Some(song) = recognition.song else {
+        return Ok(None);
+    };
+
+    Ok(Some(MatchResult {
+        artwork_url: artwork_url(db, song.id)?,
+        title: song.title,
+        artist: song.artist,
+        score: recognition.confidence, // Return as 0-1 range (frontend multiplies by 100)
+        transposition: None,
+        details: song.details,
+    }))
+}
+
+fn cover_result(db: &Database, recognition: CoverRecognition) -> Result<Option<MatchResult>> {
+    let (Some(song), Some(best)) = (recognition.song, recognition.candidates.first()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(MatchResult {
+        artwork_url: artwork_url(db, song.id)?,
+        title: song.title,
+        artist: song.artist,
+        score: best.similarity.clamp(0.0, 1.0),
+        transposition: Some(best.transposition),
+        details: song.details,
+    }))
+}
+
+/// Write a recognition to the history log, returning its id
+/// Logging is best effort and never fails the request
+fn log_recognition(
+    db: &Database,
+    source: RecognitionSource,
+    client_id: Option<&str>,
+    recognition: &Recognition,
+    started: Instant,
+) -> Option<i64> {
+    let entry = NewRecognition {
+        source,
+        client_id,
+        query_duration_ms: recognition.query_duration_ms,
+        hash_count: recognition.hash_count,
+        top_song_id: recognition.top_candidate().map(|(song_id, _)| song_id),
+        raw_score: recognition.raw_score(),
+        confidence: recognition.confidence,
+        latency_ms: started.elapsed().as_millis() as u64,
+        matched: recognition.song.is_some(),
+        deadline_exceeded: recognition.deadline_exceeded,
+        candidates: &recognition.candidates,
+    };
+
+    match db.insert_recognition(&entry) {
+        Ok(recognition_id) => Some(recognition_id),
+        Err(e) => {
+            warn!("Failed to log recognition: {}", e);
+            None
+        }
+    }
+}
+
+/// Client identifier sent in the `X-Client-Id` header
+fn client_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-client-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(valid_client_id)
+}
+
+/// A client identifier trimmed, or None if it's empty or too long
+fn valid_client_id(value: &str) -> Option<String> {
+    Some(value.trim())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_CLIENT_ID_LEN)
+        .map(str::to_string)
+}
+
+/// A `since`/`until` bound in the `created_at` format, so they compare correctly as text
+/// Accepts anything SQLite's `datetime()` does, e.g. `2026-10-01T08:00:00Z` or `2026-10-01`
+fn time_bound(db: &Database, name: &str, value: Option<String>) -> Result<Option<String>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    match db.normalize_timestamp(&value)? {
+        Some(timestamp) => Ok(Some(timestamp)),
+        None => Err(AppError::InvalidRequest(format!(
+            "{} must be a date or time like 2026-10-01 or 2026-10-01T08:00:00Z",
+            name
+        ))),
+    }
+}
+
+async fn list_recognitions(
+    State(state): State<AppState>,
+    Query(mut query): Query<RecognitionListQuery>,
+) -> Result<Json<RecognitionPage>> {
+    let limit = page_limit(query.limit)?;
+    query.since = time_bound(&state.db, "since", query.since)?;
+    query.until = time_bound(&state.db, "until", query.until)?;
+    let before_id = parse_cursor(query.cursor.as_deref())?;
+
+    // Fetch one extra row to know whether another page follows
+    let mut recognitions = state.db.list_recognitions(&query, before_id, limit + 1)?;
+    let next_cursor = if recognitions.len() > limit as usize {
+        recognitions.truncate(limit as usize);
+        recognitions.last().map(|r| r.id.to_string())
+    } else {
+        None
+    };
+
+    info!("Returning {} recognitions", recognitions.len());
+    Ok(Json(RecognitionPage {
+        recognitions,
+        next_cursor,
+    }))
+}
+
+async fn recognition_feedback(
+    State(state): State<AppState>,
+    UrlPath(recognition_id): UrlPath<i64>,
+    Json(feedback): Json<FeedbackRequest>,
+) -> Result<Json<RecognitionRecord>> {
+    let recognition = state
+        .db
+        .get_recognition(recognition_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Recognition {} not found", recognition_id)))?;
+
+    match feedback.verdict {
+        Verdict::Correct => {
+            if !recognition.matched {
+                return Err(AppError::InvalidRequest(
+                    "Recognition returned no match; send verdict \"wrong\" with correct_song_id"
+                        .to_string(),
+                ));
+            }
+            if feedback
+                .correct_song_id
+                .is_some_and(|id| Some(id) != recognition.top_song_id)
+            {
+                return Err(AppError::InvalidRequest(
+                    "correct_song_id differs from the returned match; send verdict \"wrong\""
+                        .to_string(),
+                ));
+            }
+        }
+        Verdict::Wrong => {
+            if let Some(correct_song_id) = feedback.correct_song_id {
+                if recognition.matched && recognition.top_song_id == Some(correct_song_id) {
+                    return Err(AppError::InvalidRequest(
+                        "correct_song_id is the returned match; send verdict \"correct\""
+                            .to_string(),
+                    ));
+                }
+                if state.db.get_song_metadata(correct_song_id)?.is_none() {
+                    return Err(AppError::NotFound(format!(
+                        "Song {} not found",
+                        correct_song_id
+                    )));
+                }
+            }
         }
     }
 
-    warn!(
-        "No match found (best score: {}, threshold: {})",
-        best_score, MATCH_THRESHOLD
+    // For a confirmed match the correct song is the returned one
+    let correct_song_id = match feedback.verdict {
+        Verdict::Correct => recognition.top_song_id,
+        Verdict::Wrong => feedback.correct_song_id,
+    };
+    state
+        .db
+        .set_feedback(recognition_id, feedback.verdict, correct_song_id)?;
+    info!(
+        "Feedback on recognition {}: {}",
+        recognition_id,
+        feedback.verdict.as_str()
     );
-    Ok(Json(RecognitionResponse { r#match: None }))
+
+    let recognition = state
+        .db
+        .get_recognition(recognition_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Recognition {} not found", recognition_id)))?;
+    Ok(Json(recognition))
+}
+
+async fn misidentified_songs(
+    State(state): State<AppState>,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<Vec<MisidentifiedSong>>> {
+    let top = stats_top(query.top)?;
+    Ok(Json(state.db.misidentified_songs(top)?))
+}
+
+async fn recognition_stats(
+    State(state): State<AppState>,
+    Query(mut query): Query<RecognitionStatsQuery>,
+) -> Result<Json<RecognitionStats>> {
+    let top = stats_top(query.top)?;
+    query.since = time_bound(&state.db, "since", query.since)?;
+    query.until = time_bound(&state.db, "until", query.until)?;
+    Ok(Json(state.db.recognition_stats(&query, top)?))
+}
+
+#[derive(Deserialize)]
+struct TopQuery {
+    top: Option<u32>,
+}
+
+async fn library_stats(
+    State(state): State<AppState>,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<LibraryStats>> {
+    let top = stats_top(query.top)?;
+    Ok(Json(state.db.library_stats(top)?))
+}
+
+fn stats_top(top: Option<u32>) -> Result<u32> {
+    let top = top.unwrap_or(DEFAULT_STATS_TOP);
+    if !(1..=MAX_STATS_TOP).contains(&top) {
+        return Err(AppError::InvalidRequest(format!(
+            "top must be between 1 and {}",
+            MAX_STATS_TOP
+        )));
+    }
+    Ok(top)
 }
 
 async fn upload(
@@ -409,21 +1184,65 @@ async fn upload(
 }
 
 async fn process_new_song(db: &Arc<Database>, path: &str, title: &str, artist: &str) -> Result<()> {
-    // Preprocess with FFmpeg
-    let temp_output = format!("temp/{}_processed.wav", Uuid::new_v4());
-    fs::create_dir_all("temp").await?;
+    // Title and artist come from the upload form, the rest from the file's tags
+    let mut metadata = extract_metadata(Path::new(path));
+    metadata.title = title.to_string();
+    metadata.artist = artist.to_string();
+
+    if let IndexOutcome::Duplicate { song_id, kind } = index_song(db, path, &metadata)? {
+        info!(
+            "Uploaded {} is a duplicate of song {} ({}), linked instead of indexed",
+            path,
+            song_id,
+            kind.as_str()
+        );
+    }
+
+    Ok(())
+}
 
-    preprocess_audio(path, &temp_output)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TrackDetails;
 
-    // Extract fingerprint
-    let samples = crate::fingerprint::decode_audio(&temp_output)?;
-    let fingerprints = generate_fingerprints(&samples);
+    fn song(title: &str) -> SongMetadata {
+        SongMetadata {
+            id: 42,
+            title: title.to_string(),
+            artist: "Band".to_string(),
+            path: "/music/song.mp3".to_string(),
+            created_at: "2024-01-01 12:30:00".to_string(),
+            details: TrackDetails::default(),
+        }
+    }
 
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_output).await;
+    #[test]
+    fn song_cursors_decode_to_the_id_and_sort_key_they_encode() {
+        for (sort, key) in [
+            (SongSort::Title, "Re: Intro"),
+            (SongSort::Artist, "Band"),
+            (SongSort::Date, "2024-01-01 12:30:00"),
+        ] {
+            let cursor = song_cursor(&song("Re: Intro"), sort);
+            assert_eq!(parse_song_cursor(Some(&cursor)).unwrap(), Some((42, key)));
+        }
 
-    // Save to database
-    db.insert_song(title, artist, path, &fingerprints)?;
+        assert_eq!(parse_song_cursor(Some("7:")).unwrap(), Some((7, "")));
+        assert_eq!(parse_song_cursor(None).unwrap(), None);
+    }
 
-    Ok(())
+    #[test]
+    fn malformed_song_cursors_are_rejected() {
+        for cursor in ["", "42", "x:Title", ":Title", "4.2:Title"] {
+            assert!(
+                matches!(
+                    parse_song_cursor(Some(cursor)),
+                    Err(AppError::InvalidRequest(_))
+                ),
+                "{:?}",
+                cursor
+            );
+        }
+    }
 }
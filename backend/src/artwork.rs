@@ -0,0 +1,56 @@
+use crate::error::{AppError, Result};
+use crate::types::Artwork;
+use image::{ImageFormat, ImageReader};
+use std::io::Cursor;
+
+pub const MIN_THUMBNAIL_SIZE: u32 = 16;
+pub const MAX_THUMBNAIL_SIZE: u32 = 2048;
+
+/// Build artwork from embedded picture bytes
+/// Returns None if the image format is unsupported or its header can't be read
+pub fn from_picture_data(data: &[u8]) -> Option<Artwork> {
+    let format = image::guess_format(data).ok()?;
+    let (width, height) = ImageReader::with_format(Cursor::new(data), format)
+        .into_dimensions()
+        .ok()?;
+
+    Some(Artwork {
+        mime_type: format.to_mime_type().to_string(),
+        width,
+        height,
+        data: data.to_vec(),
+    })
+}
+
+/// Resize artwork to fit within `size`x`size`, keeping its aspect ratio
+/// Returns (mime_type, bytes); artwork already small enough is returned unchanged
+pub fn thumbnail(artwork: Artwork, size: u32) -> Result<(String, Vec<u8>)> {
+    if !(MIN_THUMBNAIL_SIZE..=MAX_THUMBNAIL_SIZE).contains(&size) {
+        return Err(AppError::InvalidRequest(format!(
+            "size must be between {} and {}",
+            MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE
+        )));
+    }
+
+    if artwork.width <= size && artwork.height <= size {
+        return Ok((artwork.mime_type, artwork.data));
+    }
+
+    let format = ImageFormat::from_mime_type(&artwork.mime_type).unwrap_or(ImageFormat::Png);
+    let image = image::load_from_memory_with_format(&artwork.data, format)
+        .map_err(|e| AppError::Internal(format!("Failed to decode artwork: {}", e)))?;
+
+    // JPEG can't store alpha, so only keep JPEG for sources that were JPEG
+    let output_format = match format {
+        ImageFormat::Jpeg => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    };
+
+    let mut bytes = Vec::new();
+    image
+        .thumbnail(size, size)
+        .write_to(&mut Cursor::new(&mut bytes), output_format)
+        .map_err(|e| AppError::Internal(format!("Failed to encode artwork: {}", e)))?;
+
+    Ok((output_format.to_mime_type().to_string(), bytes))
+}
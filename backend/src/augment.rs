@@ -0,0 +1,341 @@
+//! Audio degradations for offline evaluation of the fingerprinter
+//!
+//! Each augmentation turns a clean 16kHz mono clip into what a microphone, a
+//! stream or an edit might deliver: noise at a given SNR, band limiting, lossy
+//! re-encoding, gain changes with clipping, speed changes and room reverb.
+
+use crate::error::{AppError, Result};
+use crate::fingerprint::{load_audio, SAMPLE_RATE};
+use sonica_fingerprint::resample;
+use std::fmt;
+use std::process::Command;
+use uuid::Uuid;
+
+/// Every augmentation at a moderate strength, as accepted by `Augmentation::parse_list`
+pub const DEFAULT_AUGMENTATIONS: &str =
+    "clean,white:10,pink:10,lowpass:2000,mp3:64,gain:-20,speed:1.05,reverb:1.0";
+
+/// Comb filter delays of the reverb in seconds, mutually prime in samples
+const REVERB_COMBS: [f32; 4] = [0.0297, 0.0371, 0.0411, 0.0437];
+/// All-pass diffuser delays in seconds and their gain
+const REVERB_ALLPASSES: [f32; 2] = [0.005, 0.0017];
+const REVERB_ALLPASS_GAIN: f32 = 0.7;
+/// Share of reverberated signal in the output
+const REVERB_WET: f32 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Augmentation {
+    /// The clip unchanged
+    Clean,
+    /// White noise at a signal-to-noise ratio in dB
+    WhiteNoise { snr_db: f32 },
+    /// Pink (1/f) noise at a signal-to-noise ratio in dB, closer to crowd and traffic noise
+    PinkNoise { snr_db: f32 },
+    /// Second-order Butterworth low-pass, like a phone speaker or a muffled room
+    LowPass { cutoff_hz: f32 },
+    /// MP3 round trip at a bitrate in kbit/s, through FFmpeg
+    Mp3 { bitrate_kbps: u32 },
+    /// Gain in dB, clipping at full scale
+    Gain { db: f32 },
+    /// Playback speed factor, shifting pitch and tempo together like a sped-up edit
+    Speed { factor: f32 },
+    /// Schroeder reverb with a decay time (RT60) in seconds
+    Reverb { decay_s: f32 },
+}
+
+impl Augmentation {
+    /// `clean`, `white:<snr dB>`, `pink:<snr dB>`, `lowpass:<Hz>`, `mp3:<kbps>`,
+    /// `gain:<dB>`, `speed:<factor>` or `reverb:<RT60 s>`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, value) = match spec.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (spec, None),
+        };
+        let number = |min: f32, max: f32| {
+            value
+                .and_then(|v| v.parse::<f32>().ok())
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "{} needs a value between {} and {}",
+                        name, min, max
+                    ))
+                })
+        };
+
+        match name {
+            "clean" => Ok(Self::Clean),
+            "white" => Ok(Self::WhiteNoise {
+                snr_db: number(-20.0, 60.0)?,
+            }),
+            "pink" => Ok(Self::PinkNoise {
+                snr_db: number(-20.0, 60.0)?,
+            }),
+            "lowpass" => Ok(Self::LowPass {
+                cutoff_hz: number(50.0, SAMPLE_RATE as f32 / 2.0 - 1.0)?,
+            }),
+            "mp3" => Ok(Self::Mp3 {
+                bitrate_kbps: number(8.0, 320.0)? as u32,
+            }),
+            "gain" => Ok(Self::Gain {
+                db: number(-60.0, 40.0)?,
+            }),
+            "speed" => Ok(Self::Speed {
+                factor: number(0.5, 2.0)?,
+            }),
+            "reverb" => Ok(Self::Reverb {
+                decay_s: number(0.05, 10.0)?,
+            }),
+            _ => Err(AppError::InvalidRequest(format!(
+                "Unknown augmentation {}, expected clean, white, pink, lowpass, mp3, gain, speed or reverb",
+                name
+            ))),
+        }
+    }
+
+    /// A comma separated list of specs
+    pub fn parse_list(specs: &str) -> Result<Vec<Self>> {
+        specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Degrade a 16kHz mono clip; `seed` makes the noise reproducible
+    pub fn apply(&self, samples: &[f32], seed: u64) -> Result<Vec<f32>> {
+        Ok(match *self {
+            Self::Clean => samples.to_vec(),
+            Self::WhiteNoise { snr_db } => {
+                let mut noise = NoiseSource::new(seed);
+                let noise: Vec<f32> = samples.iter().map(|_| noise.white()).collect();
+                add_noise(samples, &noise, snr_db)
+            }
+            Self::PinkNoise { snr_db } => {
+                let mut noise = NoiseSource::new(seed);
+                let noise: Vec<f32> = samples.iter().map(|_| noise.pink()).collect();
+                add_noise(samples, &noise, snr_db)
+            }
+            Self::LowPass { cutoff_hz } => low_pass(samples, cutoff_hz),
+            Self::Mp3 { bitrate_kbps } => mp3_round_trip(samples, bitrate_kbps)?,
+            Self::Gain { db } => {
+                let gain = 10f32.powf(db / 20.0);
+                samples
+                    .iter()
+                    .map(|&s| (s * gain).clamp(-1.0, 1.0))
+                    .collect()
+            }
+            // Reading the clip as if recorded at a higher rate plays it faster
+            Self::Speed { factor } => {
+                resample(samples, (SAMPLE_RATE as f32 * factor).round() as u32)
+            }
+            Self::Reverb { decay_s } => reverb(samples, decay_s),
+        })
+    }
+}
+
+impl fmt::Display for Augmentation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clean => write!(f, "clean"),
+            Self::WhiteNoise { snr_db } => write!(f, "white:{}", snr_db),
+            Self::PinkNoise { snr_db } => write!(f, "pink:{}", snr_db),
+            Self::LowPass { cutoff_hz } => write!(f, "lowpass:{}", cutoff_hz),
+            Self::Mp3 { bitrate_kbps } => write!(f, "mp3:{}", bitrate_kbps),
+            Self::Gain { db } => write!(f, "gain:{}", db),
+            Self::Speed { factor } => write!(f, "speed:{}", factor),
+            Self::Reverb { decay_s } => write!(f, "reverb:{}", decay_s),
+        }
+    }
+}
+
+/// xorshift64* white noise, filtered into pink noise on demand
+struct NoiseSource {
+    state: u64,
+    pink: [f32; 7],
+}
+
+impl NoiseSource {
+    fn new(seed: u64) -> Self {
+        Self {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+            pink: [0.0; 7],
+        }
+    }
+
+    /// Uniform in [-1, 1)
+    fn white(&mut self) -> f32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40;
+        bits as f32 / (1u64 << 23) as f32 - 1.0
+    }
+
+    /// Paul Kellet's pink noise filter, accurate to 0.05 dB above 9 Hz at 44.1kHz
+    fn pink(&mut self) -> f32 {
+        let white = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+}
+
+fn power(samples: &[f32]) -> f32 {
+    samples.iter().map(|&s| s * s).sum::<f32>() / samples.len().max(1) as f32
+}
+
+/// Mix `noise` in, scaled so the signal is `snr_db` above it
+fn add_noise(samples: &[f32], noise: &[f32], snr_db: f32) -> Vec<f32> {
+    let noise_power = power(noise);
+    if noise_power == 0.0 {
+        return samples.to_vec();
+    }
+    let target = power(samples) / 10f32.powf(snr_db / 10.0);
+    let scale = (target / noise_power).sqrt();
+    samples
+        .iter()
+        .zip(noise)
+        .map(|(&s, &n)| s + n * scale)
+        .collect()
+}
+
+/// Biquad low-pass with Q = 1/sqrt(2), 12 dB per octave above `cutoff_hz`
+fn low_pass(samples: &[f32], cutoff_hz: f32) -> Vec<f32> {
+    let omega = 2.0 * std::f32::consts::PI * cutoff_hz / SAMPLE_RATE as f32;
+    let alpha = omega.sin() / std::f32::consts::SQRT_2;
+    let cos = omega.cos();
+    let a0 = 1.0 + alpha;
+    let b0 = (1.0 - cos) / 2.0 / a0;
+    let b1 = (1.0 - cos) / a0;
+    let a1 = -2.0 * cos / a0;
+    let a2 = (1.0 - alpha) / a0;
+
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    samples
+        .iter()
+        .map(|&x| {
+            let y = b0 * x + b1 * x1 + b0 * x2 - a1 * y1 - a2 * y2;
+            x2 = x1;
+            x1 = x;
+            y2 = y1;
+            y1 = y;
+            y
+        })
+        .collect()
+}
+
+/// Parallel feedback combs into series all-passes, mixed with the dry signal
+fn reverb(samples: &[f32], decay_s: f32) -> Vec<f32> {
+    let delay = |seconds: f32| ((seconds * SAMPLE_RATE as f32) as usize).max(1);
+
+    let mut wet = vec![0.0f32; samples.len()];
+    for &seconds in &REVERB_COMBS {
+        let d = delay(seconds);
+        // Each pass through the loop decays by d / RT60 of 60 dB
+        let feedback = 10f32.powf(-3.0 * seconds / decay_s);
+        let mut buffer = vec![0.0f32; samples.len()];
+        for i in 0..samples.len() {
+            let echo = if i >= d { buffer[i - d] } else { 0.0 };
+            buffer[i] = samples[i] + feedback * echo;
+            wet[i] += buffer[i] / REVERB_COMBS.len() as f32;
+        }
+    }
+
+    for &seconds in &REVERB_ALLPASSES {
+        let d = delay(seconds);
+        let input = wet.clone();
+        for i in 0..wet.len() {
+            let (x_d, y_d) = if i >= d {
+                (input[i - d], wet[i - d])
+            } else {
+                (0.0, 0.0)
+            };
+            wet[i] = -REVERB_ALLPASS_GAIN * input[i] + x_d + REVERB_ALLPASS_GAIN * y_d;
+        }
+    }
+
+    samples
+        .iter()
+        .zip(&wet)
+        .map(|(&dry, &wet)| (1.0 - REVERB_WET) * dry + REVERB_WET * wet)
+        .collect()
+}
+
+/// Encode to MP3 with FFmpeg and decode back
+fn mp3_round_trip(samples: &[f32], bitrate_kbps: u32) -> Result<Vec<f32>> {
+    std::fs::create_dir_all("temp")?;
+    let temp_id = Uuid::new_v4();
+    let temp_wav = format!("temp/{}_clip.wav", temp_id);
+    let temp_mp3 = format!("temp/{}_clip.mp3", temp_id);
+
+    let decoded = write_wav(&temp_wav, samples)
+        .and_then(|_| encode_mp3(&temp_wav, &temp_mp3, bitrate_kbps))
+        .and_then(|_| load_audio(&temp_mp3));
+
+    let _ = std::fs::remove_file(&temp_wav);
+    let _ = std::fs::remove_file(&temp_mp3);
+    decoded
+}
+
+fn encode_mp3(input_path: &str, output_path: &str, bitrate_kbps: u32) -> Result<()> {
+    let ffmpeg_cmd = if cfg!(windows) {
+        "ffmpeg.exe"
+    } else {
+        "ffmpeg"
+    };
+
+    let output = Command::new(ffmpeg_cmd)
+        .args([
+            "-i",
+            input_path,
+            "-codec:a",
+            "libmp3lame",
+            "-b:a",
+            &format!("{}k", bitrate_kbps),
+            "-y",
+            output_path,
+        ])
+        .output()
+        .map_err(|e| AppError::Ffmpeg(format!("Failed to execute FFmpeg: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::Ffmpeg(format!(
+            "FFmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(())
+}
+
+/// 16-bit PCM WAV at the fingerprint sample rate
+fn write_wav(path: &str, samples: &[f32]) -> Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in samples {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&pcm.to_le_bytes());
+    }
+    std::fs::write(path, wav)?;
+    Ok(())
+}
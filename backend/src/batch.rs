@@ -0,0 +1,346 @@
+use crate::error::{AppError, Result};
+use crate::fingerprint::is_audio_file;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+pub const MAX_BATCH_FILES: usize = 500;
+/// Largest single file taken from an archive
+pub const MAX_ARCHIVE_ENTRY_BYTES: u64 = 100 * 1024 * 1024;
+/// Total bytes unpacked from the archives of one request, so a zip bomb can't exhaust memory
+pub const MAX_EXTRACTED_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// How much one batch request may unpack and recognize
+#[derive(Debug, Clone, Copy)]
+pub struct BatchLimits {
+    pub max_files: usize,
+    pub max_entry_bytes: u64,
+    pub max_extracted_bytes: u64,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_files: MAX_BATCH_FILES,
+            max_entry_bytes: MAX_ARCHIVE_ENTRY_BYTES,
+            max_extracted_bytes: MAX_EXTRACTED_BYTES,
+        }
+    }
+}
+
+/// One uploaded file, or one audio file unpacked from an uploaded archive
+pub struct BatchFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// Unpack archives among the uploads and return every audio file to recognize
+/// Plain uploads are taken as they are; archives contribute their audio entries only
+pub fn collect_files(uploads: Vec<BatchFile>) -> Result<Vec<BatchFile>> {
+    collect_files_within(uploads, &BatchLimits::default())
+}
+
+/// `collect_files` with the given limits
+pub fn collect_files_within(
+    uploads: Vec<BatchFile>,
+    limits: &BatchLimits,
+) -> Result<Vec<BatchFile>> {
+    let mut unpacked = Unpacked {
+        files: Vec::new(),
+        budget: limits.max_extracted_bytes,
+        limits,
+    };
+
+    for upload in uploads {
+        match archive_kind(&upload.name) {
+            Some(ArchiveKind::Zip) => unpack_zip(&upload, &mut unpacked)?,
+            Some(ArchiveKind::Tar) => {
+                unpack_tar(&upload.name, Cursor::new(&upload.data), &mut unpacked)?
+            }
+            Some(ArchiveKind::TarGz) => unpack_tar(
+                &upload.name,
+                GzDecoder::new(Cursor::new(&upload.data)),
+                &mut unpacked,
+            )?,
+            None => unpacked.push(upload)?,
+        }
+    }
+
+    Ok(unpacked.files)
+}
+
+/// Files collected so far and what's left of the request's unpacking budget
+struct Unpacked<'a> {
+    files: Vec<BatchFile>,
+    budget: u64,
+    limits: &'a BatchLimits,
+}
+
+impl Unpacked<'_> {
+    fn push(&mut self, file: BatchFile) -> Result<()> {
+        if self.files.len() >= self.limits.max_files {
+            return Err(AppError::InvalidRequest(format!(
+                "At most {} files can be recognized in one batch",
+                self.limits.max_files
+            )));
+        }
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Read an archive entry, failing once it passes the per-file or per-request limit
+    fn read_entry(&mut self, entry: impl Read, name: &str) -> Result<Vec<u8>> {
+        let limit = self.limits.max_entry_bytes.min(self.budget);
+        let mut data = Vec::new();
+        entry
+            .take(limit + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| AppError::InvalidRequest(format!("Failed to unpack {}: {}", name, e)))?;
+
+        if data.len() as u64 > limit {
+            return Err(AppError::InvalidRequest(format!(
+                "{} is too large to unpack",
+                name
+            )));
+        }
+        self.budget -= data.len() as u64;
+        Ok(data)
+    }
+}
+
+fn archive_kind(name: &str) -> Option<ArchiveKind> {
+    let name = name.to_lowercase();
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else {
+        None
+    }
+}
+
+fn unpack_zip(upload: &BatchFile, unpacked: &mut Unpacked) -> Result<()> {
+    let invalid = |e: zip::result::ZipError| {
+        AppError::InvalidRequest(format!("Invalid zip archive {}: {}", upload.name, e))
+    };
+    let mut archive = zip::ZipArchive::new(Cursor::new(&upload.data)).map_err(invalid)?;
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).map_err(invalid)?;
+        let entry_name = entry.name().to_string();
+        if entry.is_dir() || !is_audio_entry(&entry_name) {
+            continue;
+        }
+
+        let data = unpacked.read_entry(entry, &entry_name)?;
+        unpacked.push(BatchFile {
+            name: format!("{}/{}", upload.name, entry_name),
+            data,
+        })?;
+    }
+    Ok(())
+}
+
+fn unpack_tar(archive_name: &str, reader: impl Read, unpacked: &mut Unpacked) -> Result<()> {
+    let invalid = |e: std::io::Error| {
+        AppError::InvalidRequest(format!("Invalid tar archive {}: {}", archive_name, e))
+    };
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries().map_err(invalid)? {
+        let entry = entry.map_err(invalid)?;
+        let entry_name = entry.path().map_err(invalid)?.to_string_lossy().to_string();
+        if !entry.header().entry_type().is_file() || !is_audio_entry(&entry_name) {
+            continue;
+        }
+
+        let data = unpacked.read_entry(entry, &entry_name)?;
+        unpacked.push(BatchFile {
+            name: format!("{}/{}", archive_name, entry_name),
+            data,
+        })?;
+    }
+    Ok(())
+}
+
+/// Audio files by extension, skipping the resource forks macOS adds to archives
+fn is_audio_entry(name: &str) -> bool {
+    let path = Path::new(name);
+    let hidden = path
+        .file_name()
+        .is_some_and(|f| f.to_string_lossy().starts_with("._"));
+    !hidden && !name.starts_with("__MACOSX/") && is_audio_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn upload(name: &str, data: &[u8]) -> BatchFile {
+        BatchFile {
+            name: name.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    fn zip_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            if name.ends_with('/') {
+                writer
+                    .add_directory(*name, SimpleFileOptions::default())
+                    .unwrap();
+            } else {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(data).unwrap();
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            if name.ends_with('/') {
+                header.set_entry_type(tar::EntryType::Directory);
+            }
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    const ENTRIES: &[(&str, &[u8])] = &[
+        ("album/", b""),
+        ("album/one.mp3", b"one"),
+        ("album/cover.jpg", b"jpeg"),
+        ("album/._one.mp3", b"fork"),
+        ("__MACOSX/album/two.flac", b"fork"),
+        ("album/TWO.FLAC", b"two"),
+    ];
+
+    fn names(files: &[BatchFile]) -> Vec<&str> {
+        files.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    fn limits(max_files: usize, max_entry_bytes: u64, max_extracted_bytes: u64) -> BatchLimits {
+        BatchLimits {
+            max_files,
+            max_entry_bytes,
+            max_extracted_bytes,
+        }
+    }
+
+    #[test]
+    fn plain_uploads_are_taken_as_they_are() {
+        let files = collect_files(vec![upload("a.mp3", b"a"), upload("notes.txt", b"b")]).unwrap();
+        assert_eq!(names(&files), ["a.mp3", "notes.txt"]);
+        assert_eq!(files[1].data, b"b");
+    }
+
+    #[test]
+    fn archives_contribute_only_their_audio_entries() {
+        let expected = |archive: &str| {
+            vec![
+                format!("{}/album/one.mp3", archive),
+                format!("{}/album/TWO.FLAC", archive),
+            ]
+        };
+
+        for (name, data) in [
+            ("set.zip", zip_archive(ENTRIES)),
+            ("set.tar", tar_archive(ENTRIES)),
+            ("set.tar.gz", gzip(&tar_archive(ENTRIES))),
+            ("set.tgz", gzip(&tar_archive(ENTRIES))),
+        ] {
+            let files = collect_files(vec![upload(name, &data)]).unwrap();
+            assert_eq!(names(&files), expected(name), "{}", name);
+            assert_eq!(files[0].data, b"one");
+            assert_eq!(files[1].data, b"two");
+        }
+    }
+
+    #[test]
+    fn invalid_archives_are_rejected() {
+        for name in ["set.zip", "set.tar", "set.tar.gz"] {
+            let result = collect_files(vec![upload(name, b"not an archive at all")]);
+            assert!(
+                matches!(result, Err(AppError::InvalidRequest(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn file_count_limit_covers_uploads_and_archive_entries() {
+        let archive = zip_archive(&[("a.mp3", b"a"), ("b.mp3", b"b")]);
+        let within = limits(3, 100, 100);
+
+        let files = collect_files_within(
+            vec![upload("x.wav", b"x"), upload("set.zip", &archive)],
+            &within,
+        )
+        .unwrap();
+        assert_eq!(files.len(), 3);
+
+        let result = collect_files_within(
+            vec![
+                upload("x.wav", b"x"),
+                upload("y.wav", b"y"),
+                upload("set.zip", &archive),
+            ],
+            &within,
+        );
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn entries_over_the_size_limit_are_rejected() {
+        let archive = tar_archive(&[("small.mp3", b"1234"), ("large.mp3", b"12345")]);
+
+        let files = collect_files_within(vec![upload("set.tar", &archive)], &limits(10, 5, 100));
+        assert_eq!(files.unwrap().len(), 2);
+
+        let result = collect_files_within(vec![upload("set.tar", &archive)], &limits(10, 4, 100));
+        assert!(matches!(result, Err(AppError::InvalidRequest(m)) if m.contains("large.mp3")));
+    }
+
+    #[test]
+    fn total_extracted_size_is_limited_across_archives() {
+        let first = zip_archive(&[("a.mp3", b"1234")]);
+        let second = gzip(&tar_archive(&[("b.mp3", b"5678")]));
+        let uploads = || vec![upload("one.zip", &first), upload("two.tgz", &second)];
+
+        assert_eq!(
+            collect_files_within(uploads(), &limits(10, 100, 8))
+                .unwrap()
+                .len(),
+            2
+        );
+        let result = collect_files_within(uploads(), &limits(10, 100, 7));
+        assert!(matches!(result, Err(AppError::InvalidRequest(m)) if m.contains("b.mp3")));
+    }
+}
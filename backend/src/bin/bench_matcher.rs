@@ -0,0 +1,322 @@
+//! Matcher benchmark on a synthetic library
+//!
+//! Builds (or reuses) a database of random songs, then runs noisy clips cut from
+//! them through `find_matches` + `rank_matches` with and without candidate pruning,
+//! reporting latency, peak heap use and accuracy for each configuration.
+
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::fingerprint::HashScheme;
+use sonica_backend::matcher::rank_matches;
+use sonica_backend::storage::Database;
+use sonica_backend::types::{MatchOptions, TrackDetails};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: bench_matcher [--songs 10000] [--hashes-per-song 1000] \
+     [--queries 100] [--query-hashes 300] [--noise 0.3] [--db temp/bench_matcher_<songs>x<hashes>.db]";
+
+/// Counts live heap bytes so each query's peak allocation can be measured
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+struct Args {
+    songs: usize,
+    hashes_per_song: usize,
+    queries: usize,
+    query_hashes: usize,
+    noise: f64,
+    db: Option<String>,
+}
+
+/// xorshift64*, deterministic so a song's hashes can be regenerated from its id
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A hash laid out like `generate_fingerprints`: [f1: 9 bits] [f2: 9 bits] [dt: 14 bits]
+    /// Squaring skews frequencies low like real music, so some hashes become popular
+    fn hash(&mut self) -> u32 {
+        let f1 = (self.next_f64().powi(2) * 512.0) as u32;
+        let f2 = (self.next_f64().powi(2) * 512.0) as u32;
+        let dt = 5 + self.below(46) as u32;
+        (f1 << 23) | (f2 << 14) | dt
+    }
+}
+
+/// Fingerprints of synthetic song `index`, (hash, offset), a few hashes per frame
+fn song_fingerprints(index: usize, count: usize) -> Vec<(u32, u32)> {
+    let mut rng = Rng::new(index as u64 + 1);
+    (0..count).map(|i| (rng.hash(), (i / 4) as u32)).collect()
+}
+
+struct Config {
+    name: &'static str,
+    options: MatchOptions,
+}
+
+#[derive(Default)]
+struct Measurements {
+    latencies: Vec<Duration>,
+    peak_bytes: Vec<usize>,
+    aligned_songs: Vec<usize>,
+    correct: usize,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+
+    std::fs::create_dir_all("temp")?;
+    let db_path = args.db.clone().unwrap_or_else(|| {
+        format!(
+            "temp/bench_matcher_{}x{}.db",
+            args.songs, args.hashes_per_song
+        )
+    });
+    let db = Database::new(&db_path)?;
+    build_library(&db, &db_path, &args)?;
+
+    // Song ids by synthetic index, recovered from the paths
+    let mut song_ids = vec![0; args.songs];
+    for song in db.get_all_songs()? {
+        if let Some(index) = song
+            .path
+            .strip_prefix("synthetic/")
+            .and_then(|i| i.parse::<usize>().ok())
+        {
+            song_ids[index] = song.id;
+        }
+    }
+
+    // The same clips go through every configuration
+    let mut rng = Rng::new(0xC0FFEE);
+    let clips: Vec<(i64, Vec<(u32, u32)>)> = (0..args.queries)
+        .map(|_| {
+            let (index, clip) = make_clip(&mut rng, &args);
+            (song_ids[index], clip)
+        })
+        .collect();
+
+    let configs = [
+        Config {
+            name: "full alignment",
+            options: MatchOptions::default(),
+        },
+        Config {
+            name: "top-50 pruning",
+            options: MatchOptions {
+                max_candidates: Some(50),
+                ..MatchOptions::default()
+            },
+        },
+        Config {
+            name: "top-10 pruning",
+            options: MatchOptions {
+                max_candidates: Some(10),
+                ..MatchOptions::default()
+            },
+        },
+    ];
+
+    println!(
+        "{:<16} {:>9} {:>9} {:>9} {:>12} {:>12} {:>9}",
+        "config", "p50 ms", "p95 ms", "max ms", "peak heap", "aligned", "accuracy"
+    );
+
+    for config in &configs {
+        let mut m = Measurements::default();
+
+        for (song_id, clip) in &clips {
+            let baseline = ALLOCATED.load(Ordering::Relaxed);
+            PEAK.store(baseline, Ordering::Relaxed);
+            let started = Instant::now();
+
+            let matches = db.find_matches(clip, &config.options)?.matches;
+            let ranked = rank_matches(&matches, HashScheme::Pairs, 5);
+
+            m.latencies.push(started.elapsed());
+            m.peak_bytes
+                .push(PEAK.load(Ordering::Relaxed).saturating_sub(baseline));
+            m.aligned_songs.push(matches.len());
+            if ranked.first().map(|&(id, _)| id) == Some(*song_id) {
+                m.correct += 1;
+            }
+        }
+
+        m.latencies.sort_unstable();
+        m.peak_bytes.sort_unstable();
+        println!(
+            "{:<16} {:>9.2} {:>9.2} {:>9.2} {:>9.1} KB {:>12.0} {:>8.1}%",
+            config.name,
+            ms(percentile(&m.latencies, 0.5)),
+            ms(percentile(&m.latencies, 0.95)),
+            ms(*m.latencies.last().unwrap_or(&Duration::ZERO)),
+            *m.peak_bytes.last().unwrap_or(&0) as f64 / 1024.0,
+            m.aligned_songs.iter().sum::<usize>() as f64 / m.aligned_songs.len().max(1) as f64,
+            100.0 * m.correct as f64 / clips.len().max(1) as f64,
+        );
+    }
+
+    println!(
+        "\n{} songs x {} hashes, {} clips of {} hashes with {:.0}% noise",
+        args.songs,
+        args.hashes_per_song,
+        args.queries,
+        args.query_hashes,
+        args.noise * 100.0
+    );
+    println!("peak heap: max over clips; aligned: mean songs given full offset histograms");
+    Ok(())
+}
+
+/// Insert the synthetic songs, reusing the database if it already has the right size
+fn build_library(db: &Database, db_path: &str, args: &Args) -> Result<()> {
+    // The default path encodes the library size, so a matching song count means it's complete
+    let song_count = db.get_all_songs()?.len();
+    if song_count == args.songs {
+        println!("♻️  Reusing {} ({} songs)", db_path, song_count);
+        return Ok(());
+    }
+
+    println!("🧹 Building synthetic library in {}...", db_path);
+    db.clear_database()?;
+
+    let started = Instant::now();
+    for index in 0..args.songs {
+        db.insert_song(
+            &format!("Synthetic {}", index),
+            "Benchmark",
+            &format!("synthetic/{}", index),
+            &TrackDetails::default(),
+            &format!("synthetic-{}", index),
+            &song_fingerprints(index, args.hashes_per_song),
+            None,
+        )?;
+        if (index + 1) % 1000 == 0 {
+            println!("  {} songs", index + 1);
+        }
+    }
+    println!("✨ Built in {:.1}s", started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// A contiguous run of one song's hashes, shifted to start at 0,
+/// with a share of them replaced by random hashes. Returns (song index, clip)
+fn make_clip(rng: &mut Rng, args: &Args) -> (usize, Vec<(u32, u32)>) {
+    let index = rng.below(args.songs);
+    let fingerprints = song_fingerprints(index, args.hashes_per_song);
+    let len = args.query_hashes.min(fingerprints.len());
+    let start = rng.below(fingerprints.len() - len + 1);
+    let first_offset = fingerprints[start].1;
+
+    let clip = fingerprints[start..start + len]
+        .iter()
+        .map(|&(hash, offset)| {
+            let hash = if rng.next_f64() < args.noise {
+                rng.hash()
+            } else {
+                hash
+            };
+            (hash, offset - first_offset)
+        })
+        .collect();
+
+    (index, clip)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        songs: 10_000,
+        hashes_per_song: 1000,
+        queries: 100,
+        query_hashes: 300,
+        noise: 0.3,
+        db: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let value = iter
+            .next()
+            .ok_or_else(|| invalid(&format!("{} needs a value", arg)))?;
+        let number = || {
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| invalid(&format!("{} must be a positive integer", arg)))
+        };
+        match arg.as_str() {
+            "--songs" => args.songs = number()?,
+            "--hashes-per-song" => args.hashes_per_song = number()?,
+            "--queries" => args.queries = number()?,
+            "--query-hashes" => args.query_hashes = number()?,
+            "--noise" => {
+                args.noise = value
+                    .parse()
+                    .ok()
+                    .filter(|n| (0.0..1.0).contains(n))
+                    .ok_or_else(|| invalid("--noise must be between 0 and 1"))?
+            }
+            "--db" => args.db = Some(value),
+            _ => return Err(invalid(&format!("Unknown argument {}", arg))),
+        }
+    }
+    Ok(args)
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(format!("{}\n{}", message, USAGE))
+}
@@ -0,0 +1,321 @@
+//! Offline evaluation of recognition under degraded audio
+//!
+//! Cuts random clips of each length from the library's source files, degrades
+//! them with every augmentation and runs them through the recognizer, reporting
+//! accuracy, false-positive rate and latency per augmentation and clip length.
+//! Clips from `--negatives` files (songs not in the library) count as false
+//! positives whenever they match anything.
+
+use sonica_backend::augment::{Augmentation, DEFAULT_AUGMENTATIONS};
+use sonica_backend::config::Config;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::fingerprint::{load_audio, ms_to_samples};
+use sonica_backend::recognizer::recognize_samples;
+use sonica_backend::storage::Database;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: eval [--db songs.db] [--lengths 3,5,10] [--clips 50] \
+     [--augment clean,white:10,pink:10,lowpass:2000,mp3:64,gain:-20,speed:1.05,reverb:1.0] \
+     [--negatives <dir>] [--seed 1]";
+
+struct Args {
+    db: String,
+    lengths: Vec<f64>,
+    clips: usize,
+    augmentations: Vec<Augmentation>,
+    negatives: Option<PathBuf>,
+    seed: u64,
+}
+
+/// xorshift64*, so a seed reproduces the same clips
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// A file clips are cut from, with the song it should be recognized as
+struct Source {
+    path: String,
+    song_id: Option<i64>,
+}
+
+/// A clip to cut: the length index, where it starts as a share of the
+/// remaining audio, and the seed of its augmentations
+struct PlannedClip {
+    length: usize,
+    start: f64,
+    seed: u64,
+}
+
+/// Outcomes of the clips of one augmentation and length
+#[derive(Default)]
+struct Cell {
+    clips: usize,
+    /// Clips from `--negatives`, which have no right answer
+    negatives: usize,
+    correct: usize,
+    false_positives: usize,
+    latencies: Vec<Duration>,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let config = Config::load(Config::path())?;
+    let db = Database::new(&args.db)?;
+
+    let mut sources: Vec<Source> = db
+        .get_all_songs()?
+        .into_iter()
+        .map(|song| Source {
+            path: song.path,
+            song_id: Some(song.id),
+        })
+        .collect();
+    if sources.is_empty() {
+        return Err(invalid(&format!("{} has no songs to evaluate", args.db)));
+    }
+    let library_size = sources.len();
+    if let Some(dir) = &args.negatives {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                sources.push(Source {
+                    path: path.to_string_lossy().into_owned(),
+                    song_id: None,
+                });
+            }
+        }
+    }
+    let negative_count = sources.len() - library_size;
+
+    // Plan every clip up front so each source is decoded once
+    let mut rng = Rng::new(args.seed);
+    let mut plan: BTreeMap<usize, Vec<PlannedClip>> = BTreeMap::new();
+    for length in 0..args.lengths.len() {
+        for _ in 0..args.clips {
+            plan.entry(rng.below(library_size))
+                .or_default()
+                .push(PlannedClip {
+                    length,
+                    start: rng.next_f64(),
+                    seed: rng.next_u64(),
+                });
+            if negative_count > 0 {
+                plan.entry(library_size + rng.below(negative_count))
+                    .or_default()
+                    .push(PlannedClip {
+                        length,
+                        start: rng.next_f64(),
+                        seed: rng.next_u64(),
+                    });
+            }
+        }
+    }
+
+    println!(
+        "🎧 {} clips per length from {} songs{}, {} augmentations",
+        args.clips,
+        library_size,
+        if negative_count > 0 {
+            format!(" and as many from {} negatives", negative_count)
+        } else {
+            String::new()
+        },
+        args.augmentations.len()
+    );
+
+    let mut cells: Vec<Vec<Cell>> = args
+        .augmentations
+        .iter()
+        .map(|_| args.lengths.iter().map(|_| Cell::default()).collect())
+        .collect();
+    let mut skipped = 0;
+
+    for (source_index, clips) in &plan {
+        let source = &sources[*source_index];
+        let audio = match load_audio(&source.path) {
+            Ok(audio) => audio,
+            Err(e) => {
+                eprintln!("  ❌ {}: {}", source.path, e);
+                skipped += clips.len();
+                continue;
+            }
+        };
+
+        for clip in clips {
+            let len = ms_to_samples((args.lengths[clip.length] * 1000.0) as u64);
+            if audio.len() < len {
+                skipped += 1;
+                continue;
+            }
+            let start = (clip.start * (audio.len() - len) as f64) as usize;
+            let samples = &audio[start..start + len];
+
+            for (a, augmentation) in args.augmentations.iter().enumerate() {
+                let degraded = augmentation.apply(samples, clip.seed)?;
+                let started = Instant::now();
+                let recognition = recognize_samples(&db, &config, &degraded, None)?;
+                let elapsed = started.elapsed();
+
+                let cell = &mut cells[a][clip.length];
+                cell.clips += 1;
+                if source.song_id.is_none() {
+                    cell.negatives += 1;
+                }
+                cell.latencies.push(elapsed);
+                match (recognition.song.map(|song| song.id), source.song_id) {
+                    (Some(found), Some(expected)) if found == expected => cell.correct += 1,
+                    (Some(_), _) => cell.false_positives += 1,
+                    (None, _) => {}
+                }
+            }
+        }
+        println!("  ✅ {} ({} clips)", source.path, clips.len());
+    }
+
+    for row in cells.iter_mut() {
+        for cell in row.iter_mut() {
+            cell.latencies.sort_unstable();
+        }
+    }
+
+    print_table("accuracy", &args, &cells, |cell| {
+        format!("{:.1}%", percent(cell.correct, cell.clips - cell.negatives))
+    });
+    print_table("false positives", &args, &cells, |cell| {
+        format!("{:.1}%", percent(cell.false_positives, cell.clips))
+    });
+    print_table("latency p50/p95 ms", &args, &cells, |cell| {
+        format!(
+            "{:.0}/{:.0}",
+            ms(percentile(&cell.latencies, 0.5)),
+            ms(percentile(&cell.latencies, 0.95))
+        )
+    });
+
+    println!(
+        "\nthreshold {}; accuracy: library clips matched to their song; \
+         false positives: clips matched to a wrong song, or to any song for negatives",
+        config.match_threshold
+    );
+    if skipped > 0 {
+        println!(
+            "⚠️  {} clips skipped (undecodable or shorter than the clip length)",
+            skipped
+        );
+    }
+    Ok(())
+}
+
+fn print_table(title: &str, args: &Args, cells: &[Vec<Cell>], value: impl Fn(&Cell) -> String) {
+    println!("\n{}", title);
+    print!("{:<16}", "augmentation");
+    for length in &args.lengths {
+        print!(" {:>12}", format!("{}s", length));
+    }
+    println!();
+    for (augmentation, row) in args.augmentations.iter().zip(cells) {
+        print!("{:<16}", augmentation.to_string());
+        for cell in row {
+            let value = if cell.clips > 0 {
+                value(cell)
+            } else {
+                "-".to_string()
+            };
+            print!(" {:>12}", value);
+        }
+        println!();
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        db: "songs.db".to_string(),
+        lengths: vec![3.0, 5.0, 10.0],
+        clips: 50,
+        augmentations: Augmentation::parse_list(DEFAULT_AUGMENTATIONS)?,
+        negatives: None,
+        seed: 1,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let value = iter
+            .next()
+            .ok_or_else(|| invalid(&format!("{} needs a value", arg)))?;
+        match arg.as_str() {
+            "--db" => args.db = value,
+            "--lengths" => {
+                args.lengths = value
+                    .split(',')
+                    .map(|length| length.trim().parse::<f64>().ok().filter(|&l| l > 0.0))
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|lengths| !lengths.is_empty())
+                    .ok_or_else(|| invalid("--lengths must be positive seconds, e.g. 3,5,10"))?
+            }
+            "--clips" => {
+                args.clips = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid("--clips must be a positive integer"))?
+            }
+            "--augment" => {
+                args.augmentations =
+                    Augmentation::parse_list(&value).map_err(|e| invalid(&e.to_string()))?;
+                if args.augmentations.is_empty() {
+                    return Err(invalid("--augment needs at least one augmentation"));
+                }
+            }
+            "--negatives" => args.negatives = Some(value.into()),
+            "--seed" => {
+                args.seed = value
+                    .parse()
+                    .map_err(|_| invalid("--seed must be an integer"))?
+            }
+            _ => return Err(invalid(&format!("Unknown argument {}", arg))),
+        }
+    }
+    Ok(args)
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(format!("{}\n{}", message, USAGE))
+}
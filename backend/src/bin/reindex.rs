@@ -1,7 +1,8 @@
+use sonica_backend::config::Config;
 use sonica_backend::error::Result;
-use sonica_backend::fingerprint::{decode_audio, generate_fingerprints, preprocess_audio};
+use sonica_backend::indexer::{index_song, IndexOutcome};
+use sonica_backend::metadata::extract_metadata;
 use sonica_backend::storage::Database;
-use std::path::Path;
 use tokio::fs;
 
 #[tokio::main]
@@ -10,6 +11,9 @@ async fn main() -> Result<()> {
 
     println!("🧹 Clearing database...");
     db.clear_database()?;
+    // An empty index takes the configured fingerprint settings
+    let config = Config::load(Config::path())?;
+    db.adopt_fingerprint_settings(&config.fingerprint)?;
 
     let songs_dir = "songs";
     let mut entries = fs::read_dir(songs_dir).await?;
@@ -24,39 +28,16 @@ async fn main() -> Result<()> {
 
             println!("Processing: {}", filename);
 
-            // Preprocess
-            let temp_output = format!("temp/{}_reindex.wav", uuid::Uuid::new_v4());
-            fs::create_dir_all("temp").await?;
+            let metadata = extract_metadata(&path);
 
-            if let Err(e) = preprocess_audio(&path_str, &temp_output) {
-                eprintln!("  ❌ Preprocess failed: {}", e);
-                continue;
-            }
-
-            // Fingerprint
-            let samples = match decode_audio(&temp_output) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("  ❌ Decode failed: {}", e);
-                    let _ = fs::remove_file(&temp_output).await;
-                    continue;
+            match index_song(&db, &path_str, &metadata) {
+                Ok(IndexOutcome::Indexed { hash_count, .. }) => {
+                    println!("  ✅ Indexed {} hashes", hash_count)
                 }
-            };
-
-            let fingerprints = generate_fingerprints(&samples);
-            let _ = fs::remove_file(&temp_output).await;
-
-            // Insert
-            let title = Path::new(&filename)
-                .file_stem()
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
-            let artist = "Unknown"; // Simple default for re-indexing
-
-            match db.insert_song(&title, artist, &path_str, &fingerprints) {
-                Ok(_) => println!("  ✅ Indexed {} hashes", fingerprints.len()),
-                Err(e) => eprintln!("  ❌ Insert failed: {}", e),
+                Ok(IndexOutcome::Duplicate { song_id, kind }) => {
+                    println!("  🔁 Duplicate of song {} ({})", song_id, kind.as_str())
+                }
+                Err(e) => eprintln!("  ❌ Indexing failed: {}", e),
             }
         }
     }
@@ -0,0 +1,89 @@
+use sonica_backend::config::Config;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::fingerprint::load_audio;
+use sonica_backend::storage::Database;
+use sonica_backend::visualize::{self, ExportFormat};
+use std::path::Path;
+
+const USAGE: &str = "Usage: spectrogram <file> [--out <path>] [--format png|json|npy|npz] \
+     [--reference <song_id>] [--max-bin 512]";
+
+fn main() -> Result<()> {
+    let mut path = None;
+    let mut out = None;
+    let mut format = None;
+    let mut reference = None;
+    let mut max_bin = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = Some(value(args.next(), &arg)?),
+            "--format" => format = Some(ExportFormat::parse(&value(args.next(), &arg)?)?),
+            "--reference" => reference = Some(number(args.next(), &arg)?),
+            "--max-bin" => max_bin = Some(number(args.next(), &arg)? as usize),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(invalid(&format!("Unknown argument {}", arg))),
+        }
+    }
+    let path = path.ok_or_else(|| invalid("No input file given"))?;
+    let max_bin = visualize::max_bin(max_bin)?;
+
+    // --format wins, then the extension of --out, then PNG
+    let format = format
+        .or_else(|| out.as_deref().and_then(ExportFormat::from_path))
+        .unwrap_or(ExportFormat::Png);
+    let out = out.unwrap_or_else(|| {
+        let stem = Path::new(&path)
+            .file_stem()
+            .map_or("spectrogram".into(), |s| s.to_string_lossy());
+        format!("{}.{}", stem, format.extension())
+    });
+
+    let samples = load_audio(&path)?;
+    let db = match reference {
+        Some(_) => Some(Database::new("songs.db")?),
+        None => None,
+    };
+    // Preprocess like the library does, or as configured for a new one
+    let settings = match &db {
+        Some(db) => db.fingerprint_settings(),
+        None => Config::load(Config::path())?.fingerprint,
+    };
+    let reference = db.as_ref().zip(reference.map(|id| id as i64));
+    let constellation = visualize::constellation(&samples, &settings, reference)?;
+    std::fs::write(&out, visualize::export(&constellation, format, max_bin)?)?;
+
+    println!(
+        "🎨 {}: {} frames, {} peaks -> {}",
+        path,
+        constellation.spectrogram.len(),
+        constellation.peaks.len(),
+        out
+    );
+    if let Some(reference) = &constellation.reference {
+        println!(
+            "  🎯 {} - {}: {} hashes aligned at frame {}, {} peaks matched",
+            reference.artist,
+            reference.title,
+            reference.aligned_hashes,
+            reference.relative_offset,
+            constellation.matched.len()
+        );
+    }
+    Ok(())
+}
+
+fn value(value: Option<String>, flag: &str) -> Result<String> {
+    value.ok_or_else(|| invalid(&format!("{} needs a value", flag)))
+}
+
+fn number(value: Option<String>, flag: &str) -> Result<u64> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(&format!("{} needs a number", flag)))
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(format!("{}\n{}", message, USAGE))
+}
@@ -0,0 +1,76 @@
+use sonica_backend::config::Config;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::storage::Database;
+use sonica_backend::timeline::{recognize_timeline_file, TimelineOptions};
+use sonica_backend::types::TimelineQuery;
+
+const USAGE: &str = "Usage: timeline <file> [--window-ms 10000] [--hop-ms 5000] [--json]";
+
+fn main() -> Result<()> {
+    let mut path = None;
+    let mut query = TimelineQuery::default();
+    let mut json = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--window-ms" => query.window_ms = Some(number(args.next(), &arg)?),
+            "--hop-ms" => query.hop_ms = Some(number(args.next(), &arg)?),
+            "--json" => json = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(invalid(&format!("Unknown argument {}", arg))),
+        }
+    }
+    let path = path.ok_or_else(|| invalid("No input file given"))?;
+    let options = TimelineOptions::from_query(&query)?;
+
+    let config = Config::load(Config::path())?;
+    let db = Database::new("songs.db")?;
+
+    let timeline = recognize_timeline_file(&db, &config, &path, &options)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&timeline)?);
+        return Ok(());
+    }
+
+    println!(
+        "🎚️  {} ({}), {} segments",
+        path,
+        clock(timeline.duration_ms),
+        timeline.segments.len()
+    );
+    for segment in &timeline.segments {
+        println!(
+            "  {} - {}  {} - {}  (from {}, {:.0}%)",
+            clock(segment.start_ms),
+            clock(segment.end_ms),
+            segment.artist,
+            segment.title,
+            clock(segment.offset_in_song_ms),
+            segment.confidence * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// h:mm:ss
+fn clock(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn number(value: Option<String>, flag: &str) -> Result<u64> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(&format!("{} needs a number", flag)))
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(format!("{}\n{}", message, USAGE))
+}
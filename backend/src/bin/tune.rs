@@ -0,0 +1,180 @@
+use serde::Deserialize;
+use sonica_backend::config::Config;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::recognizer::recognize_audio;
+use sonica_backend::storage::Database;
+use sonica_backend::tuning::{label_query, tune, DEFAULT_MIN_PRECISION, MIN_LABELLED_SAMPLES};
+use sonica_backend::types::LabelledScore;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "Usage: tune [--eval <manifest.json>] [--no-history] \
+     [--min-precision <0-1>] [--report <roc.json>] [--dry-run]";
+
+/// One clip of a labelled evaluation set
+#[derive(Deserialize)]
+struct EvalClip {
+    /// Audio file, relative to the manifest
+    path: PathBuf,
+    /// Song the clip is from, or null for clips that aren't in the library
+    song_id: Option<i64>,
+}
+
+struct Args {
+    eval: Option<PathBuf>,
+    history: bool,
+    min_precision: f64,
+    report: Option<PathBuf>,
+    dry_run: bool,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let config_path = Config::path();
+    let mut config = Config::load(&config_path)?;
+    let db = Database::new("songs.db")?;
+
+    let mut samples = Vec::new();
+
+    if args.history {
+        let history = db.labelled_scores()?;
+        println!("📜 {} recognitions with feedback", history.len());
+        samples.extend(history);
+    }
+
+    if let Some(manifest) = &args.eval {
+        let eval = replay_eval_set(&db, &config, manifest)?;
+        println!(
+            "🎧 {} clips replayed from {}",
+            eval.len(),
+            manifest.display()
+        );
+        samples.extend(eval);
+    }
+
+    let report = tune(&samples, args.min_precision);
+    println!(
+        "📊 {} samples ({} correct, {} wrong), ROC AUC {:.3}",
+        report.samples, report.positives, report.negatives, report.auc
+    );
+
+    println!("threshold  precision  recall  fpr     f1");
+    let mut last = None;
+    for point in &report.points {
+        // Only print thresholds where the outcome changes
+        let counts = (point.true_positives, point.false_positives);
+        if last == Some(counts) {
+            continue;
+        }
+        last = Some(counts);
+        println!(
+            "{:>9}  {:>9.3}  {:>6.3}  {:>6.3}  {:.3}",
+            point.threshold, point.precision, point.recall, point.false_positive_rate, point.f1
+        );
+    }
+
+    if let Some(path) = &args.report {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("📝 Report written to {}", path.display());
+    }
+
+    if report.samples < MIN_LABELLED_SAMPLES || report.positives == 0 {
+        println!(
+            "⚠️  Need at least {} labelled queries including correct ones, keeping threshold {}",
+            MIN_LABELLED_SAMPLES, config.match_threshold
+        );
+        return Ok(());
+    }
+
+    let Some(recommended) = &report.recommended else {
+        println!(
+            "⚠️  No usable threshold found, keeping {}",
+            config.match_threshold
+        );
+        return Ok(());
+    };
+
+    println!(
+        "✅ Recommended threshold {} (precision {:.3}, recall {:.3}), currently {}",
+        recommended.threshold, recommended.precision, recommended.recall, config.match_threshold
+    );
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    config.match_threshold = recommended.threshold;
+    config.save(&config_path)?;
+    println!(
+        "💾 Saved to {}, restart the server to apply it",
+        config_path
+    );
+
+    Ok(())
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        eval: None,
+        history: true,
+        min_precision: DEFAULT_MIN_PRECISION,
+        report: None,
+        dry_run: false,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--eval" => args.eval = Some(value(&mut iter, &arg)?.into()),
+            "--no-history" => args.history = false,
+            "--min-precision" => {
+                args.min_precision = value(&mut iter, &arg)?
+                    .parse()
+                    .ok()
+                    .filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or_else(|| invalid("--min-precision must be between 0 and 1"))?
+            }
+            "--report" => args.report = Some(value(&mut iter, &arg)?.into()),
+            "--dry-run" => args.dry_run = true,
+            _ => return Err(invalid(&format!("Unknown argument {}", arg))),
+        }
+    }
+
+    if !args.history && args.eval.is_none() {
+        return Err(invalid("--no-history needs --eval"));
+    }
+    Ok(args)
+}
+
+fn value(iter: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    iter.next()
+        .ok_or_else(|| invalid(&format!("{} needs a value", flag)))
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidRequest(format!("{}\n{}", message, USAGE))
+}
+
+/// Run every clip in the manifest through the recognizer and label its top candidate
+fn replay_eval_set(db: &Database, config: &Config, manifest: &Path) -> Result<Vec<LabelledScore>> {
+    let clips: Vec<EvalClip> = serde_json::from_str(&std::fs::read_to_string(manifest)?)?;
+    let base = manifest.parent().unwrap_or(Path::new("."));
+
+    let mut samples = Vec::new();
+    for clip in clips {
+        let path = base.join(&clip.path);
+        let recognition = match std::fs::read(&path)
+            .map_err(AppError::from)
+            .and_then(|data| recognize_audio(db, config, &data, None))
+        {
+            Ok(recognition) => recognition,
+            Err(e) => {
+                eprintln!("  ❌ {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        samples.push(label_query(clip.song_id, recognition.top_candidate()));
+    }
+
+    Ok(samples)
+}
@@ -0,0 +1,160 @@
+use crate::cover::DEFAULT_COVER_THRESHOLD;
+use crate::error::{AppError, Result};
+use crate::fingerprint::FingerprintSettings;
+use crate::matcher::DEFAULT_MATCH_THRESHOLD;
+use crate::types::MatchOptions;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Config file read at startup, overridable with SONICA_CONFIG
+pub const DEFAULT_CONFIG_PATH: &str = "sonica.json";
+
+/// Hashes shared by this many songs are mostly silence, steady tones and common beats
+pub const DEFAULT_MAX_HASH_SONGS: u64 = 1000;
+
+/// Candidates kept after counting raw hits; the true song is almost always among them
+pub const DEFAULT_MAX_CANDIDATES: usize = 50;
+
+/// Largest `max_candidates` a config may set; each shortlisted song is bound as its own
+/// SQLite parameter, so this stays far below SQLite's limit on bound variables
+pub const MAX_CANDIDATES_LIMIT: usize = 1000;
+
+/// Time a recognition may take, counted from when its request arrives, so it covers
+/// receiving and decoding the clip as well as matching. Hash lookups and candidate scoring
+/// stop once it passes and the best result so far is returned; FFmpeg decoding isn't
+/// interrupted, but a clip that took the whole budget to decode is not looked up at all
+pub const DEFAULT_RECOGNITION_DEADLINE_MS: u64 = 3000;
+
+/// Each worker runs its own FFmpeg process
+pub const DEFAULT_BATCH_WORKERS: usize = 4;
+
+/// A few hours of compressed audio; uploads are streamed to disk, so this bounds disk use
+pub const DEFAULT_MAX_TIMELINE_UPLOAD_MB: usize = 256;
+
+/// Server settings loaded from the config file
+/// Missing fields fall back to their defaults, so an empty file is valid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Raw score a top candidate must exceed to be reported as a match
+    pub match_threshold: i64,
+    /// Query hashes found in more songs than this are skipped; null disables the cap
+    pub max_hash_songs: Option<u64>,
+    /// Weight histogram votes by inverse document frequency of their hash,
+    /// relative to the query's median hash
+    pub idf_weighting: bool,
+    /// Songs with the most raw hash hits that get full offset alignment, from 1 to
+    /// `MAX_CANDIDATES_LIMIT`; null aligns all
+    pub max_candidates: Option<usize>,
+    /// Time budget of the hash lookup of one recognition in milliseconds; null waits for the full lookup
+    pub recognition_deadline_ms: Option<u64>,
+    /// Files of one batch request recognized at the same time
+    pub batch_workers: usize,
+    /// Largest recording `POST /recognize/timeline` accepts, in megabytes
+    pub max_timeline_upload_mb: usize,
+    /// Mean chroma similarity a top candidate must exceed in `mode=cover` recognition
+    pub cover_threshold: f32,
+    /// Fingerprint settings of a new index; an index with songs keeps the
+    /// settings it was built with until it's rebuilt with `reindex`
+    pub fingerprint: FingerprintSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            match_threshold: DEFAULT_MATCH_THRESHOLD,
+            max_hash_songs: Some(DEFAULT_MAX_HASH_SONGS),
+            idf_weighting: false,
+            max_candidates: Some(DEFAULT_MAX_CANDIDATES),
+            recognition_deadline_ms: Some(DEFAULT_RECOGNITION_DEADLINE_MS),
+            batch_workers: DEFAULT_BATCH_WORKERS,
+            max_timeline_upload_mb: DEFAULT_MAX_TIMELINE_UPLOAD_MB,
+            cover_threshold: DEFAULT_COVER_THRESHOLD,
+            fingerprint: FingerprintSettings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Path from SONICA_CONFIG, or the default next to the database
+    pub fn path() -> String {
+        std::env::var("SONICA_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    /// Read the config file, using defaults if it doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let config: Self = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject settings the recognizer can't run with
+    pub fn validate(&self) -> Result<()> {
+        if let Some(max_candidates) = self.max_candidates {
+            if !(1..=MAX_CANDIDATES_LIMIT).contains(&max_candidates) {
+                return Err(AppError::Config(format!(
+                    "max_candidates must be between 1 and {}, got {}",
+                    MAX_CANDIDATES_LIMIT, max_candidates
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            max_hash_songs: self.max_hash_songs,
+            idf_weighting: self.idf_weighting,
+            max_candidates: self.max_candidates,
+            deadline: None,
+        }
+    }
+
+    /// Deadline of a recognition whose request arrived at `started`
+    pub fn recognition_deadline(&self, started: Instant) -> Option<Instant> {
+        self.recognition_deadline_ms
+            .map(|ms| started + Duration::from_millis(ms))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents + "\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Config {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn max_candidates_must_fit_the_shortlist_query() {
+        assert!(Config::default().validate().is_ok());
+        assert!(parse(r#"{"max_candidates": null}"#).validate().is_ok());
+        assert!(parse(r#"{"max_candidates": 1}"#).validate().is_ok());
+        assert!(parse(&format!(
+            r#"{{"max_candidates": {}}}"#,
+            MAX_CANDIDATES_LIMIT
+        ))
+        .validate()
+        .is_ok());
+
+        for invalid in [0, MAX_CANDIDATES_LIMIT + 1, 1_000_000] {
+            let config = parse(&format!(r#"{{"max_candidates": {}}}"#, invalid));
+            assert!(
+                matches!(config.validate(), Err(AppError::Config(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
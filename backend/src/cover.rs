@@ -0,0 +1,256 @@
+//! Cover identification: covers, live versions and re-recordings
+//!
+//! Landmark hashes only match the exact recording. Here every song keeps a
+//! compact chroma sequence instead, and a query's chroma is aligned against
+//! each of them in all 12 keys with a tempo-tolerant dynamic programming
+//! alignment. The score is the mean similarity of the aligned steps, so it
+//! doesn't grow with the query length. Every song is compared, so queries take
+//! time linear in the library size.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::fingerprint::{chroma_sequence, duration_ms, load_audio, transpose, Chroma};
+use crate::storage::Database;
+use crate::types::SongMetadata;
+use rayon::prelude::*;
+use tracing::{info, warn};
+
+/// Default mean similarity along the alignment for a cover to be reported
+/// Unrelated songs reach about 0.4 by lining up chords that happen to agree
+pub const DEFAULT_COVER_THRESHOLD: f32 = 0.55;
+
+/// Subtracted per step where the query holds or skips a song step, so the
+/// alignment follows the diagonal unless the tempos really differ
+const TEMPO_CHANGE_PENALTY: f32 = 0.1;
+/// Queries shorter than this many chroma steps (about 4 seconds) aren't compared
+const MIN_QUERY_STEPS: usize = 8;
+/// Runner-up candidates kept
+const CANDIDATES_KEPT: usize = 5;
+
+pub struct CoverCandidate {
+    pub song_id: i64,
+    /// Mean similarity of the aligned chroma steps, from -1 to 1
+    pub similarity: f32,
+    /// Semitones the query is above the song, from -5 to 6
+    pub transposition: i8,
+}
+
+/// Outcome of matching one query clip against the library's chroma
+pub struct CoverRecognition {
+    pub query_duration_ms: u64,
+    /// Best candidates, best first, including those below the threshold
+    pub candidates: Vec<CoverCandidate>,
+    /// Metadata of the top candidate if it passed the threshold
+    pub song: Option<SongMetadata>,
+}
+
+impl CoverRecognition {
+    pub fn top_candidate(&self) -> Option<&CoverCandidate> {
+        self.candidates.first()
+    }
+}
+
+/// Chroma as stored: one byte per pitch class, 0-255 relative to the loudest
+pub fn encode_chroma(sequence: &[Chroma]) -> Vec<u8> {
+    sequence
+        .iter()
+        .flat_map(|chroma| chroma.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect()
+}
+
+pub fn decode_chroma(data: &[u8]) -> Vec<Chroma> {
+    data.chunks_exact(12)
+        .map(|bytes| std::array::from_fn(|class| bytes[class] as f32 / 255.0))
+        .collect()
+}
+
+/// Compute and store the chroma sequence of a song's decoded 16kHz mono samples
+pub fn store_chroma(db: &Database, song_id: i64, samples: &[f32]) -> Result<()> {
+    db.insert_chroma(song_id, &encode_chroma(&chroma_sequence(samples)))
+}
+
+/// Decode and store the chroma of songs indexed before chroma was kept
+/// Songs whose files fail to decode are skipped; returns the number stored
+pub fn backfill_chroma(db: &Database) -> Result<usize> {
+    let songs = db.songs_without_chroma()?;
+    if songs.is_empty() {
+        return Ok(0);
+    }
+    info!(
+        "Computing chroma of {} songs for cover identification",
+        songs.len()
+    );
+
+    let mut stored = 0;
+    for (song_id, path) in songs {
+        match load_audio(&path) {
+            Ok(samples) => {
+                store_chroma(db, song_id, &samples)?;
+                stored += 1;
+            }
+            Err(e) => warn!("Skipping chroma of {}: {}", path, e),
+        }
+    }
+    Ok(stored)
+}
+
+/// Match decoded 16kHz mono samples against every song's chroma
+pub fn recognize_cover(
+    db: &Database,
+    config: &Config,
+    samples: &[f32],
+) -> Result<CoverRecognition> {
+    let mut recognition = CoverRecognition {
+        query_duration_ms: duration_ms(samples),
+        candidates: Vec::new(),
+        song: None,
+    };
+
+    let query: Vec<Chroma> = chroma_sequence(samples).iter().map(center).collect();
+    if query.len() < MIN_QUERY_STEPS {
+        return Ok(recognition);
+    }
+    // The query in all 12 keys, shifted up 0-11 semitones
+    let transposed: Vec<Vec<Chroma>> = (0..12)
+        .map(|shift| query.iter().map(|c| transpose(c, shift)).collect())
+        .collect();
+
+    let songs = db.get_all_chroma()?;
+    let mut candidates: Vec<CoverCandidate> = songs
+        .par_iter()
+        .filter_map(|(song_id, data)| {
+            let song: Vec<Chroma> = decode_chroma(data).iter().map(center).collect();
+            if song.is_empty() {
+                return None;
+            }
+            let (similarity, transposition) = align_transposed(&transposed, &song);
+            Some(CoverCandidate {
+                song_id: *song_id,
+                similarity,
+                transposition,
+            })
+        })
+        .collect();
+    candidates.sort_unstable_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then(a.song_id.cmp(&b.song_id))
+    });
+    candidates.truncate(CANDIDATES_KEPT);
+    recognition.candidates = candidates;
+
+    if let Some(best) = recognition.top_candidate() {
+        if best.similarity > config.cover_threshold {
+            recognition.song = db.get_song_metadata(best.song_id)?;
+        }
+    }
+    Ok(recognition)
+}
+
+/// Best alignment of the query in any key
+/// Returns (similarity, semitones the query is above the song)
+fn align_transposed(transposed: &[Vec<Chroma>], song: &[Chroma]) -> (f32, i8) {
+    transposed
+        .iter()
+        .enumerate()
+        .map(|(shift, query)| {
+            // Shifting the query up `shift` semitones lines it up, so it was that far below
+            let above = (12 - shift) % 12;
+            let above = if above > 6 {
+                above as i8 - 12
+            } else {
+                above as i8
+            };
+            (align(query, song), above)
+        })
+        // Ties go to the smallest transposition
+        .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.abs().cmp(&a.1.abs())))
+        .unwrap_or((0.0, 0))
+}
+
+/// Mean similarity along the best alignment of the whole query with any part of the song
+/// Each query step moves one song step on, or holds or skips one at a penalty,
+/// so tempos within a factor of two of each other still line up
+fn align(query: &[Chroma], song: &[Chroma]) -> f32 {
+    let mut previous: Vec<f32> = song.iter().map(|s| dot(&query[0], s)).collect();
+    let mut current = vec![f32::NEG_INFINITY; song.len()];
+
+    for q in &query[1..] {
+        for (j, s) in song.iter().enumerate() {
+            let diagonal = if j >= 1 {
+                previous[j - 1]
+            } else {
+                f32::NEG_INFINITY
+            };
+            let skip = if j >= 2 {
+                previous[j - 2]
+            } else {
+                f32::NEG_INFINITY
+            };
+            let hold = previous[j];
+            let best = diagonal.max(skip.max(hold) - TEMPO_CHANGE_PENALTY);
+            current[j] = dot(q, s) + best;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous.into_iter().fold(f32::NEG_INFINITY, f32::max) / query.len() as f32
+}
+
+/// Zero mean and unit length, so the dot product is a correlation and
+/// chords that share no notes score below zero; silence stays all zero
+fn center(chroma: &Chroma) -> Chroma {
+    let mean = chroma.iter().sum::<f32>() / 12.0;
+    let mut centered = chroma.map(|value| value - mean);
+    let norm = dot(&centered, &centered).sqrt();
+    if norm > f32::EPSILON {
+        centered.iter_mut().for_each(|value| *value /= norm);
+    }
+    centered
+}
+
+fn dot(a: &Chroma, b: &Chroma) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chroma_round_trips_through_bytes() {
+        let sequence = vec![[0.0, 0.5, 1.0, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.75]; 3];
+        let data = encode_chroma(&sequence);
+        assert_eq!(data.len(), 36);
+        for (decoded, original) in decode_chroma(&data).iter().zip(&sequence) {
+            for (a, b) in decoded.iter().zip(original) {
+                assert!((a - b).abs() < 0.5 / 255.0 + f32::EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn alignment_finds_the_transposition() {
+        // A major or minor chord on every step, in no repeating pattern
+        let song: Vec<Chroma> = (0..24)
+            .map(|step| {
+                let root = (step * step * 7 + 3 * step) % 12;
+                let third = if step % 3 == 0 { 3 } else { 4 };
+                let mut chroma = [0.0; 12];
+                for note in [0, third, 7] {
+                    chroma[(root + note) % 12] = 1.0;
+                }
+                center(&chroma)
+            })
+            .collect();
+        // Steps 6-17 played 3 semitones higher
+        let query: Vec<Chroma> = song[6..18].iter().map(|c| transpose(c, 3)).collect();
+        let transposed: Vec<Vec<Chroma>> = (0..12)
+            .map(|shift| query.iter().map(|c| transpose(c, shift)).collect())
+            .collect();
+
+        let (similarity, transposition) = align_transposed(&transposed, &song);
+        assert_eq!(transposition, 3);
+        assert!((similarity - 1.0).abs() < 1e-4);
+    }
+}
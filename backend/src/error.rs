@@ -26,12 +26,18 @@ pub enum AppError {
     #[error("Fingerprint error: {0}")]
     Fingerprint(String),
 
+    #[error("Metadata error: {0}")]
+    Metadata(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
+    #[error("Config error: {0}")]
+    Config(String),
+
     #[error("External API error: {0}")]
     External(String),
 
@@ -48,8 +54,10 @@ impl IntoResponse for AppError {
             AppError::Audio(e) => (StatusCode::BAD_REQUEST, e),
             AppError::Ffmpeg(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             AppError::Fingerprint(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+            AppError::Metadata(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             AppError::NotFound(e) => (StatusCode::NOT_FOUND, e),
             AppError::InvalidRequest(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::Config(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             AppError::External(e) => (StatusCode::BAD_GATEWAY, e),
             AppError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
         };
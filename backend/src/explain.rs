@@ -0,0 +1,100 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::fingerprint::{conditioned_spectrogram, duration_ms, find_peaks, frames_to_ms};
+use crate::matcher::{align, confidence, offset_histogram, rank_matches};
+use crate::storage::Database;
+use crate::types::{CandidateExplanation, Explanation, HashHits, HistogramBin, MatchDecision};
+use std::collections::HashMap;
+
+/// Candidates explained per query
+pub const EXPLAIN_CANDIDATES: usize = 10;
+/// Histogram bins reported per candidate
+pub const HISTOGRAM_BINS: usize = 5;
+
+/// Run the recognition pipeline on decoded 16kHz mono samples, keeping what
+/// each stage produced: peaks, hash hits, candidate histograms and the decision
+/// Runs without a deadline so the whole query is always looked up
+pub fn explain_samples(db: &Database, config: &Config, samples: &[f32]) -> Result<Explanation> {
+    let settings = db.fingerprint_settings();
+    let spec = conditioned_spectrogram(samples, &settings.preprocessing);
+    let peaks = find_peaks(&spec);
+    let peak_count = peaks.len();
+    let fingerprints = settings.scheme.hash(peaks);
+
+    let mut occurrences: HashMap<u32, usize> = HashMap::new();
+    for &(hash, _) in &fingerprints {
+        *occurrences.entry(hash).or_default() += 1;
+    }
+    let query_hashes: Vec<u32> = fingerprints.iter().map(|&(hash, _)| hash).collect();
+    let mut hashes: Vec<HashHits> = db
+        .hash_counts(&query_hashes)?
+        .into_iter()
+        .map(|count| HashHits {
+            hash: count.hash,
+            occurrences: occurrences[&count.hash],
+            db_hits: count.count,
+            song_count: count.song_count,
+            skipped: config
+                .max_hash_songs
+                .is_some_and(|max| count.song_count > max),
+        })
+        .collect();
+    hashes.sort_by(|a, b| b.db_hits.cmp(&a.db_hits).then(a.hash.cmp(&b.hash)));
+
+    let matches = db
+        .find_matches(&fingerprints, &config.match_options())?
+        .matches;
+    let ranked = rank_matches(&matches, settings.scheme, EXPLAIN_CANDIDATES);
+
+    let mut candidates = Vec::with_capacity(ranked.len());
+    for (song_id, raw_score) in ranked {
+        let Some(song) = db.get_song_metadata(song_id)? else {
+            continue;
+        };
+        let offsets = &matches[&song_id];
+        let time_scale = align(offsets, settings.scheme).time_scale;
+        candidates.push(CandidateExplanation {
+            song_id,
+            title: song.title,
+            artist: song.artist,
+            raw_score,
+            confidence: confidence(raw_score),
+            hash_hits: offsets.len(),
+            time_scale,
+            histogram: offset_histogram(offsets, time_scale, HISTOGRAM_BINS)
+                .into_iter()
+                .map(|(relative_offset, votes)| HistogramBin {
+                    relative_offset,
+                    offset_ms: frames_to_ms(relative_offset),
+                    votes,
+                })
+                .collect(),
+        });
+    }
+
+    let best_score = candidates.first().map_or(0, |c| c.raw_score);
+    let decision = if fingerprints.is_empty() {
+        MatchDecision::NoFingerprints
+    } else if candidates.is_empty() {
+        MatchDecision::NoCandidates
+    } else if best_score > config.match_threshold {
+        MatchDecision::Matched
+    } else {
+        MatchDecision::BelowThreshold
+    };
+
+    Ok(Explanation {
+        query_duration_ms: duration_ms(samples),
+        peak_count,
+        hash_count: fingerprints.len(),
+        distinct_hashes: hashes.len(),
+        hashes_without_hits: hashes.iter().filter(|h| h.db_hits == 0).count(),
+        hashes_skipped: hashes.iter().filter(|h| h.skipped).count(),
+        aligned_candidates: matches.len(),
+        threshold: config.match_threshold,
+        best_score,
+        decision,
+        candidates,
+        hashes,
+    })
+}
@@ -1,5 +1,9 @@
+//! Audio loading for fingerprinting: FFmpeg preprocessing and symphonia decoding
+//!
+//! The DSP itself lives in the `sonica-fingerprint` crate so it can be built for
+//! WebAssembly; it's re-exported here for the rest of the backend.
+
 use crate::error::{AppError, Result};
-use rustfft::{num_complex::Complex, FftPlanner};
 use std::path::Path;
 use std::process::Command;
 use symphonia::core::audio::Signal;
@@ -7,10 +11,27 @@ use symphonia::core::io::MediaSourceStream;
 use symphonia::core::probe::Hint;
 use symphonia::default::get_codecs;
 use symphonia::default::get_probe;
-
-const SAMPLE_RATE: u32 = 16000;
-const WINDOW_SIZE: usize = 4096; // Better frequency resolution
-const HOP_SIZE: usize = 2048; // 50% overlap
+use uuid::Uuid;
+
+pub use sonica_fingerprint::{
+    bin_to_hz, chroma_sequence, chroma_step_ms, conditioned_spectrogram, duration_ms, find_peaks,
+    fingerprint, frames_to_ms, generate_fingerprints, hash_peak_pair, hash_peaks, hash_triplets,
+    ms_to_samples, spectrogram, transpose, triplets, Chroma, FingerprintSettings, HashScheme,
+    Normalization, Preprocessing, Triplet, FINGERPRINT_VERSION, FREQUENCY_BINS, SAMPLE_RATE,
+};
+
+/// Whether a file is audio the indexer and recognizer take, by extension
+pub fn is_audio_file(path: &Path) -> bool {
+    if let Some(ext) = path.extension() {
+        let ext_lower = ext.to_string_lossy().to_lowercase();
+        matches!(
+            ext_lower.as_str(),
+            "mp3" | "wav" | "flac" | "m4a" | "aac" | "ogg" | "opus" | "wma"
+        )
+    } else {
+        false
+    }
+}
 
 /// Preprocess audio using FFmpeg (convert to 16kHz mono WAV)
 pub fn preprocess_audio(input_path: &str, output_path: &str) -> Result<()> {
@@ -46,8 +67,43 @@ pub fn preprocess_audio(input_path: &str, output_path: &str) -> Result<()> {
     Ok(())
 }
 
+/// Preprocess any audio file with FFmpeg and decode it to 16kHz mono samples
+pub fn load_audio(path: &str) -> Result<Vec<f32>> {
+    std::fs::create_dir_all("temp")?;
+    let temp_output = format!("temp/{}_processed.wav", Uuid::new_v4());
+    let samples = preprocess_audio(path, &temp_output).and_then(|_| decode_audio(&temp_output));
+    let _ = std::fs::remove_file(&temp_output);
+    samples
+}
+
+/// Like `load_audio`, but hands the samples to `on_block` as they're decoded
+/// instead of collecting them, so long recordings needn't fit in memory
+/// Returns the number of samples decoded
+pub fn stream_audio(path: &str, on_block: impl FnMut(&[f32]) -> Result<()>) -> Result<usize> {
+    std::fs::create_dir_all("temp")?;
+    let temp_output = format!("temp/{}_processed.wav", Uuid::new_v4());
+    let decoded = preprocess_audio(path, &temp_output)
+        .and_then(|_| decode_audio_blocks(&temp_output, on_block));
+    let _ = std::fs::remove_file(&temp_output);
+    decoded
+}
+
 /// Decode audio file to float samples
 pub fn decode_audio(path: &str) -> Result<Vec<f32>> {
+    let mut samples = Vec::new();
+    decode_audio_blocks(path, |block| {
+        samples.extend_from_slice(block);
+        Ok(())
+    })?;
+    Ok(samples)
+}
+
+/// Decode audio file packet by packet, passing each packet's float samples to `on_block`
+/// Returns the number of samples decoded
+pub fn decode_audio_blocks(
+    path: &str,
+    mut on_block: impl FnMut(&[f32]) -> Result<()>,
+) -> Result<usize> {
     let file = std::fs::File::open(path)?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
@@ -74,7 +130,8 @@ pub fn decode_audio(path: &str) -> Result<Vec<f32>> {
         .make(codec_params, &Default::default())
         .map_err(|e| AppError::Audio(format!("Failed to create decoder: {}", e)))?;
 
-    let mut samples = Vec::new();
+    let mut decoded_count = 0;
+    let mut converted = Vec::new();
     let track = &mut probed.format;
 
     loop {
@@ -91,167 +148,33 @@ pub fn decode_audio(path: &str) -> Result<Vec<f32>> {
             continue;
         }
 
-        match decoder.decode(&packet) {
+        let block: &[f32] = match decoder.decode(&packet) {
             Ok(decoded) => {
                 use symphonia::core::audio::AudioBufferRef;
+                converted.clear();
                 match decoded {
-                    AudioBufferRef::F32(buf) => samples.extend(buf.chan(0).iter().cloned()),
+                    AudioBufferRef::F32(buf) => converted.extend(buf.chan(0).iter().cloned()),
                     AudioBufferRef::U8(buf) => {
-                        samples.extend(buf.chan(0).iter().map(|&s| s as f32 / 128.0 - 1.0))
+                        converted.extend(buf.chan(0).iter().map(|&s| s as f32 / 128.0 - 1.0))
                     }
                     AudioBufferRef::S16(buf) => {
-                        samples.extend(buf.chan(0).iter().map(|&s| s as f32 / 32768.0))
+                        converted.extend(buf.chan(0).iter().map(|&s| s as f32 / 32768.0))
                     }
                     _ => continue, // Handle other formats if needed
                 }
+                &converted
             }
             Err(_) => continue,
+        };
+        if !block.is_empty() {
+            decoded_count += block.len();
+            on_block(block)?;
         }
     }
 
-    if samples.is_empty() {
+    if decoded_count == 0 {
         return Err(AppError::Audio("No audio samples decoded".to_string()));
     }
 
-    Ok(samples)
-}
-
-/// Generate spectrogram (STFT)
-fn spectrogram(samples: &[f32]) -> Vec<Vec<f32>> {
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(WINDOW_SIZE);
-
-    if samples.len() < WINDOW_SIZE {
-        return Vec::new();
-    }
-
-    let num_frames = (samples.len() - WINDOW_SIZE) / HOP_SIZE;
-    let mut spectrogram = Vec::with_capacity(num_frames);
-
-    // Hanning window
-    let window: Vec<f32> = (0..WINDOW_SIZE)
-        .map(|i| {
-            0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / (WINDOW_SIZE - 1) as f32).cos())
-        })
-        .collect();
-
-    for i in 0..num_frames {
-        let start = i * HOP_SIZE;
-        let end = start + WINDOW_SIZE;
-        let chunk = &samples[start..end];
-
-        let mut buffer: Vec<Complex<f32>> = chunk
-            .iter()
-            .zip(&window)
-            .map(|(&s, &w)| Complex::new(s * w, 0.0))
-            .collect();
-
-        fft.process(&mut buffer);
-
-        // Keep magnitude of first half (Nyquist)
-        let magnitude: Vec<f32> = buffer[0..WINDOW_SIZE / 2]
-            .iter()
-            .map(|c| c.norm())
-            .collect();
-
-        spectrogram.push(magnitude);
-    }
-
-    spectrogram
-}
-
-/// Find peaks in spectrogram (Constellation Map)
-fn find_peaks(spectrogram: &[Vec<f32>]) -> Vec<(usize, usize)> {
-    let rows = spectrogram.len();
-    if rows == 0 {
-        return Vec::new();
-    }
-    let cols = spectrogram[0].len();
-    let mut peaks = Vec::new();
-
-    // Divide into frequency bands to ensure peaks across spectrum
-    // e.g., Low, Mid, High
-    let bands = [(0, 50), (50, 200), (200, 500), (500, cols)];
-
-    for (start_bin, end_bin) in bands {
-        for t in 0..rows {
-            let mut max_val = 0.0;
-            let mut max_freq = 0;
-
-            // Find max in this band for this time frame
-            for f in start_bin..end_bin.min(cols) {
-                if spectrogram[t][f] > max_val {
-                    max_val = spectrogram[t][f];
-                    max_freq = f;
-                }
-            }
-
-            // Simple local maximum check (time axis)
-            // Check if it's a peak compared to neighbors
-            if max_val > 1.0 {
-                // Noise threshold
-                let mut is_peak = true;
-                // Check +/- 2 frames
-                for dt in 1..=2 {
-                    if t >= dt && spectrogram[t - dt][max_freq] > max_val {
-                        is_peak = false;
-                        break;
-                    }
-                    if t + dt < rows && spectrogram[t + dt][max_freq] > max_val {
-                        is_peak = false;
-                        break;
-                    }
-                }
-
-                if is_peak {
-                    peaks.push((t, max_freq));
-                }
-            }
-        }
-    }
-
-    peaks
-}
-
-/// Generate hashes from peaks (Combinatorial Hashing)
-/// Returns: (hash, time_offset)
-pub fn generate_fingerprints(samples: &[f32]) -> Vec<(u32, u32)> {
-    let spec = spectrogram(samples);
-    let mut peaks = find_peaks(&spec);
-    // Sort peaks by time (t) to ensure t2 > t1 in the loop
-    peaks.sort_by_key(|k| k.0);
-
-    let mut fingerprints = Vec::new();
-
-    // Target zone: look ahead in time
-    let target_zone_start = 5; // frames ahead
-    let target_zone_end = 50; // frames ahead
-
-    for i in 0..peaks.len() {
-        let (t1, f1) = peaks[i];
-
-        for j in (i + 1)..peaks.len() {
-            let (t2, f2) = peaks[j];
-            let dt = t2 - t1;
-
-            if dt < target_zone_start {
-                continue;
-            }
-            if dt > target_zone_end {
-                break;
-            } // Peaks are sorted by time usually
-
-            // Hash: [f1: 9 bits] [f2: 9 bits] [dt: 14 bits]
-            // f1, f2 are bin indices. WINDOW=4096, bins=2048.
-            // We care mostly about lower 512 bins (0-2kHz) where music energy is.
-            // Let's mask to 9 bits (511).
-
-            if f1 < 512 && f2 < 512 {
-                let hash = ((f1 as u32) << 23) | ((f2 as u32) << 14) | (dt as u32);
-                fingerprints.push((hash, t1 as u32));
-            }
-        }
-    }
-
-    fingerprints
+    Ok(decoded_count)
 }
@@ -0,0 +1,158 @@
+use crate::cover::store_chroma;
+use crate::error::Result;
+use crate::fingerprint::{duration_ms, fingerprint, load_audio};
+use crate::matcher::best_match;
+use crate::metadata::ExtractedMetadata;
+use crate::storage::{is_unique_violation, Database};
+use crate::types::{DuplicateKind, MatchOptions};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+/// Max hashes of a new song looked up when checking for fingerprint duplicates
+/// Evenly spaced samples keep their relative offsets, so alignment still shows
+const DUPLICATE_SAMPLE_SIZE: usize = 2000;
+/// Fraction of sampled hashes that must align with one song to call it a duplicate
+const DUPLICATE_OVERLAP_RATIO: f32 = 0.3;
+/// Minimum aligned hashes, so very short files aren't flagged on noise
+const DUPLICATE_MIN_SCORE: i64 = 50;
+
+pub enum IndexOutcome {
+    Indexed {
+        song_id: i64,
+        hash_count: usize,
+    },
+    /// The file was linked to an existing song instead of being indexed
+    Duplicate {
+        song_id: i64,
+        kind: DuplicateKind,
+    },
+}
+
+/// Preprocess, fingerprint and store a song file along with its metadata
+/// Copies of songs already in the library are linked rather than indexed again
+pub fn index_song(db: &Database, path: &str, metadata: &ExtractedMetadata) -> Result<IndexOutcome> {
+    let samples = load_audio(path)?;
+    index_samples(db, path, metadata, &samples)
+}
+
+/// Fingerprint and store decoded 16kHz mono samples and their chroma under `path`,
+/// which only has to identify the song uniquely
+pub fn index_samples(
+    db: &Database,
+    path: &str,
+    metadata: &ExtractedMetadata,
+    samples: &[f32],
+) -> Result<IndexOutcome> {
+    // Same decoded audio under another filename
+    let content_hash = content_hash(samples);
+    if let Some(song_id) = db.find_song_by_content_hash(&content_hash)? {
+        return link_duplicate(db, path, song_id, DuplicateKind::ContentHash);
+    }
+
+    let fingerprints = fingerprint(samples, &db.fingerprint_settings());
+
+    // Same recording with a different encoding
+    if let Some(song_id) = find_fingerprint_duplicate(db, &fingerprints)? {
+        return link_duplicate(db, path, song_id, DuplicateKind::Fingerprint);
+    }
+
+    // Fall back to the decoded length when the container reports no duration
+    let mut details = metadata.details.clone();
+    details
+        .duration_ms
+        .get_or_insert_with(|| duration_ms(samples));
+
+    // Save to database
+    let song_id = match db.insert_song(
+        &metadata.title,
+        &metadata.artist,
+        path,
+        &details,
+        &content_hash,
+        &fingerprints,
+        metadata.artwork.as_ref(),
+    ) {
+        Ok(song_id) => song_id,
+        // A copy of the same audio was stored since the check above
+        Err(e) if is_unique_violation(&e) => match db.find_song_by_content_hash(&content_hash)? {
+            Some(song_id) => return link_duplicate(db, path, song_id, DuplicateKind::ContentHash),
+            None => return Err(e),
+        },
+        Err(e) => return Err(e),
+    };
+    store_chroma(db, song_id, samples)?;
+
+    Ok(IndexOutcome::Indexed {
+        song_id,
+        hash_count: fingerprints.len(),
+    })
+}
+
+fn link_duplicate(
+    db: &Database,
+    path: &str,
+    song_id: i64,
+    kind: DuplicateKind,
+) -> Result<IndexOutcome> {
+    db.insert_duplicate(path, song_id, kind)?;
+    Ok(IndexOutcome::Duplicate { song_id, kind })
+}
+
+/// Decode and store the content hash of songs indexed before it was kept
+/// Songs whose files fail to decode, or whose audio another song already has, are
+/// skipped; returns the number stored
+pub fn backfill_content_hash(db: &Database) -> Result<usize> {
+    let songs = db.songs_without_content_hash()?;
+    if songs.is_empty() {
+        return Ok(0);
+    }
+    info!(
+        "Computing content hashes of {} songs for duplicate detection",
+        songs.len()
+    );
+
+    let mut stored = 0;
+    for (song_id, path) in songs {
+        match load_audio(&path) {
+            Ok(samples) => {
+                if db.set_content_hash(song_id, &content_hash(&samples))? {
+                    stored += 1;
+                }
+            }
+            Err(e) => warn!("Skipping content hash of {}: {}", path, e),
+        }
+    }
+    Ok(stored)
+}
+
+/// SHA-256 of the decoded samples, hex encoded
+fn content_hash(samples: &[f32]) -> String {
+    let mut hasher = Sha256::new();
+    for sample in samples {
+        hasher.update(sample.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Look up a sample of the song's hashes and return the existing song they align with, if any
+fn find_fingerprint_duplicate(db: &Database, fingerprints: &[(u32, u32)]) -> Result<Option<i64>> {
+    if fingerprints.is_empty() {
+        return Ok(None);
+    }
+
+    let step = fingerprints.len().div_ceil(DUPLICATE_SAMPLE_SIZE);
+    let sample: Vec<(u32, u32)> = fingerprints.iter().step_by(step).copied().collect();
+
+    // Plain hit counts, so the score stays comparable to the sample size
+    let matches = db.find_matches(&sample, &MatchOptions::default())?.matches;
+    let Some((song_id, score)) = best_match(&matches, db.fingerprint_settings().scheme) else {
+        return Ok(None);
+    };
+
+    let overlap = score as f32 / sample.len() as f32;
+    if score >= DUPLICATE_MIN_SCORE && overlap >= DUPLICATE_OVERLAP_RATIO {
+        Ok(Some(song_id))
+    } else {
+        Ok(None)
+    }
+}
@@ -1,6 +1,22 @@
 pub mod api;
+pub mod artwork;
+pub mod augment;
+pub mod batch;
+pub mod config;
+pub mod cover;
 pub mod error;
+pub mod explain;
 pub mod fingerprint;
+pub mod indexer;
+pub mod matcher;
+pub mod metadata;
+pub mod recognizer;
 pub mod storage;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod timeline;
+pub mod tuning;
 pub mod types;
+pub mod visualize;
 pub mod watcher;
+pub mod wire;
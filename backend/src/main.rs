@@ -1,18 +1,17 @@
-pub mod api;
-pub mod error;
-pub mod fingerprint;
-pub mod storage;
-pub mod types;
-pub mod watcher;
-
-use crate::api::{create_router, AppState};
-use crate::error::{AppError, Result};
-use crate::storage::Database;
+use sonica_backend::api::{create_router, AppState};
+use sonica_backend::config::Config;
+use sonica_backend::cover::backfill_chroma;
+use sonica_backend::error::{AppError, Result};
+use sonica_backend::fingerprint::is_audio_file;
+use sonica_backend::indexer::{self, backfill_content_hash, IndexOutcome};
+use sonica_backend::metadata::{extract_metadata, ExtractedMetadata};
+use sonica_backend::storage::Database;
+use sonica_backend::watcher;
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::fs as tokio_fs;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -31,15 +30,45 @@ async fn main() -> Result<()> {
 
     info!("Starting Sonica Backend (Shazam Engine)...");
 
+    // Load config
+    let config_path = Config::path();
+    let config = Arc::new(Config::load(&config_path)?);
+    info!(
+        "Loaded config from {} (match threshold: {})",
+        config_path, config.match_threshold
+    );
+
     // Initialize database
     let db = Arc::new(Database::new("songs.db")?);
     let song_count = db.get_all_songs()?.len();
     info!("Database initialized with {} songs", song_count);
+    if db.adopt_fingerprint_settings(&config.fingerprint)? != config.fingerprint {
+        warn!(
+            "Config fingerprint settings differ from the ones songs.db was built with; \
+             keeping the index's until it's rebuilt with reindex"
+        );
+    }
 
     // Load existing songs and scan songs/ directory
     info!("Scanning songs...");
     load_and_process_songs(&db).await?;
 
+    // Songs indexed before chroma was stored can't be found in cover mode until it's computed
+    let db_backfill = Arc::clone(&db);
+    tokio::task::spawn_blocking(move || match backfill_chroma(&db_backfill) {
+        Ok(0) => {}
+        Ok(stored) => info!("Stored chroma of {} songs", stored),
+        Err(e) => error!("Chroma backfill failed: {}", e),
+    });
+
+    // Songs indexed before content hashes were stored aren't found as duplicates until then
+    let db_backfill = Arc::clone(&db);
+    tokio::task::spawn_blocking(move || match backfill_content_hash(&db_backfill) {
+        Ok(0) => {}
+        Ok(stored) => info!("Stored content hashes of {} songs", stored),
+        Err(e) => error!("Content hash backfill failed: {}", e),
+    });
+
     // Start file watcher
     let db_watcher = Arc::clone(&db);
     let watch_handler: Arc<dyn Fn(String) + Send + Sync> = Arc::new(move |path: String| {
@@ -50,7 +79,7 @@ async fn main() -> Result<()> {
             info!("New file detected: {}", path_clone);
 
             // Extract metadata
-            let (title, artist) = extract_metadata(Path::new(&path_clone));
+            let metadata = extract_metadata(Path::new(&path_clone));
 
             // Check if already processed
             if db.song_exists_by_path(&path_clone).unwrap_or(false) {
@@ -59,7 +88,7 @@ async fn main() -> Result<()> {
             }
 
             // Process the song
-            if let Err(e) = process_song(&Arc::clone(&db), &path_clone, &title, &artist).await {
+            if let Err(e) = process_song(&Arc::clone(&db), &path_clone, &metadata).await {
                 error!("Error processing song {}: {}", path_clone, e);
             } else {
                 info!("Successfully processed: {}", path_clone);
@@ -73,6 +102,7 @@ async fn main() -> Result<()> {
     // Create app state
     let app_state = AppState {
         db: Arc::clone(&db),
+        config,
     };
 
     // Create router with logging middleware
@@ -165,12 +195,12 @@ async fn load_and_process_songs(db: &Arc<Database>) -> Result<()> {
             }
 
             // Extract metadata
-            let (title, artist) = extract_metadata(Path::new(path));
+            let metadata = extract_metadata(Path::new(path));
 
             // Process synchronously (we're in rayon thread)
             let db_clone = Arc::clone(&db_arc);
             handle
-                .block_on(process_song(&db_clone, path, &title, &artist))
+                .block_on(process_song(&db_clone, path, &metadata))
                 .map(|_| ())
                 .map_err(|e| {
                     error!("Error processing {}: {}", path, e);
@@ -184,70 +214,14 @@ async fn load_and_process_songs(db: &Arc<Database>) -> Result<()> {
     Ok(())
 }
 
-async fn process_song(db: &Arc<Database>, path: &str, title: &str, artist: &str) -> Result<()> {
-    // Preprocess with FFmpeg
-    let temp_output = format!("temp/{}_processed.wav", uuid::Uuid::new_v4());
-
-    fingerprint::preprocess_audio(path, &temp_output)?;
-
-    // Decode and Fingerprint
-    let samples = fingerprint::decode_audio(&temp_output)?;
-    let fingerprints = fingerprint::generate_fingerprints(&samples);
-
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_output);
-
-    // Save to database
-    db.insert_song(title, artist, path, &fingerprints)?;
-
-    Ok(())
-}
-
-fn extract_metadata(path: &Path) -> (String, String) {
-    use lofty::{prelude::*, read_from_path};
-
-    let mut title = String::from("Unknown");
-    let mut artist = String::from("Unknown");
-
-    // Try to read tags
-    if let Ok(tagged_file) = read_from_path(path) {
-        if let Some(tag) = tagged_file
-            .primary_tag()
-            .or_else(|| tagged_file.first_tag())
-        {
-            if let Some(t) = tag.title() {
-                if !t.trim().is_empty() {
-                    title = t.to_string();
-                }
-            }
-            if let Some(a) = tag.artist() {
-                if !a.trim().is_empty() {
-                    artist = a.to_string();
-                }
-            }
-        }
-    }
-
-    // Fallback to filename if title is still unknown
-    if title == "Unknown" {
-        title = path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("Unknown")
-            .to_string();
-    }
-
-    (title, artist)
-}
-
-fn is_audio_file(path: &Path) -> bool {
-    if let Some(ext) = path.extension() {
-        let ext_lower = ext.to_string_lossy().to_lowercase();
-        matches!(
-            ext_lower.as_str(),
-            "mp3" | "wav" | "flac" | "m4a" | "aac" | "ogg" | "opus" | "wma"
-        )
-    } else {
-        false
+async fn process_song(db: &Arc<Database>, path: &str, metadata: &ExtractedMetadata) -> Result<()> {
+    if let IndexOutcome::Duplicate { song_id, kind } = indexer::index_song(db, path, metadata)? {
+        info!(
+            "{} is a duplicate of song {} ({}), linked instead of indexed",
+            path,
+            song_id,
+            kind.as_str()
+        );
     }
+    Ok(())
 }
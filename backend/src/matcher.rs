@@ -0,0 +1,334 @@
+use crate::fingerprint::HashScheme;
+use crate::types::Matches;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Default threshold for a match, used when the config doesn't set one
+/// Need at least X matching points aligned in time
+pub const DEFAULT_MATCH_THRESHOLD: i64 = 8;
+
+/// Time scales tried for triplet hashes, as song frames per query frame:
+/// queries slowed down to 0.7x or sped up to 1.4x
+const MIN_TIME_SCALE: f64 = 0.7;
+const MAX_TIME_SCALE: f64 = 1.4;
+/// Frames a hit may be off its scaled offset and still count: time-stretched peaks
+/// land on whole frames in both the song and the query
+const SCALED_TOLERANCE: i64 = 1;
+
+/// How a query lines up with a song: song frame = time_scale * query frame + offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    /// Song frame lining up with the start of the query
+    pub offset: i64,
+    /// Song frames per query frame, above 1 for sped-up queries; 1 for pair hashes
+    pub time_scale: f64,
+    /// Weighted hits on the alignment
+    pub score: i64,
+    /// Frames a hit may be off the alignment
+    tolerance: i64,
+}
+
+impl Alignment {
+    /// Whether a hit at these offsets is on the alignment
+    pub fn contains(&self, db_offset: u32, query_offset: u32) -> bool {
+        (scaled_offset(db_offset, query_offset, self.time_scale) - self.offset).abs()
+            <= self.tolerance
+    }
+}
+
+/// Score a candidate song by the largest bin of its offset histogram:
+/// the number of matching hashes that agree on one relative time offset,
+/// each counted by its weight (1 unless IDF weighting is on)
+pub fn score_offsets(offsets: &[(u32, u32, f32)], scheme: HashScheme) -> i64 {
+    align(offsets, scheme).score
+}
+
+/// Best alignment of a candidate's hits for the index's hash scheme
+/// Pair hashes line up on one offset; triplet hashes also on a time scale
+pub fn align(offsets: &[(u32, u32, f32)], scheme: HashScheme) -> Alignment {
+    match scheme {
+        HashScheme::Pairs => {
+            let (offset, score) = align_offsets(offsets);
+            Alignment {
+                offset,
+                time_scale: 1.0,
+                score,
+                tolerance: 0,
+            }
+        }
+        HashScheme::Triplets => align_scaled(offsets),
+    }
+}
+
+/// Search time scales for the one whose offset histogram has the largest bin,
+/// then refine it by a least-squares fit of the hits on it
+fn align_scaled(offsets: &[(u32, u32, f32)]) -> Alignment {
+    let searched = search_time_scales(offsets);
+    let refined = fit_alignment(offsets, &searched);
+    if refined.score >= searched.score {
+        refined
+    } else {
+        searched
+    }
+}
+
+/// Scales are tried one query span apart outwards from 1, so the best one puts
+/// the hits at both ends of the query within half a frame of the true alignment
+fn search_time_scales(offsets: &[(u32, u32, f32)]) -> Alignment {
+    let mut best = Alignment {
+        offset: 0,
+        time_scale: 1.0,
+        score: 0,
+        tolerance: SCALED_TOLERANCE,
+    };
+    let first = offsets.iter().map(|&(_, q, _)| q).min().unwrap_or(0);
+    let last = offsets.iter().map(|&(_, q, _)| q).max().unwrap_or(0);
+    let step = 1.0 / (last - first).max(1) as f64;
+
+    let mut best_votes = 0.0;
+    let mut keys: Vec<(i64, f32)> = Vec::with_capacity(offsets.len());
+    let steps = ((MAX_TIME_SCALE - 1.0).max(1.0 - MIN_TIME_SCALE) / step).ceil() as i64;
+    for i in 0..=2 * steps {
+        // 1, 1 + step, 1 - step, 1 + 2 step...
+        let signed = if i % 2 == 1 { (i + 1) / 2 } else { -(i / 2) };
+        // The last step each way stops at the limit, so the limits themselves are tried
+        let time_scale = (1.0 + signed as f64 * step).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+
+        keys.clear();
+        keys.extend(
+            offsets
+                .iter()
+                .map(|&(db, q, weight)| (scaled_offset(db, q, time_scale), weight)),
+        );
+        keys.sort_unstable_by_key(|&(offset, _)| offset);
+
+        // Sliding window of offsets within the tolerance of its centre
+        let (mut start, mut end, mut votes) = (0, 0, 0.0);
+        for centre in 0..keys.len() {
+            let offset = keys[centre].0;
+            if centre > 0 && keys[centre - 1].0 == offset {
+                continue;
+            }
+            while end < keys.len() && keys[end].0 <= offset + SCALED_TOLERANCE {
+                votes += keys[end].1;
+                end += 1;
+            }
+            while keys[start].0 < offset - SCALED_TOLERANCE {
+                votes -= keys[start].1;
+                start += 1;
+            }
+            // Ties go to the scale nearest 1, tried first, then the earlier offset
+            if votes > best_votes {
+                best_votes = votes;
+                best.offset = offset;
+                best.time_scale = time_scale;
+            }
+        }
+    }
+
+    best.score = best_votes.round() as i64;
+    best
+}
+
+/// The line through the hits on `alignment` by weighted least squares, and its score
+/// With the tolerance, a range of scales fits the hits equally well; the fit takes
+/// the middle of it, which is the estimate of the query's speed
+fn fit_alignment(offsets: &[(u32, u32, f32)], alignment: &Alignment) -> Alignment {
+    let inliers = offsets
+        .iter()
+        .filter(|&&(db, q, _)| alignment.contains(db, q));
+    let (mut sum_w, mut sum_q, mut sum_db, mut sum_qq, mut sum_qdb) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for &(db, q, weight) in inliers {
+        let (w, q, db) = (weight as f64, q as f64, db as f64);
+        sum_w += w;
+        sum_q += w * q;
+        sum_db += w * db;
+        sum_qq += w * q * q;
+        sum_qdb += w * q * db;
+    }
+    let variance = sum_w * sum_qq - sum_q * sum_q;
+    if sum_w == 0.0 || variance <= f64::EPSILON {
+        return *alignment;
+    }
+    let time_scale =
+        ((sum_w * sum_qdb - sum_q * sum_db) / variance).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    let mut fitted = Alignment {
+        offset: ((sum_db - time_scale * sum_q) / sum_w).round() as i64,
+        time_scale,
+        ..*alignment
+    };
+    let votes: f32 = offsets
+        .iter()
+        .filter(|&&(db, q, _)| fitted.contains(db, q))
+        .map(|&(_, _, weight)| weight)
+        .sum();
+    fitted.score = votes.round() as i64;
+    fitted
+}
+
+/// Song frame lining up with the start of the query if this hit is on the alignment
+fn scaled_offset(db_offset: u32, query_offset: u32, time_scale: f64) -> i64 {
+    (db_offset as f64 - time_scale * query_offset as f64).round() as i64
+}
+
+/// Largest bin of the offset histogram as (relative_offset, score)
+/// relative_offset is the song frame that lines up with the start of the query
+pub fn align_offsets(offsets: &[(u32, u32, f32)]) -> (i64, i64) {
+    let mut histogram = HashMap::new();
+    let mut best_offset = 0;
+    let mut max_votes: f32 = 0.0;
+
+    for &(db_offset, query_offset, weight) in offsets {
+        // relative_offset = db_offset - query_offset
+        // We use wrapping arithmetic or offset to avoid negative numbers if needed,
+        // but here we can just use i64.
+        let relative_offset = (db_offset as i64) - (query_offset as i64);
+        let votes = histogram.entry(relative_offset).or_insert(0.0);
+        *votes += weight;
+        // Ties go to the earlier offset so the result doesn't depend on input order
+        if *votes > max_votes || (*votes == max_votes && relative_offset < best_offset) {
+            max_votes = *votes;
+            best_offset = relative_offset;
+        }
+    }
+
+    (best_offset, max_votes.round() as i64)
+}
+
+/// The `top` largest bins of the offset histogram at `time_scale` as (relative_offset, votes),
+/// largest first, ties to the earlier offset like `align_offsets`
+pub fn offset_histogram(
+    offsets: &[(u32, u32, f32)],
+    time_scale: f64,
+    top: usize,
+) -> Vec<(i64, f32)> {
+    let mut histogram: HashMap<i64, f32> = HashMap::new();
+    for &(db_offset, query_offset, weight) in offsets {
+        *histogram
+            .entry(scaled_offset(db_offset, query_offset, time_scale))
+            .or_insert(0.0) += weight;
+    }
+
+    let mut bins: Vec<(i64, f32)> = histogram.into_iter().collect();
+    bins.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    bins.truncate(top);
+    bins
+}
+
+/// Histogram of Offsets Algorithm
+/// Returns (song_id, score) of the best scoring candidate from `find_matches`
+pub fn best_match(matches: &Matches, scheme: HashScheme) -> Option<(i64, i64)> {
+    matches
+        .par_iter()
+        .map(|(&song_id, offsets)| (song_id, score_offsets(offsets, scheme)))
+        .max_by_key(|&(song_id, score)| (score, std::cmp::Reverse(song_id)))
+}
+
+/// Candidates from `find_matches` ordered by score, best first, truncated to `limit`
+/// Candidates are scored in parallel on the rayon pool
+pub fn rank_matches(matches: &Matches, scheme: HashScheme, limit: usize) -> Vec<(i64, i64)> {
+    rank_matches_until(matches, scheme, limit, None).0
+}
+
+/// `rank_matches`, leaving candidates unscored once `deadline` passes
+/// Returns the candidates scored so far and whether any were left out
+pub fn rank_matches_until(
+    matches: &Matches,
+    scheme: HashScheme,
+    limit: usize,
+    deadline: Option<Instant>,
+) -> (Vec<(i64, i64)>, bool) {
+    let scored: Vec<Option<(i64, i64)>> = matches
+        .par_iter()
+        .map(|(&song_id, offsets)| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+            Some((song_id, score_offsets(offsets, scheme)))
+        })
+        .collect();
+    let truncated = scored.iter().any(Option::is_none);
+
+    let mut ranked: Vec<(i64, i64)> = scored.into_iter().flatten().collect();
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    (ranked, truncated)
+}
+
+/// Normalize a raw score to 0-1 range (frontend will multiply by 100 for percentage)
+#[allow(clippy::manual_clamp)]
+pub fn confidence(score: i64) -> f32 {
+    // The score is the count of matching fingerprint points aligned in time
+    // Use a logarithmic scale to map to 0-1 range more naturally
+    // Formula: normalized = 1 - exp(-score / scale_factor)
+    // This ensures scores are always between 0 and 1
+
+    // Scale factor: higher values = slower growth toward 1.0
+    // For score of 25, we want ~0.85, so: 1 - exp(-25/15) ≈ 0.81
+    // For score of 40, we want ~0.95, so: 1 - exp(-40/15) ≈ 0.93
+    let scale_factor = 15.0;
+    let normalized_score = 1.0 - (-(score as f32) / scale_factor).exp();
+
+    // Clamp to ensure it's always between 0 and 1
+    normalized_score.min(1.0).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hits of a query on a song at `time_scale` and `offset`, plus unaligned ones
+    fn hits(time_scale: f64, offset: i64) -> Vec<(u32, u32, f32)> {
+        let aligned = (0..60u32).map(|q| {
+            let db = (time_scale * q as f64 + offset as f64).round() as u32;
+            (db, q, 1.0)
+        });
+        let scattered = (0..40u32).map(|i| ((i * 7919) % 500, (i * 31) % 60, 1.0));
+        aligned.chain(scattered).collect()
+    }
+
+    #[test]
+    fn pairs_align_on_a_plain_offset() {
+        let alignment = align(&hits(1.0, 120), HashScheme::Pairs);
+        assert_eq!(alignment.offset, 120);
+        assert_eq!(alignment.time_scale, 1.0);
+        assert!(alignment.score >= 60);
+        assert!(alignment.contains(130, 10) && !alignment.contains(131, 10));
+    }
+
+    #[test]
+    fn triplets_estimate_the_time_scale() {
+        for time_scale in [0.7, 0.8, 1.0, 1.25, 1.4] {
+            let alignment = align(&hits(time_scale, 200), HashScheme::Triplets);
+            // At 1.4 a scattered hit outvotes the fit, leaving the estimate one search step off
+            assert!(
+                (alignment.time_scale - time_scale).abs() < 0.02,
+                "{} estimated as {}",
+                time_scale,
+                alignment.time_scale
+            );
+            assert!((alignment.offset - 200).abs() <= 1);
+            assert!(alignment.score >= 60);
+        }
+    }
+
+    #[test]
+    fn ranking_stops_scoring_at_the_deadline() {
+        let matches: Matches = (1..=3).map(|song_id| (song_id, hits(1.0, 100))).collect();
+
+        let (ranked, truncated) = rank_matches_until(&matches, HashScheme::Pairs, 5, None);
+        assert_eq!(ranked.len(), 3);
+        assert!(!truncated);
+
+        let (ranked, truncated) =
+            rank_matches_until(&matches, HashScheme::Pairs, 5, Some(Instant::now()));
+        assert!(ranked.is_empty());
+        assert!(truncated);
+    }
+
+    #[test]
+    fn pairs_miss_a_scaled_query() {
+        assert!(score_offsets(&hits(1.25, 200), HashScheme::Pairs) < 10);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::artwork;
+use crate::error::{AppError, Result};
+use crate::types::{Artwork, TrackDetails};
+use lofty::config::WriteOptions;
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+use lofty::read_from_path;
+use lofty::tag::Tag;
+use std::path::Path;
+
+pub struct ExtractedMetadata {
+    pub title: String,
+    pub artist: String,
+    pub details: TrackDetails,
+    pub artwork: Option<Artwork>,
+}
+
+/// Read title, artist and the remaining tag details from the file,
+/// falling back to the filename for the title
+pub fn extract_metadata(path: &Path) -> ExtractedMetadata {
+    let mut title = String::from("Unknown");
+    let mut artist = String::from("Unknown");
+    let mut details = TrackDetails::default();
+    let mut artwork = None;
+
+    // Try to read tags
+    if let Ok(tagged_file) = read_from_path(path) {
+        let duration = tagged_file.properties().duration();
+        if !duration.is_zero() {
+            details.duration_ms = Some(duration.as_millis() as u64);
+        }
+
+        if let Some(tag) = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())
+        {
+            if let Some(t) = non_empty(tag.title().as_deref()) {
+                title = t;
+            }
+            if let Some(a) = non_empty(tag.artist().as_deref()) {
+                artist = a;
+            }
+
+            details.album = non_empty(tag.album().as_deref());
+            details.album_artist = non_empty(tag.get_string(&ItemKey::AlbumArtist));
+            details.track_number = tag.track();
+            details.disc_number = tag.disk();
+            details.year = tag.year();
+            details.genre = non_empty(tag.genre().as_deref());
+            details.isrc = non_empty(tag.get_string(&ItemKey::Isrc));
+
+            // Prefer the front cover, otherwise take whatever picture comes first
+            let pictures = tag.pictures();
+            artwork = pictures
+                .iter()
+                .find(|p| p.pic_type() == PictureType::CoverFront)
+                .or_else(|| pictures.first())
+                .and_then(|p| artwork::from_picture_data(p.data()));
+        }
+    }
+
+    // Fallback to filename if title is still unknown
+    if title == "Unknown" {
+        title = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+    }
+
+    ExtractedMetadata {
+        title,
+        artist,
+        details,
+        artwork,
+    }
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Write title and/or artist back into the audio file's tags
+/// Creates a tag of the format's primary type if the file has none
+pub fn write_tags(path: &Path, title: Option<&str>, artist: Option<&str>) -> Result<()> {
+    let mut tagged_file = read_from_path(path)
+        .map_err(|e| AppError::Metadata(format!("Failed to read tags: {}", e)))?;
+
+    if tagged_file.primary_tag().is_none() && tagged_file.first_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+
+    let tag = if tagged_file.primary_tag().is_some() {
+        tagged_file.primary_tag_mut()
+    } else {
+        tagged_file.first_tag_mut()
+    }
+    .ok_or_else(|| AppError::Metadata("No writable tag found".to_string()))?;
+
+    if let Some(title) = title {
+        tag.set_title(title.to_string());
+    }
+    if let Some(artist) = artist {
+        tag.set_artist(artist.to_string());
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| AppError::Metadata(format!("Failed to write tags: {}", e)))?;
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::fingerprint::{decode_audio, duration_ms, fingerprint, preprocess_audio};
+use crate::matcher::{confidence, rank_matches_until};
+use crate::storage::Database;
+use crate::types::{MatchOptions, SongMetadata};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Number of runner-up candidates kept for the history log and feedback analysis
+const CANDIDATES_KEPT: usize = 5;
+
+/// Outcome of matching one query clip against the library
+pub struct Recognition {
+    pub query_duration_ms: u64,
+    pub hash_count: usize,
+    /// Best scoring candidates as (song_id, raw score), best first,
+    /// including those below the threshold
+    pub candidates: Vec<(i64, i64)>,
+    /// Confidence of the top candidate (0-1)
+    pub confidence: f32,
+    /// Metadata of the top candidate if it passed the threshold
+    pub song: Option<SongMetadata>,
+    /// The deadline passed during lookup or scoring, so candidates come from part of the
+    /// query or part of the library
+    pub deadline_exceeded: bool,
+}
+
+impl Recognition {
+    pub fn top_candidate(&self) -> Option<(i64, i64)> {
+        self.candidates.first().copied()
+    }
+
+    pub fn raw_score(&self) -> i64 {
+        self.top_candidate().map_or(0, |(_, score)| score)
+    }
+}
+
+/// Preprocess an uploaded clip with FFmpeg, then fingerprint and match it
+/// Once `deadline` passes, matching stops and the best result so far is returned
+pub fn recognize_audio(
+    db: &Database,
+    config: &Config,
+    audio_data: &[u8],
+    deadline: Option<Instant>,
+) -> Result<Recognition> {
+    let samples = decode_upload(audio_data)?;
+    recognize_samples(db, config, &samples, deadline)
+}
+
+/// Convert uploaded audio of any format to 16kHz mono samples with FFmpeg
+pub fn decode_upload(audio_data: &[u8]) -> Result<Vec<f32>> {
+    // Save to temp file
+    std::fs::create_dir_all("temp")?;
+    let temp_id = Uuid::new_v4();
+    let temp_input = format!("temp/{}", temp_id);
+    let temp_output = format!("temp/{}_processed.wav", temp_id);
+
+    std::fs::write(&temp_input, audio_data)?;
+
+    // Preprocess with FFmpeg, then decode
+    let samples =
+        preprocess_audio(&temp_input, &temp_output).and_then(|_| decode_audio(&temp_output));
+
+    // Clean up temp files
+    let _ = std::fs::remove_file(&temp_input);
+    let _ = std::fs::remove_file(&temp_output);
+
+    samples
+}
+
+/// Fingerprint decoded 16kHz mono samples with the index's settings and match them
+pub fn recognize_samples(
+    db: &Database,
+    config: &Config,
+    samples: &[f32],
+    deadline: Option<Instant>,
+) -> Result<Recognition> {
+    let fingerprints = fingerprint(samples, &db.fingerprint_settings());
+    recognize_fingerprints(db, config, &fingerprints, duration_ms(samples), deadline)
+}
+
+/// Match query fingerprints against the library using the offset histogram,
+/// searching time scales too if the index hashes triplets
+pub fn recognize_fingerprints(
+    db: &Database,
+    config: &Config,
+    fingerprints: &[(u32, u32)],
+    query_duration_ms: u64,
+    deadline: Option<Instant>,
+) -> Result<Recognition> {
+    let mut recognition = Recognition {
+        query_duration_ms,
+        hash_count: fingerprints.len(),
+        candidates: Vec::new(),
+        confidence: 0.0,
+        song: None,
+        deadline_exceeded: false,
+    };
+
+    if fingerprints.is_empty() {
+        return Ok(recognition);
+    }
+
+    // Find matches in DB
+    let options = MatchOptions {
+        deadline,
+        ..config.match_options()
+    };
+    let lookup = db.find_matches(fingerprints, &options)?;
+    let matches = lookup.matches;
+
+    // Histogram of Offsets Algorithm
+    let scheme = db.fingerprint_settings().scheme;
+    let (candidates, scoring_truncated) =
+        rank_matches_until(&matches, scheme, CANDIDATES_KEPT, deadline);
+    recognition.candidates = candidates;
+    recognition.deadline_exceeded = lookup.truncated || scoring_truncated;
+    let Some((best_song_id, best_score)) = recognition.top_candidate() else {
+        return Ok(recognition);
+    };
+
+    recognition.confidence = confidence(best_score);
+
+    if best_score > config.match_threshold {
+        recognition.song = db.get_song_metadata(best_song_id)?;
+    }
+
+    Ok(recognition)
+}
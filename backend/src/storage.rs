@@ -1,19 +1,73 @@
-use crate::error::Result;
-use rusqlite::{params, Connection};
-use std::collections::HashMap;
-use std::sync::Mutex;
+use crate::config::MAX_CANDIDATES_LIMIT;
+use crate::error::{AppError, Result};
+use crate::fingerprint::FingerprintSettings;
+use crate::types::{
+    Artwork, DuplicateKind, DuplicateSong, HashCount, LabelledScore, LatencyPercentiles,
+    LibraryStats, Lookup, MatchOptions, Matches, MisidentifiedSong, NewRecognition,
+    RecognitionListQuery, RecognitionRecord, RecognitionStats, RecognitionStatsQuery, SongCount,
+    SongListQuery, SongMetadata, SongSort, SortOrder, SourceFailures, StatsBucket, TimeBucket,
+    TrackDetails, Verdict,
+};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+const SONG_COLUMNS: &str = "id, title, artist, path, created_at, album, album_artist, \
+     track_number, disc_number, year, genre, duration_ms, isrc";
+
+const RECOGNITION_COLUMNS: &str = "id, created_at, source, client_id, query_duration_ms, \
+     hash_count, top_song_id, raw_score, confidence, latency_ms, matched, verdict, correct_song_id";
+const RECOGNITION_TABLES: &str =
+    "recognitions LEFT JOIN recognition_feedback ON recognition_feedback.recognition_id = id";
+
+/// `index_settings` key of the JSON `FingerprintSettings` the fingerprints were computed with
+const FINGERPRINT_SETTINGS_KEY: &str = "fingerprint";
+
+/// Columns added to `songs` after the initial schema, with their SQL types
+/// Databases created before they existed are migrated on startup, like those below
+const ADDED_SONG_COLUMNS: [(&str, &str); 9] = [
+    ("album", "TEXT"),
+    ("album_artist", "TEXT"),
+    ("track_number", "INTEGER"),
+    ("disc_number", "INTEGER"),
+    ("year", "INTEGER"),
+    ("genre", "TEXT"),
+    ("duration_ms", "INTEGER"),
+    ("isrc", "TEXT"),
+    ("content_hash", "TEXT"),
+];
+
+/// Query hashes per `hash_stats` lookup, well under SQLite's limit on bound parameters
+const HASH_STATS_CHUNK: usize = 500;
+
+/// Columns added to `recognitions` after the initial schema, with their SQL types
+const ADDED_RECOGNITION_COLUMNS: [(&str, &str); 1] =
+    [("deadline_exceeded", "INTEGER NOT NULL DEFAULT 0")];
 
 pub struct Database {
     conn: Mutex<Connection>,
+    /// Cached from `index_settings`, read by every index and query
+    fingerprint_settings: RwLock<FingerprintSettings>,
 }
 
 impl Database {
     pub fn new(db_path: &str) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
+        Self::from_connection(Connection::open(db_path)?)
+    }
+
+    /// A private database that lives until it's dropped, for tests and one-off tools
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
         let db = Database {
             conn: Mutex::new(conn),
+            fingerprint_settings: RwLock::new(FingerprintSettings::default()),
         };
         db.create_tables()?;
+        *db.fingerprint_settings.write().unwrap() = db.load_fingerprint_settings()?;
         Ok(db)
     }
 
@@ -27,11 +81,51 @@ impl Database {
                 title TEXT,
                 artist TEXT,
                 path TEXT UNIQUE,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                album TEXT,
+                album_artist TEXT,
+                track_number INTEGER,
+                disc_number INTEGER,
+                year INTEGER,
+                genre TEXT,
+                duration_ms INTEGER,
+                isrc TEXT,
+                content_hash TEXT
             )",
             [],
         )?;
 
+        // Add columns missing from older databases
+        add_missing_columns(&conn, "songs", &ADDED_SONG_COLUMNS)?;
+
+        // Full-text index over title/artist/album, kept in sync by triggers
+        let fts_exists = conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'songs_fts'")?
+            .exists([])?;
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS songs_fts USING fts5(
+                title, artist, album, content='songs', content_rowid='id'
+            );
+            CREATE TRIGGER IF NOT EXISTS songs_fts_insert AFTER INSERT ON songs BEGIN
+                INSERT INTO songs_fts(rowid, title, artist, album)
+                VALUES (new.id, new.title, new.artist, new.album);
+            END;
+            CREATE TRIGGER IF NOT EXISTS songs_fts_delete AFTER DELETE ON songs BEGIN
+                INSERT INTO songs_fts(songs_fts, rowid, title, artist, album)
+                VALUES ('delete', old.id, old.title, old.artist, old.album);
+            END;
+            CREATE TRIGGER IF NOT EXISTS songs_fts_update AFTER UPDATE ON songs BEGIN
+                INSERT INTO songs_fts(songs_fts, rowid, title, artist, album)
+                VALUES ('delete', old.id, old.title, old.artist, old.album);
+                INSERT INTO songs_fts(rowid, title, artist, album)
+                VALUES (new.id, new.title, new.artist, new.album);
+            END;",
+        )?;
+        if !fts_exists {
+            // Index songs that were added before the FTS table existed
+            conn.execute("INSERT INTO songs_fts(songs_fts) VALUES ('rebuild')", [])?;
+        }
+
         // Fingerprints table (Hashes)
         // hash: 32-bit integer (freq + time delta)
         // song_id: Foreign key
@@ -46,29 +140,235 @@ impl Database {
             [],
         )?;
 
+        // SHA-256 of the decoded audio, used to detect re-indexed copies
+        // Unique so two copies indexed at once can't both be stored; older databases had a
+        // plain index, and any copies they let through keep their hash on the first song only
+        conn.execute("DROP INDEX IF EXISTS idx_songs_content_hash", [])?;
+        conn.execute(
+            "UPDATE songs SET content_hash = NULL
+             WHERE content_hash IS NOT NULL AND id NOT IN (
+                 SELECT MIN(id) FROM songs WHERE content_hash IS NOT NULL GROUP BY content_hash
+             )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_songs_unique_content_hash ON songs(content_hash)",
+            [],
+        )?;
+
+        // Files linked to an existing song instead of being indexed again
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS song_duplicates (
+                path TEXT PRIMARY KEY,
+                song_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(song_id) REFERENCES songs(id)
+            )",
+            [],
+        )?;
+
+        // Recognition history, one row per query
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS recognitions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                source TEXT NOT NULL,
+                client_id TEXT,
+                query_duration_ms INTEGER NOT NULL,
+                hash_count INTEGER NOT NULL,
+                top_song_id INTEGER,
+                raw_score INTEGER NOT NULL,
+                confidence REAL NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                matched INTEGER NOT NULL,
+                deadline_exceeded INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        add_missing_columns(&conn, "recognitions", &ADDED_RECOGNITION_COLUMNS)?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_recognitions_created_at ON recognitions(created_at)",
+            [],
+        )?;
+
+        // Top candidates of each recognition with their raw histogram scores
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS recognition_candidates (
+                recognition_id INTEGER NOT NULL,
+                rank INTEGER NOT NULL,
+                song_id INTEGER NOT NULL,
+                score INTEGER NOT NULL,
+                PRIMARY KEY(recognition_id, rank),
+                FOREIGN KEY(recognition_id) REFERENCES recognitions(id)
+            )",
+            [],
+        )?;
+
+        // User feedback on recognition results, labelled data for threshold tuning
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS recognition_feedback (
+                recognition_id INTEGER PRIMARY KEY,
+                verdict TEXT NOT NULL,
+                correct_song_id INTEGER,
+                feedback_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(recognition_id) REFERENCES recognitions(id)
+            )",
+            [],
+        )?;
+
+        // Embedded cover art, one image per song
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS artwork (
+                song_id INTEGER PRIMARY KEY,
+                mime_type TEXT NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                data BLOB NOT NULL,
+                FOREIGN KEY(song_id) REFERENCES songs(id)
+            )",
+            [],
+        )?;
+
+        // Resized artwork served for `?size=`, so each size is only resized once
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS artwork_thumbnails (
+                song_id INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                mime_type TEXT NOT NULL,
+                data BLOB NOT NULL,
+                PRIMARY KEY(song_id, size),
+                FOREIGN KEY(song_id) REFERENCES songs(id)
+            )",
+            [],
+        )?;
+
+        // Chroma sequence per song for cover identification, 12 bytes per step
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS song_chroma (
+                song_id INTEGER PRIMARY KEY,
+                data BLOB NOT NULL,
+                FOREIGN KEY(song_id) REFERENCES songs(id)
+            )",
+            [],
+        )?;
+
+        // Settings the index was built with, as JSON by key
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS index_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         // Index for fast lookups
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_fingerprints_hash ON fingerprints(hash)",
             [],
         )?;
 
+        // Document frequency of each hash: the number of songs containing it
+        let hash_stats_exists = conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'hash_stats'")?
+            .exists([])?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hash_stats (
+                hash INTEGER PRIMARY KEY,
+                song_count INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        if !hash_stats_exists {
+            // Count hashes indexed before the table existed
+            conn.execute(
+                "INSERT INTO hash_stats (hash, song_count)
+                 SELECT hash, COUNT(DISTINCT song_id) FROM fingerprints GROUP BY hash",
+                [],
+            )?;
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_hash_stats_song_count ON hash_stats(song_count)",
+            [],
+        )?;
+
         Ok(())
     }
 
+    /// Stored settings, or the defaults for indexes built before settings were stored
+    fn load_fingerprint_settings(&self) -> Result<FingerprintSettings> {
+        let conn = self.conn.lock().unwrap();
+        let stored: Option<String> = conn
+            .query_row(
+                "SELECT value FROM index_settings WHERE key = ?1",
+                [FINGERPRINT_SETTINGS_KEY],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(match stored {
+            Some(json) => serde_json::from_str(&json)?,
+            None => FingerprintSettings::default(),
+        })
+    }
+
+    /// Settings every song of this index was fingerprinted with, to use for queries too
+    pub fn fingerprint_settings(&self) -> FingerprintSettings {
+        *self.fingerprint_settings.read().unwrap()
+    }
+
+    /// Take `settings` for this index if it has no songs yet
+    /// An index with songs keeps its own, since changing them takes a reindex
+    /// Returns the settings in effect
+    pub fn adopt_fingerprint_settings(
+        &self,
+        settings: &FingerprintSettings,
+    ) -> Result<FingerprintSettings> {
+        let conn = self.conn.lock().unwrap();
+        let has_songs = conn.prepare("SELECT 1 FROM songs LIMIT 1")?.exists([])?;
+        let mut current = self.fingerprint_settings.write().unwrap();
+        if !has_songs && *current != *settings {
+            conn.execute(
+                "INSERT OR REPLACE INTO index_settings (key, value) VALUES (?1, ?2)",
+                params![FINGERPRINT_SETTINGS_KEY, serde_json::to_string(settings)?],
+            )?;
+            *current = *settings;
+        }
+        Ok(*current)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn insert_song(
         &self,
         title: &str,
         artist: &str,
         path: &str,
+        details: &TrackDetails,
+        content_hash: &str,
         fingerprints: &[(u32, u32)], // (hash, offset)
+        artwork: Option<&Artwork>,
     ) -> Result<i64> {
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
 
         // 1. Insert Song Metadata
         tx.execute(
-            "INSERT INTO songs (title, artist, path) VALUES (?1, ?2, ?3)",
-            params![title, artist, path],
+            "INSERT INTO songs (title, artist, path, album, album_artist, track_number,
+                disc_number, year, genre, duration_ms, isrc, content_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                title,
+                artist,
+                path,
+                details.album,
+                details.album_artist,
+                details.track_number,
+                details.disc_number,
+                details.year,
+                details.genre,
+                details.duration_ms,
+                details.isrc,
+                content_hash
+            ],
         )?;
         let song_id = tx.last_insert_rowid();
 
@@ -83,23 +383,223 @@ impl Database {
             }
         }
 
+        // 3. Count each distinct hash once towards its document frequency
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO hash_stats (hash, song_count) VALUES (?1, 1)
+                 ON CONFLICT(hash) DO UPDATE SET song_count = song_count + 1",
+            )?;
+
+            let distinct: HashSet<u32> = fingerprints.iter().map(|&(hash, _)| hash).collect();
+            for hash in distinct {
+                stmt.execute(params![hash])?;
+            }
+        }
+
+        // 4. Embedded cover art
+        if let Some(artwork) = artwork {
+            tx.execute(
+                "INSERT INTO artwork (song_id, mime_type, width, height, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    song_id,
+                    artwork.mime_type,
+                    artwork.width,
+                    artwork.height,
+                    artwork.data
+                ],
+            )?;
+        }
+
         tx.commit()?;
         Ok(song_id)
     }
 
-    pub fn get_all_songs(&self) -> Result<Vec<crate::types::SongMetadata>> {
+    pub fn get_all_songs(&self) -> Result<Vec<SongMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM songs ORDER BY created_at DESC",
+            SONG_COLUMNS
+        ))?;
+
+        let songs = stmt.query_map([], song_from_row)?;
+
+        let mut result = Vec::new();
+        for song in songs {
+            result.push(song?);
+        }
+        Ok(result)
+    }
+
+    /// One page of songs, filtered and sorted per `query`
+    /// `after` is the id and sort key of the last song of the previous page (keyset
+    /// pagination); songs sharing a sort key are ordered by id
+    pub fn list_songs(
+        &self,
+        query: &SongListQuery,
+        after: Option<(i64, &str)>,
+        limit: u32,
+    ) -> Result<Vec<SongMetadata>> {
+        let sort_column = match query.sort {
+            SongSort::Title => "title COLLATE NOCASE",
+            SongSort::Artist => "artist COLLATE NOCASE",
+            SongSort::Date => "created_at",
+        };
+        let order = query.order.unwrap_or(match query.sort {
+            SongSort::Date => SortOrder::Desc,
+            SongSort::Title | SongSort::Artist => SortOrder::Asc,
+        });
+        let (direction, comparison) = match order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+
+        let mut conditions = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(artist) = &query.artist {
+            values.push(artist.clone().into());
+            conditions.push(format!("artist = ?{} COLLATE NOCASE", values.len()));
+        }
+        if let Some(search) = query.q.as_deref().and_then(fts_query) {
+            values.push(search.into());
+            conditions.push(format!(
+                "id IN (SELECT rowid FROM songs_fts WHERE songs_fts MATCH ?{})",
+                values.len()
+            ));
+        }
+        // Compare with the key the previous page ended on rather than looking the song up,
+        // so paging carries on if it has since been deleted or renamed
+        if let Some((after_id, after_key)) = after {
+            values.push(after_key.to_string().into());
+            values.push(after_id.into());
+            conditions.push(format!(
+                "({col}, id) {cmp} (?{key}, ?{id})",
+                col = sort_column,
+                cmp = comparison,
+                key = values.len() - 1,
+                id = values.len()
+            ));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        values.push(i64::from(limit).into());
+        let sql = format!(
+            "SELECT {} FROM songs {} ORDER BY {} {dir}, id {dir} LIMIT ?{}",
+            SONG_COLUMNS,
+            where_clause,
+            sort_column,
+            values.len(),
+            dir = direction
+        );
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let songs = stmt.query_map(params_from_iter(values), song_from_row)?;
+
+        let mut result = Vec::new();
+        for song in songs {
+            result.push(song?);
+        }
+        Ok(result)
+    }
+
+    pub fn insert_recognition(&self, recognition: &NewRecognition) -> Result<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO recognitions (source, client_id, query_duration_ms, hash_count,
+                top_song_id, raw_score, confidence, latency_ms, matched, deadline_exceeded)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                recognition.source.as_str(),
+                recognition.client_id,
+                recognition.query_duration_ms,
+                recognition.hash_count,
+                recognition.top_song_id,
+                recognition.raw_score,
+                recognition.confidence,
+                recognition.latency_ms,
+                recognition.matched,
+                recognition.deadline_exceeded
+            ],
+        )?;
+        let recognition_id = tx.last_insert_rowid();
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO recognition_candidates (recognition_id, rank, song_id, score)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (rank, (song_id, score)) in recognition.candidates.iter().enumerate() {
+                stmt.execute(params![recognition_id, rank as i64, song_id, score])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(recognition_id)
+    }
+
+    pub fn get_recognition(&self, recognition_id: i64) -> Result<Option<RecognitionRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM {} WHERE id = ?1",
+            RECOGNITION_COLUMNS, RECOGNITION_TABLES
+        ))?;
+
+        let mut rows = stmt.query_map(params![recognition_id], recognition_from_row)?;
+
+        if let Some(row) = rows.next() {
+            Ok(Some(row?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Record feedback on a recognition, replacing any earlier feedback
+    pub fn set_feedback(
+        &self,
+        recognition_id: i64,
+        verdict: Verdict,
+        correct_song_id: Option<i64>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO recognition_feedback (recognition_id, verdict, correct_song_id)
+             VALUES (?1, ?2, ?3)",
+            params![recognition_id, verdict.as_str(), correct_song_id],
+        )?;
+        Ok(())
+    }
+
+    /// Songs most often returned wrongly or missed, according to feedback
+    pub fn misidentified_songs(&self, top: u32) -> Result<Vec<MisidentifiedSong>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, title, artist, path, created_at FROM songs ORDER BY created_at DESC",
+            "SELECT x.song_id, s.title, s.artist, SUM(x.wrong) AS wrong, SUM(x.missed) AS missed
+             FROM (
+                 SELECT r.top_song_id AS song_id, 1 AS wrong, 0 AS missed
+                 FROM recognition_feedback f JOIN recognitions r ON r.id = f.recognition_id
+                 WHERE f.verdict = 'wrong' AND r.matched = 1
+                 UNION ALL
+                 SELECT correct_song_id, 0, 1 FROM recognition_feedback
+                 WHERE verdict = 'wrong' AND correct_song_id IS NOT NULL
+             ) x
+             LEFT JOIN songs s ON s.id = x.song_id
+             GROUP BY x.song_id ORDER BY wrong + missed DESC LIMIT ?1",
         )?;
 
-        let songs = stmt.query_map([], |row| {
-            Ok(crate::types::SongMetadata {
-                id: row.get(0)?,
+        let songs = stmt.query_map(params![top], |row| {
+            Ok(MisidentifiedSong {
+                song_id: row.get(0)?,
                 title: row.get(1)?,
                 artist: row.get(2)?,
-                path: row.get(3)?,
-                created_at: row.get::<_, String>(4)?,
+                wrong_matches: row.get(3)?,
+                missed: row.get(4)?,
             })
         })?;
 
@@ -110,56 +610,529 @@ impl Database {
         Ok(result)
     }
 
+    /// Top candidate scores of recognitions that have feedback, labelled by it
+    /// The top candidate is right when feedback names it as the correct song,
+    /// which also covers misses where it fell below the threshold. Recognitions
+    /// cut short by the deadline are left out, since they scored part of the query
+    pub fn labelled_scores(&self) -> Result<Vec<LabelledScore>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT r.raw_score, COALESCE(f.correct_song_id = r.top_song_id, 0)
+             FROM recognition_feedback f JOIN recognitions r ON r.id = f.recognition_id
+             WHERE r.top_song_id IS NOT NULL AND r.deadline_exceeded = 0",
+        )?;
+
+        let scores = stmt.query_map([], |row| {
+            Ok(LabelledScore {
+                raw_score: row.get(0)?,
+                correct: row.get(1)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for score in scores {
+            result.push(score?);
+        }
+        Ok(result)
+    }
+
+    /// `value` in the `created_at` format, `YYYY-MM-DD HH:MM:SS` in UTC, or None if
+    /// SQLite can't read it as a date and time
+    pub fn normalize_timestamp(&self, value: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row("SELECT datetime(?1)", params![value], |row| row.get(0))?)
+    }
+
+    /// One page of the recognition history, newest first
+    pub fn list_recognitions(
+        &self,
+        query: &RecognitionListQuery,
+        before_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Vec<RecognitionRecord>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(matched) = query.matched {
+            values.push(i64::from(matched).into());
+            conditions.push(format!("matched = ?{}", values.len()));
+        }
+        if let Some(client_id) = &query.client_id {
+            values.push(client_id.clone().into());
+            conditions.push(format!("client_id = ?{}", values.len()));
+        }
+        if let Some(source) = query.source {
+            values.push(source.as_str().to_string().into());
+            conditions.push(format!("source = ?{}", values.len()));
+        }
+        if let Some(song_id) = query.song_id {
+            values.push(song_id.into());
+            conditions.push(format!("top_song_id = ?{}", values.len()));
+        }
+        if let Some(since) = &query.since {
+            values.push(since.clone().into());
+            conditions.push(format!("created_at >= ?{}", values.len()));
+        }
+        if let Some(until) = &query.until {
+            values.push(until.clone().into());
+            conditions.push(format!("created_at < ?{}", values.len()));
+        }
+        if let Some(before_id) = before_id {
+            values.push(before_id.into());
+            conditions.push(format!("id < ?{}", values.len()));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        values.push(i64::from(limit).into());
+        let sql = format!(
+            "SELECT {} FROM {} {} ORDER BY id DESC LIMIT ?{}",
+            RECOGNITION_COLUMNS,
+            RECOGNITION_TABLES,
+            where_clause,
+            values.len()
+        );
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let recognitions = stmt.query_map(params_from_iter(values), recognition_from_row)?;
+
+        let mut result = Vec::new();
+        for recognition in recognitions {
+            result.push(recognition?);
+        }
+        Ok(result)
+    }
+
+    /// Aggregate the recognition history over the requested time range
+    pub fn recognition_stats(
+        &self,
+        query: &RecognitionStatsQuery,
+        top: u32,
+    ) -> Result<RecognitionStats> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(since) = &query.since {
+            values.push(since.clone().into());
+            conditions.push(format!("created_at >= ?{}", values.len()));
+        }
+        if let Some(until) = &query.until {
+            values.push(until.clone().into());
+            conditions.push(format!("created_at < ?{}", values.len()));
+        }
+        let range = if conditions.is_empty() {
+            "1 = 1".to_string()
+        } else {
+            conditions.join(" AND ")
+        };
+        let bucket_format = match query.bucket {
+            StatsBucket::Hour => "%Y-%m-%d %H:00:00",
+            StatsBucket::Day => "%Y-%m-%d",
+        };
+
+        let conn = self.conn.lock().unwrap();
+
+        let (total, matched, max_latency): (u64, u64, u64) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(matched), 0), COALESCE(MAX(latency_ms), 0)
+                 FROM recognitions WHERE {}",
+                range
+            ),
+            params_from_iter(values.iter()),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        // Nearest-rank percentiles, picking each row in SQL rather than loading every latency
+        let mut latency_values = values.clone();
+        latency_values.push(0.into());
+        let mut latency_stmt = conn.prepare(&format!(
+            "SELECT latency_ms FROM recognitions WHERE {}
+             ORDER BY latency_ms LIMIT 1 OFFSET ?{}",
+            range,
+            latency_values.len()
+        ))?;
+        let mut percentile =
+            |p: f64| -> Result<u64> {
+                if total == 0 {
+                    return Ok(0);
+                }
+                let rank = ((p * total as f64).ceil() as u64).clamp(1, total);
+                *latency_values.last_mut().unwrap() = ((rank - 1) as i64).into();
+                Ok(latency_stmt
+                    .query_row(params_from_iter(latency_values.iter()), |row| row.get(0))?)
+            };
+        let latency_ms = LatencyPercentiles {
+            p50: percentile(0.50)?,
+            p90: percentile(0.90)?,
+            p99: percentile(0.99)?,
+            max: max_latency,
+        };
+
+        let buckets = conn
+            .prepare(&format!(
+                "SELECT strftime('{}', created_at) AS bucket, COUNT(*), SUM(matched)
+                 FROM recognitions WHERE {} GROUP BY bucket ORDER BY bucket",
+                bucket_format, range
+            ))?
+            .query_map(params_from_iter(values.iter()), |row| {
+                Ok(TimeBucket {
+                    start: row.get(0)?,
+                    total: row.get(1)?,
+                    matched: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut top_values = values.clone();
+        top_values.push(i64::from(top).into());
+        let top_songs = conn
+            .prepare(&format!(
+                "SELECT t.song_id, s.title, s.artist, t.hits
+                 FROM (
+                     SELECT top_song_id AS song_id, COUNT(*) AS hits FROM recognitions
+                     WHERE matched = 1 AND {}
+                     GROUP BY top_song_id ORDER BY hits DESC, top_song_id LIMIT ?{}
+                 ) t
+                 LEFT JOIN songs s ON s.id = t.song_id
+                 ORDER BY t.hits DESC, t.song_id",
+                range,
+                top_values.len()
+            ))?
+            .query_map(params_from_iter(top_values.iter()), |row| {
+                Ok(SongCount {
+                    song_id: row.get(0)?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    count: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let failed_sources = conn
+            .prepare(&format!(
+                "SELECT source, client_id, SUM(1 - matched) AS failures, COUNT(*)
+                 FROM recognitions WHERE {}
+                 GROUP BY source, client_id HAVING failures > 0
+                 ORDER BY failures DESC LIMIT ?{}",
+                range,
+                top_values.len()
+            ))?
+            .query_map(params_from_iter(top_values.iter()), |row| {
+                Ok(SourceFailures {
+                    source: row.get(0)?,
+                    client_id: row.get(1)?,
+                    failures: row.get(2)?,
+                    total: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(RecognitionStats {
+            total,
+            matched,
+            match_rate: if total > 0 {
+                matched as f64 / total as f64
+            } else {
+                0.0
+            },
+            latency_ms,
+            buckets,
+            top_songs,
+            failed_sources,
+        })
+    }
+
+    /// Size of the library and its fingerprint index
+    pub fn library_stats(&self, top: u32) -> Result<LibraryStats> {
+        let conn = self.conn.lock().unwrap();
+
+        let song_count: u64 = conn.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0))?;
+        let fingerprint_count: u64 =
+            conn.query_row("SELECT COUNT(*) FROM fingerprints", [], |row| row.get(0))?;
+        let db_size_bytes: u64 = conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+
+        // Hashes found in the most songs, read from `hash_stats` instead of grouping every fingerprint
+        let hottest_hashes = conn
+            .prepare(
+                "SELECT h.hash, (SELECT COUNT(*) FROM fingerprints WHERE hash = h.hash), h.song_count
+                 FROM hash_stats h ORDER BY h.song_count DESC, h.hash LIMIT ?1",
+            )?
+            .query_map(params![top], |row| {
+                Ok(HashCount {
+                    hash: row.get(0)?,
+                    count: row.get(1)?,
+                    song_count: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(LibraryStats {
+            song_count,
+            fingerprint_count,
+            avg_hashes_per_song: if song_count > 0 {
+                fingerprint_count as f64 / song_count as f64
+            } else {
+                0.0
+            },
+            db_size_bytes,
+            hottest_hashes,
+        })
+    }
+
+    /// Fingerprint and song counts of each distinct hash, in the order given
+    /// Hashes missing from the index are reported with zero counts
+    pub fn hash_counts(&self, hashes: &[u32]) -> Result<Vec<HashCount>> {
+        let conn = self.conn.lock().unwrap();
+        let mut count_stmt = conn.prepare("SELECT COUNT(*) FROM fingerprints WHERE hash = ?1")?;
+        let mut songs_stmt = conn.prepare("SELECT song_count FROM hash_stats WHERE hash = ?1")?;
+
+        let mut seen = HashSet::new();
+        let mut counts = Vec::new();
+        for &hash in hashes {
+            if !seen.insert(hash) {
+                continue;
+            }
+            counts.push(HashCount {
+                hash,
+                count: count_stmt.query_row(params![hash], |row| row.get(0))?,
+                song_count: songs_stmt
+                    .query_row(params![hash], |row| row.get(0))
+                    .optional()?
+                    .unwrap_or(0),
+            });
+        }
+        Ok(counts)
+    }
+
+    /// Offsets in one song of each of the given hashes it contains
+    pub fn song_hash_offsets(
+        &self,
+        song_id: i64,
+        hashes: &[u32],
+    ) -> Result<HashMap<u32, Vec<u32>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT offset FROM fingerprints WHERE hash = ?1 AND song_id = ?2")?;
+
+        let mut offsets = HashMap::new();
+        for &hash in hashes {
+            if offsets.contains_key(&hash) {
+                continue;
+            }
+            let song_offsets = stmt
+                .query_map(params![hash, song_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<u32>>>()?;
+            if !song_offsets.is_empty() {
+                offsets.insert(hash, song_offsets);
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// True if the path was indexed or linked to an existing song as a duplicate
     pub fn song_exists_by_path(&self, path: &str) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT 1 FROM songs WHERE path = ?1 LIMIT 1")?;
+        let mut stmt = conn.prepare(
+            "SELECT 1 FROM songs WHERE path = ?1
+             UNION ALL
+             SELECT 1 FROM song_duplicates WHERE path = ?1
+             LIMIT 1",
+        )?;
         let exists = stmt.exists(params![path])?;
         Ok(exists)
     }
 
+    pub fn find_song_by_content_hash(&self, content_hash: &str) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT id FROM songs WHERE content_hash = ?1 LIMIT 1")?;
+        let mut rows = stmt.query_map(params![content_hash], |row| row.get(0))?;
+
+        if let Some(row) = rows.next() {
+            Ok(Some(row?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Songs indexed before content hashes were stored, as (song_id, path)
+    pub fn songs_without_content_hash(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, path FROM songs WHERE content_hash IS NULL ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Store a song's content hash, unless another song already has it
+    /// Returns whether it was stored
+    pub fn set_content_hash(&self, song_id: i64, content_hash: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE OR IGNORE songs SET content_hash = ?2 WHERE id = ?1",
+            params![song_id, content_hash],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn insert_duplicate(&self, path: &str, song_id: i64, kind: DuplicateKind) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO song_duplicates (path, song_id, kind) VALUES (?1, ?2, ?3)",
+            params![path, song_id, kind.as_str()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_duplicates(&self) -> Result<Vec<DuplicateSong>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, song_id, kind, created_at FROM song_duplicates ORDER BY created_at DESC",
+        )?;
+
+        let duplicates = stmt.query_map([], |row| {
+            Ok(DuplicateSong {
+                path: row.get(0)?,
+                song_id: row.get(1)?,
+                kind: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for duplicate in duplicates {
+            result.push(duplicate?);
+        }
+        Ok(result)
+    }
+
     /// Find matching fingerprints in the database
-    /// Returns a map of song_id -> list of (db_offset, query_offset)
+    /// Returns (db_offset, query_offset, weight) per song; see `MatchOptions`
     pub fn find_matches(
         &self,
         query_hashes: &[(u32, u32)],
-    ) -> Result<HashMap<i64, Vec<(u32, u32)>>> {
+        options: &MatchOptions,
+    ) -> Result<Lookup> {
         let conn = self.conn.lock().unwrap();
+        let query_hashes = weigh_hashes(&conn, query_hashes, options)?;
         let mut stmt = conn.prepare("SELECT song_id, offset FROM fingerprints WHERE hash = ?1")?;
 
-        let mut matches: HashMap<i64, Vec<(u32, u32)>> = HashMap::new();
+        let mut matches: Matches = HashMap::new();
+        let mut truncated = false;
 
-        for (hash, query_offset) in query_hashes {
-            let rows = stmt.query_map(params![hash], |row| {
-                Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?))
-            });
+        let Some(max_candidates) = options.max_candidates else {
+            for &(hash, query_offset, weight) in &query_hashes {
+                if past_deadline(options) {
+                    truncated = true;
+                    break;
+                }
+                let rows = stmt.query_map(params![hash], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?))
+                })?;
 
-            if let Ok(rows) = rows {
                 for row in rows {
-                    if let Ok((song_id, db_offset)) = row {
-                        matches
-                            .entry(song_id)
-                            .or_default()
-                            .push((db_offset, *query_offset));
-                    }
+                    let (song_id, db_offset) = row?;
+                    matches
+                        .entry(song_id)
+                        .or_default()
+                        .push((db_offset, query_offset, weight));
                 }
             }
+            return Ok(Lookup { matches, truncated });
+        };
+
+        // Coarse pass: count hits per song without keeping any offsets,
+        // then fetch offsets only for the songs with the most hits
+        let mut count_stmt = conn.prepare(
+            "SELECT song_id, COUNT(*) FROM fingerprints WHERE hash = ?1 GROUP BY song_id",
+        )?;
+        let mut hits: HashMap<i64, f32> = HashMap::new();
+        let mut counted = 0;
+        for &(hash, _, weight) in &query_hashes {
+            if past_deadline(options) {
+                truncated = true;
+                break;
+            }
+            let rows = count_stmt.query_map(params![hash], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?))
+            })?;
+
+            for row in rows {
+                let (song_id, count) = row?;
+                *hits.entry(song_id).or_default() += weight * count as f32;
+            }
+            counted += 1;
+        }
+
+        // Each shortlisted song is a bound parameter; options built outside a validated
+        // config are clamped to the same limit
+        let shortlist = top_songs_by_hits(hits, max_candidates.min(MAX_CANDIDATES_LIMIT));
+        if shortlist.is_empty() {
+            return Ok(Lookup { matches, truncated });
+        }
+        // ?1 is the hash, the shortlisted song ids follow
+        let mut shortlist_stmt = conn.prepare(&format!(
+            "SELECT song_id, offset FROM fingerprints WHERE hash = ?1 AND song_id IN ({})",
+            (2..shortlist.len() + 2)
+                .map(|n| format!("?{}", n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+        let mut args: Vec<i64> = vec![0];
+        args.extend(&shortlist);
+        for &(hash, query_offset, weight) in &query_hashes[..counted] {
+            if past_deadline(options) {
+                truncated = true;
+                break;
+            }
+            args[0] = hash as i64;
+            let rows = shortlist_stmt.query_map(params_from_iter(&args), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?))
+            })?;
+
+            for row in rows {
+                let (song_id, db_offset) = row?;
+                matches
+                    .entry(song_id)
+                    .or_default()
+                    .push((db_offset, query_offset, weight));
+            }
         }
 
-        Ok(matches)
+        Ok(Lookup { matches, truncated })
     }
 
-    pub fn get_song_metadata(&self, song_id: i64) -> Result<Option<crate::types::SongMetadata>> {
+    pub fn get_song_metadata(&self, song_id: i64) -> Result<Option<SongMetadata>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt =
-            conn.prepare("SELECT id, title, artist, path, created_at FROM songs WHERE id = ?1")?;
+            conn.prepare(&format!("SELECT {} FROM songs WHERE id = ?1", SONG_COLUMNS))?;
+
+        let mut rows = stmt.query_map(params![song_id], song_from_row)?;
+
+        if let Some(row) = rows.next() {
+            Ok(Some(row?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_artwork(&self, song_id: i64) -> Result<Option<Artwork>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT mime_type, width, height, data FROM artwork WHERE song_id = ?1")?;
 
         let mut rows = stmt.query_map(params![song_id], |row| {
-            Ok(crate::types::SongMetadata {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                artist: row.get(2)?,
-                path: row.get(3)?,
-                created_at: row.get::<_, String>(4)?,
+            Ok(Artwork {
+                mime_type: row.get(0)?,
+                width: row.get(1)?,
+                height: row.get(2)?,
+                data: row.get(3)?,
             })
         })?;
 
@@ -170,11 +1143,849 @@ impl Database {
         }
     }
 
+    pub fn insert_chroma(&self, song_id: i64, data: &[u8]) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO song_chroma (song_id, data) VALUES (?1, ?2)",
+            params![song_id, data],
+        )?;
+        Ok(())
+    }
+
+    /// Every stored chroma sequence as (song_id, data), by song id
+    pub fn get_all_chroma(&self) -> Result<Vec<(i64, Vec<u8>)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT song_id, data FROM song_chroma ORDER BY song_id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Songs indexed before chroma was stored, as (song_id, path)
+    pub fn songs_without_chroma(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, path FROM songs
+             WHERE id NOT IN (SELECT song_id FROM song_chroma) ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// A previously resized copy of a song's artwork, as (mime_type, bytes)
+    pub fn get_artwork_thumbnail(
+        &self,
+        song_id: i64,
+        size: u32,
+    ) -> Result<Option<(String, Vec<u8>)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT mime_type, data FROM artwork_thumbnails WHERE song_id = ?1 AND size = ?2",
+        )?;
+
+        let mut rows =
+            stmt.query_map(params![song_id, size], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        if let Some(row) = rows.next() {
+            Ok(Some(row?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn insert_artwork_thumbnail(
+        &self,
+        song_id: i64,
+        size: u32,
+        mime_type: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO artwork_thumbnails (song_id, size, mime_type, data)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![song_id, size, mime_type, data],
+        )?;
+        Ok(())
+    }
+
+    pub fn has_artwork(&self, song_id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT 1 FROM artwork WHERE song_id = ?1 LIMIT 1")?;
+        let exists = stmt.exists(params![song_id])?;
+        Ok(exists)
+    }
+
+    /// Update title and/or artist of a song, leaving `None` fields untouched
+    /// Returns false if no song with this id exists
+    pub fn update_song_metadata(
+        &self,
+        song_id: i64,
+        title: Option<&str>,
+        artist: Option<&str>,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE songs SET title = COALESCE(?2, title), artist = COALESCE(?3, artist) WHERE id = ?1",
+            params![song_id, title, artist],
+        )?;
+        Ok(updated > 0)
+    }
+
     // Helper to clear database for re-indexing
     pub fn clear_database(&self) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM fingerprints", [])?;
+        conn.execute("DELETE FROM hash_stats", [])?;
+        conn.execute("DELETE FROM artwork_thumbnails", [])?;
+        conn.execute("DELETE FROM artwork", [])?;
+        conn.execute("DELETE FROM song_chroma", [])?;
+        conn.execute("DELETE FROM song_duplicates", [])?;
         conn.execute("DELETE FROM songs", [])?;
         Ok(())
     }
 }
+
+/// Whether `error` is a write rejected by a UNIQUE constraint
+pub fn is_unique_violation(error: &AppError) -> bool {
+    matches!(
+        error,
+        AppError::Database(rusqlite::Error::SqliteFailure(e, _))
+            if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+    )
+}
+
+/// Add the `columns` a table created by an older version lacks
+fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let existing: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (column, sql_type) in columns {
+        if !existing.iter().any(|c| c == column) {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, sql_type),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop query hashes over the popularity cap and attach each remaining hash's weight
+/// IDF weights are scaled so the query's median hash weighs 1, which keeps scores
+/// in the hit-count units of `match_threshold` and `confidence`
+/// Returns (hash, query_offset, weight)
+fn weigh_hashes(
+    conn: &Connection,
+    query_hashes: &[(u32, u32)],
+    options: &MatchOptions,
+) -> Result<Vec<(u32, u32, f32)>> {
+    if options.max_hash_songs.is_none() && !options.idf_weighting {
+        return Ok(query_hashes
+            .iter()
+            .map(|&(hash, query_offset)| (hash, query_offset, 1.0))
+            .collect());
+    }
+
+    let song_count: u64 = if options.idf_weighting {
+        conn.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0))?
+    } else {
+        0
+    };
+
+    let distinct: Vec<u32> = query_hashes
+        .iter()
+        .map(|&(hash, _)| hash)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut hash_songs_by_hash: HashMap<u32, u64> = HashMap::with_capacity(distinct.len());
+    for chunk in distinct.chunks(HASH_STATS_CHUNK) {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT hash, song_count FROM hash_stats WHERE hash IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        ))?;
+        let rows = stmt.query_map(params_from_iter(chunk), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        for row in rows {
+            let (hash, hash_songs) = row?;
+            hash_songs_by_hash.insert(hash, hash_songs);
+        }
+    }
+
+    let mut weighted = Vec::with_capacity(query_hashes.len());
+    for &(hash, query_offset) in query_hashes {
+        let hash_songs = hash_songs_by_hash.get(&hash).copied().unwrap_or(0);
+        if hash_songs == 0 {
+            continue;
+        }
+        // Too common to tell songs apart, and expensive to collect
+        if options.max_hash_songs.is_some_and(|max| hash_songs > max) {
+            continue;
+        }
+
+        let weight = if options.idf_weighting {
+            idf_weight(hash_songs, song_count)
+        } else {
+            1.0
+        };
+        weighted.push((hash, query_offset, weight));
+    }
+
+    if options.idf_weighting && !weighted.is_empty() {
+        let mut weights: Vec<f32> = weighted.iter().map(|&(_, _, weight)| weight).collect();
+        let median = *weights
+            .select_nth_unstable_by(weighted.len() / 2, f32::total_cmp)
+            .1;
+        if median > 0.0 {
+            for (_, _, weight) in weighted.iter_mut() {
+                *weight /= median;
+            }
+        }
+    }
+    Ok(weighted)
+}
+
+fn past_deadline(options: &MatchOptions) -> bool {
+    options
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// The `limit` songs with the most (weighted) hash hits, ignoring alignment
+fn top_songs_by_hits(hits: HashMap<i64, f32>, limit: usize) -> HashSet<i64> {
+    let mut ranked: Vec<(i64, f32)> = hits.into_iter().collect();
+    ranked.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(song_id, _)| song_id)
+        .collect()
+}
+
+/// Inverse document frequency of a hash found in `hash_songs` of `song_count` songs,
+/// from 1 for a hash unique to one song down to its least for one in every song
+fn idf_weight(hash_songs: u64, song_count: u64) -> f32 {
+    let song_count = song_count.max(hash_songs) as f32;
+    (1.0 + song_count / hash_songs as f32).ln() / (1.0 + song_count).ln()
+}
+
+/// Map a row selected with `RECOGNITION_COLUMNS` to `RecognitionRecord`
+fn recognition_from_row(row: &Row) -> rusqlite::Result<RecognitionRecord> {
+    Ok(RecognitionRecord {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        source: row.get(2)?,
+        client_id: row.get(3)?,
+        query_duration_ms: row.get(4)?,
+        hash_count: row.get(5)?,
+        top_song_id: row.get(6)?,
+        raw_score: row.get(7)?,
+        confidence: row.get(8)?,
+        latency_ms: row.get(9)?,
+        matched: row.get(10)?,
+        verdict: row
+            .get::<_, Option<String>>(11)?
+            .as_deref()
+            .and_then(Verdict::parse),
+        correct_song_id: row.get(12)?,
+    })
+}
+
+/// Turn free text into an FTS5 query matching every word as a prefix
+/// Words are quoted so user input can't inject FTS syntax
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Map a row selected with `SONG_COLUMNS` to `SongMetadata`
+fn song_from_row(row: &Row) -> rusqlite::Result<SongMetadata> {
+    Ok(SongMetadata {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        artist: row.get(2)?,
+        path: row.get(3)?,
+        created_at: row.get::<_, String>(4)?,
+        details: TrackDetails {
+            album: row.get(5)?,
+            album_artist: row.get(6)?,
+            track_number: row.get(7)?,
+            disc_number: row.get(8)?,
+            year: row.get(9)?,
+            genre: row.get(10)?,
+            duration_ms: row.get(11)?,
+            isrc: row.get(12)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{RecognitionSource, StatsBucket};
+
+    fn add_song(db: &Database, title: &str, artist: &str, album: Option<&str>) -> i64 {
+        let path = format!(
+            "/music/{}/{}-{}.mp3",
+            artist,
+            title,
+            db.get_all_songs().unwrap().len()
+        );
+        let details = TrackDetails {
+            album: album.map(str::to_string),
+            ..Default::default()
+        };
+        db.insert_song(title, artist, &path, &details, &path, &[], None)
+            .unwrap()
+    }
+
+    fn query(sort: SongSort) -> SongListQuery {
+        SongListQuery {
+            sort,
+            ..Default::default()
+        }
+    }
+
+    fn sort_key(song: &SongMetadata, sort: SongSort) -> String {
+        match sort {
+            SongSort::Title => song.title.clone(),
+            SongSort::Artist => song.artist.clone(),
+            SongSort::Date => song.created_at.clone(),
+        }
+    }
+
+    /// Ids of every song, fetched `limit` at a time
+    fn page_through(db: &Database, query: &SongListQuery, limit: u32) -> Vec<i64> {
+        let mut ids = Vec::new();
+        let mut after: Option<(i64, String)> = None;
+        loop {
+            let page = db
+                .list_songs(
+                    query,
+                    after.as_ref().map(|(id, key)| (*id, key.as_str())),
+                    limit,
+                )
+                .unwrap();
+            ids.extend(page.iter().map(|song| song.id));
+            match page.last() {
+                Some(last) if page.len() == limit as usize => {
+                    after = Some((last.id, sort_key(last, query.sort)))
+                }
+                _ => return ids,
+            }
+        }
+    }
+
+    fn titles(songs: &[SongMetadata]) -> Vec<&str> {
+        songs.iter().map(|song| song.title.as_str()).collect()
+    }
+
+    fn log(
+        db: &Database,
+        source: RecognitionSource,
+        client_id: Option<&str>,
+        top: Option<(i64, i64)>,
+        matched: bool,
+        created_at: &str,
+    ) -> i64 {
+        let candidates: Vec<(i64, i64)> = top.into_iter().collect();
+        let id = db
+            .insert_recognition(&NewRecognition {
+                source,
+                client_id,
+                query_duration_ms: 5000,
+                hash_count: 900,
+                top_song_id: top.map(|(song_id, _)| song_id),
+                raw_score: top.map_or(0, |(_, score)| score),
+                confidence: if matched { 0.9 } else { 0.1 },
+                latency_ms: 40,
+                matched,
+                deadline_exceeded: false,
+                candidates: &candidates,
+            })
+            .unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE recognitions SET created_at = ?2 WHERE id = ?1",
+                params![id, created_at],
+            )
+            .unwrap();
+        id
+    }
+
+    fn recognition_ids(db: &Database, query: &RecognitionListQuery) -> Vec<i64> {
+        db.list_recognitions(query, None, 50)
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect()
+    }
+
+    #[test]
+    fn recognitions_are_logged_with_their_candidates_and_feedback() {
+        let db = Database::open_in_memory().unwrap();
+        let song = add_song(&db, "Intro", "Band", None);
+        let id = db
+            .insert_recognition(&NewRecognition {
+                source: RecognitionSource::Fingerprints,
+                client_id: Some("ios-4f2a"),
+                query_duration_ms: 5120,
+                hash_count: 1834,
+                top_song_id: Some(song),
+                raw_score: 42,
+                confidence: 0.94,
+                latency_ms: 37,
+                matched: true,
+                deadline_exceeded: false,
+                candidates: &[(song, 42), (song + 1, 9)],
+            })
+            .unwrap();
+
+        let record = db.get_recognition(id).unwrap().unwrap();
+        assert_eq!(record.source, "fingerprints");
+        assert_eq!(record.client_id.as_deref(), Some("ios-4f2a"));
+        assert_eq!((record.query_duration_ms, record.hash_count), (5120, 1834));
+        assert_eq!((record.top_song_id, record.raw_score), (Some(song), 42));
+        assert!(record.matched && record.verdict.is_none());
+        let ranked: Vec<(i64, i64, i64)> = db
+            .conn
+            .lock()
+            .unwrap()
+            .prepare("SELECT rank, song_id, score FROM recognition_candidates ORDER BY rank")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ranked, [(0, song, 42), (1, song + 1, 9)]);
+
+        db.set_feedback(id, Verdict::Correct, None).unwrap();
+        db.set_feedback(id, Verdict::Wrong, Some(song + 1)).unwrap();
+        let record = db.get_recognition(id).unwrap().unwrap();
+        assert_eq!(record.verdict, Some(Verdict::Wrong));
+        assert_eq!(record.correct_song_id, Some(song + 1));
+        assert!(db.get_recognition(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn recognition_history_filters_combine() {
+        let db = Database::open_in_memory().unwrap();
+        let http = log(
+            &db,
+            RecognitionSource::Http,
+            Some("web"),
+            Some((1, 40)),
+            true,
+            "2026-10-01 08:00:00",
+        );
+        let ws = log(
+            &db,
+            RecognitionSource::Ws,
+            Some("android"),
+            Some((2, 3)),
+            false,
+            "2026-10-01 09:30:00",
+        );
+        let batch = log(
+            &db,
+            RecognitionSource::Batch,
+            None,
+            None,
+            false,
+            "2026-10-02 00:00:00",
+        );
+        let late = log(
+            &db,
+            RecognitionSource::Http,
+            Some("web"),
+            Some((2, 55)),
+            true,
+            "2026-10-03 12:00:00",
+        );
+
+        let all = RecognitionListQuery::default();
+        assert_eq!(recognition_ids(&db, &all), [late, batch, ws, http]);
+
+        let source = |source| RecognitionListQuery {
+            source: Some(source),
+            ..Default::default()
+        };
+        assert_eq!(
+            recognition_ids(&db, &source(RecognitionSource::Http)),
+            [late, http]
+        );
+        assert_eq!(
+            recognition_ids(&db, &source(RecognitionSource::Batch)),
+            [batch]
+        );
+        assert!(recognition_ids(&db, &source(RecognitionSource::Fingerprints)).is_empty());
+
+        let unmatched = RecognitionListQuery {
+            matched: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(recognition_ids(&db, &unmatched), [batch, ws]);
+
+        // song_id is the top candidate, matched or not
+        let song = RecognitionListQuery {
+            song_id: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(recognition_ids(&db, &song), [late, ws]);
+
+        let client = RecognitionListQuery {
+            client_id: Some("web".to_string()),
+            matched: Some(true),
+            until: Some("2026-10-03 00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(recognition_ids(&db, &client), [http]);
+
+        // since is inclusive, until exclusive
+        let day = RecognitionListQuery {
+            since: Some("2026-10-01 09:30:00".to_string()),
+            until: Some("2026-10-02 00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(recognition_ids(&db, &day), [ws]);
+
+        let page = db.list_recognitions(&all, Some(batch), 1).unwrap();
+        assert_eq!(page.iter().map(|r| r.id).collect::<Vec<_>>(), [ws]);
+    }
+
+    #[test]
+    fn timestamps_are_normalized_to_the_created_at_format() {
+        let db = Database::open_in_memory().unwrap();
+        for (value, expected) in [
+            ("2026-10-01", "2026-10-01 00:00:00"),
+            ("2026-10-01 08:00:00", "2026-10-01 08:00:00"),
+            ("2026-10-01T08:00:00Z", "2026-10-01 08:00:00"),
+            ("2026-10-01T08:00:00+02:00", "2026-10-01 06:00:00"),
+        ] {
+            assert_eq!(
+                db.normalize_timestamp(value).unwrap().as_deref(),
+                Some(expected),
+                "{}",
+                value
+            );
+        }
+        for value in ["", "yesterday", "2026-13-01", "01/10/2026"] {
+            assert_eq!(db.normalize_timestamp(value).unwrap(), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn latency_percentiles_use_the_nearest_rank() {
+        let db = Database::open_in_memory().unwrap();
+        let empty = db
+            .recognition_stats(&RecognitionStatsQuery::default(), 10)
+            .unwrap();
+        assert_eq!((empty.total, empty.match_rate), (0, 0.0));
+        assert_eq!(
+            (
+                empty.latency_ms.p50,
+                empty.latency_ms.p99,
+                empty.latency_ms.max
+            ),
+            (0, 0, 0)
+        );
+
+        // Latencies 1..=200 ms logged in reverse order, so ids don't give them away
+        for i in 0..200 {
+            let id = log(
+                &db,
+                RecognitionSource::Http,
+                None,
+                (i % 4 == 0).then_some((1, 40)),
+                i % 4 == 0,
+                "2026-10-01 08:00:00",
+            );
+            db.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE recognitions SET latency_ms = ?2 WHERE id = ?1",
+                    params![id, 200 - i],
+                )
+                .unwrap();
+        }
+
+        let stats = db
+            .recognition_stats(&RecognitionStatsQuery::default(), 10)
+            .unwrap();
+        assert_eq!(
+            (stats.total, stats.matched, stats.match_rate),
+            (200, 50, 0.25)
+        );
+        let latency = &stats.latency_ms;
+        assert_eq!(
+            (latency.p50, latency.p90, latency.p99, latency.max),
+            (100, 180, 198, 200)
+        );
+    }
+
+    #[test]
+    fn recognition_stats_cover_only_the_requested_range() {
+        let db = Database::open_in_memory().unwrap();
+        let song = add_song(&db, "Intro", "Band", None);
+        for (source, client_id, top, matched, created_at) in [
+            (
+                RecognitionSource::Http,
+                Some("web"),
+                Some((song, 40)),
+                true,
+                "2026-09-30 23:59:59",
+            ),
+            (
+                RecognitionSource::Http,
+                Some("web"),
+                Some((song, 40)),
+                true,
+                "2026-10-01 00:00:00",
+            ),
+            (
+                RecognitionSource::Ws,
+                Some("android"),
+                Some((song, 3)),
+                false,
+                "2026-10-01 08:10:00",
+            ),
+            (
+                RecognitionSource::Http,
+                Some("web"),
+                Some((99, 50)),
+                true,
+                "2026-10-01 08:50:00",
+            ),
+            (
+                RecognitionSource::Ws,
+                Some("android"),
+                None,
+                false,
+                "2026-10-01 23:00:00",
+            ),
+            (
+                RecognitionSource::Batch,
+                None,
+                None,
+                false,
+                "2026-10-02 00:00:00",
+            ),
+        ] {
+            log(&db, source, client_id, top, matched, created_at);
+        }
+
+        let october_first = |bucket| RecognitionStatsQuery {
+            since: Some("2026-10-01 00:00:00".to_string()),
+            until: Some("2026-10-02 00:00:00".to_string()),
+            bucket,
+            top: None,
+        };
+
+        let stats = db
+            .recognition_stats(&october_first(StatsBucket::Hour), 10)
+            .unwrap();
+        assert_eq!((stats.total, stats.matched), (4, 2));
+        let buckets: Vec<(&str, u64, u64)> = stats
+            .buckets
+            .iter()
+            .map(|b| (b.start.as_str(), b.total, b.matched))
+            .collect();
+        assert_eq!(
+            buckets,
+            [
+                ("2026-10-01 00:00:00", 1, 1),
+                ("2026-10-01 08:00:00", 2, 1),
+                ("2026-10-01 23:00:00", 1, 0)
+            ]
+        );
+        // Only matched recognitions count towards top songs; deleted songs keep their id
+        let top: Vec<(i64, Option<&str>, u64)> = stats
+            .top_songs
+            .iter()
+            .map(|s| (s.song_id, s.title.as_deref(), s.count))
+            .collect();
+        assert_eq!(top, [(song, Some("Intro"), 1), (99, None, 1)]);
+        let failed: Vec<(&str, Option<&str>, u64, u64)> = stats
+            .failed_sources
+            .iter()
+            .map(|f| {
+                (
+                    f.source.as_str(),
+                    f.client_id.as_deref(),
+                    f.failures,
+                    f.total,
+                )
+            })
+            .collect();
+        assert_eq!(failed, [("ws", Some("android"), 2, 2)]);
+
+        let stats = db
+            .recognition_stats(&october_first(StatsBucket::Day), 10)
+            .unwrap();
+        let buckets: Vec<(&str, u64)> = stats
+            .buckets
+            .iter()
+            .map(|b| (b.start.as_str(), b.total))
+            .collect();
+        assert_eq!(buckets, [("2026-10-01", 4)]);
+
+        let everything = db
+            .recognition_stats(&RecognitionStatsQuery::default(), 1)
+            .unwrap();
+        assert_eq!((everything.total, everything.buckets.len()), (6, 3));
+        assert_eq!(everything.top_songs.len(), 1);
+        assert_eq!(everything.top_songs[0].count, 2);
+        assert_eq!(everything.failed_sources.len(), 1);
+    }
+
+    #[test]
+    fn library_stats_rank_hashes_by_the_songs_containing_them() {
+        let db = Database::open_in_memory().unwrap();
+        // Hash 7 is repeated within one song, so it has the most fingerprints but one song
+        let songs: [&[(u32, u32)]; 3] = [
+            &[(7, 0), (7, 10), (7, 20), (7, 30), (5, 1), (3, 2)],
+            &[(5, 0), (3, 4)],
+            &[(5, 0), (9, 3)],
+        ];
+        for (i, fingerprints) in songs.iter().enumerate() {
+            let path = format!("/music/{}.mp3", i);
+            db.insert_song(
+                "Song",
+                "Band",
+                &path,
+                &TrackDetails::default(),
+                &path,
+                fingerprints,
+                None,
+            )
+            .unwrap();
+        }
+
+        let stats = db.library_stats(3).unwrap();
+        assert_eq!((stats.song_count, stats.fingerprint_count), (3, 10));
+        assert!((stats.avg_hashes_per_song - 10.0 / 3.0).abs() < 1e-9);
+        assert!(stats.db_size_bytes > 0);
+        let hottest: Vec<(u32, u64, u64)> = stats
+            .hottest_hashes
+            .iter()
+            .map(|h| (h.hash, h.count, h.song_count))
+            .collect();
+        assert_eq!(hottest, [(5, 3, 3), (3, 2, 2), (7, 4, 1)]);
+
+        let counts: Vec<(u32, u64, u64)> = db
+            .hash_counts(&[9, 42, 7])
+            .unwrap()
+            .iter()
+            .map(|h| (h.hash, h.count, h.song_count))
+            .collect();
+        assert_eq!(counts, [(9, 1, 1), (42, 0, 0), (7, 4, 1)]);
+    }
+
+    #[test]
+    fn paging_visits_songs_sharing_a_sort_key_once() {
+        let db = Database::open_in_memory().unwrap();
+        let mut ids: Vec<i64> = (0..7)
+            .map(|_| add_song(&db, "Intro", "Band", None))
+            .collect();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE songs SET created_at = '2024-01-01 00:00:00'", [])
+            .unwrap();
+
+        for limit in [1, 2, 3, 7, 8] {
+            assert_eq!(page_through(&db, &query(SongSort::Title), limit), ids);
+            assert_eq!(page_through(&db, &query(SongSort::Artist), limit), ids);
+        }
+
+        // Newest first, so ties come in descending id order too
+        ids.reverse();
+        for limit in [1, 2, 3] {
+            assert_eq!(page_through(&db, &query(SongSort::Date), limit), ids);
+        }
+    }
+
+    #[test]
+    fn paging_carries_on_after_the_last_song_is_renamed_or_deleted() {
+        let db = Database::open_in_memory().unwrap();
+        for title in ["Alpha", "Bravo", "Charlie", "Delta"] {
+            add_song(&db, title, "Band", None);
+        }
+        let first = db.list_songs(&query(SongSort::Title), None, 2).unwrap();
+        assert_eq!(titles(&first), ["Alpha", "Bravo"]);
+        let bravo = first[1].id;
+
+        db.update_song_metadata(bravo, Some("Echo"), None).unwrap();
+        let next = db
+            .list_songs(&query(SongSort::Title), Some((bravo, "Bravo")), 10)
+            .unwrap();
+        assert_eq!(titles(&next), ["Charlie", "Delta", "Echo"]);
+
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM songs WHERE id = ?1", params![bravo])
+            .unwrap();
+        let next = db
+            .list_songs(&query(SongSort::Title), Some((bravo, "Bravo")), 10)
+            .unwrap();
+        assert_eq!(titles(&next), ["Charlie", "Delta"]);
+    }
+
+    #[test]
+    fn search_matches_word_prefixes_of_title_artist_and_album() {
+        let db = Database::open_in_memory().unwrap();
+        add_song(&db, "Yesterday", "The Beatles", Some("Help!"));
+        add_song(&db, "Helpless", "Neil Young", None);
+        add_song(&db, "Beat It", "Michael Jackson", Some("Thriller"));
+        let renamed = add_song(&db, "Untitled", "Unknown", None);
+        db.update_song_metadata(renamed, Some("Thriller Night"), None)
+            .unwrap();
+
+        let search = |q: &str| {
+            let query = SongListQuery {
+                sort: SongSort::Title,
+                q: Some(q.to_string()),
+                ..Default::default()
+            };
+            let songs = db.list_songs(&query, None, 10).unwrap();
+            titles(&songs)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search("beat"), ["Beat It", "Yesterday"]);
+        assert_eq!(search("help"), ["Helpless", "Yesterday"]);
+        assert_eq!(search("thriller"), ["Beat It", "Thriller Night"]);
+        assert_eq!(search("neil young"), ["Helpless"]);
+        assert!(search("untitled").is_empty());
+        // Quotes and FTS operators are searched for literally instead of failing the query
+        assert!(search("\"beat OR").is_empty());
+        // Blank searches don't filter
+        assert_eq!(search("  ").len(), 4);
+
+        let by_artist = SongListQuery {
+            artist: Some("the beatles".to_string()),
+            q: Some("yes".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            titles(&db.list_songs(&by_artist, None, 10).unwrap()),
+            ["Yesterday"]
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Reproducible synthetic audio for tests
+//!
+//! Every generator is deterministic: the same arguments and seed give the same
+//! samples on every run and platform, so index → recognize tests need neither
+//! music files nor FFmpeg. Output is 16kHz mono in [-1, 1], like `load_audio`.
+
+use crate::fingerprint::{ms_to_samples, SAMPLE_RATE};
+use std::f32::consts::PI;
+
+/// Seconds of audio as a sample count
+fn samples_for(seconds: f32) -> usize {
+    ms_to_samples((seconds * 1000.0) as u64)
+}
+
+fn time(i: usize) -> f32 {
+    i as f32 / SAMPLE_RATE as f32
+}
+
+/// xorshift64*, seeded
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Frequency of a MIDI note number, A4 = 69 = 440 Hz
+pub fn midi_to_hz(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+pub fn silence(seconds: f32) -> Vec<f32> {
+    vec![0.0; samples_for(seconds)]
+}
+
+/// A sine tone
+pub fn tone(frequency: f32, seconds: f32, amplitude: f32) -> Vec<f32> {
+    (0..samples_for(seconds))
+        .map(|i| amplitude * (2.0 * PI * frequency * time(i)).sin())
+        .collect()
+}
+
+/// Sine tones of `frequencies` played together, peaking around 0.8
+pub fn chord(frequencies: &[f32], seconds: f32) -> Vec<f32> {
+    let amplitude = 0.8 / frequencies.len().max(1) as f32;
+    (0..samples_for(seconds))
+        .map(|i| {
+            frequencies
+                .iter()
+                .map(|&f| amplitude * (2.0 * PI * f * time(i)).sin())
+                .sum()
+        })
+        .collect()
+}
+
+/// Exponential sine sweep from `start_hz` to `end_hz`
+pub fn sweep(start_hz: f32, end_hz: f32, seconds: f32) -> Vec<f32> {
+    let rate = (end_hz / start_hz).ln() / seconds;
+    (0..samples_for(seconds))
+        .map(|i| {
+            // Phase is the integral of start_hz * e^(rate t)
+            let phase = if rate.abs() < 1e-9 {
+                start_hz * time(i)
+            } else {
+                start_hz * ((rate * time(i)).exp() - 1.0) / rate
+            };
+            0.5 * (2.0 * PI * phase).sin()
+        })
+        .collect()
+}
+
+/// Uniform white noise
+pub fn white_noise(seconds: f32, amplitude: f32, seed: u64) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    (0..samples_for(seconds))
+        .map(|_| amplitude * (2.0 * rng.next_f32() - 1.0))
+        .collect()
+}
+
+/// Kick on beats 1 and 3, snare on 2 and 4 and hi-hats on eighth notes,
+/// with a seeded drum tuning, ghost kicks and hi-hat accents
+pub fn drum_pattern(bpm: f32, seconds: f32, seed: u64) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    let kick = kick(40.0 + 40.0 * rng.next_f32());
+    let snare_body = 150.0 + 150.0 * rng.next_f32();
+    let mut out = silence(seconds);
+    let eighth = 30.0 / bpm;
+    let eighths = (seconds / eighth).ceil() as usize;
+
+    for step in 0..eighths {
+        let start = samples_for(step as f32 * eighth);
+        let beat = step / 2 % 4;
+        if step % 2 == 0 && (beat == 0 || beat == 2) || rng.below(8) == 0 {
+            add_at(&mut out, start, &kick);
+        }
+        if step % 2 == 0 && (beat == 1 || beat == 3) {
+            add_at(&mut out, start, &snare(&mut rng, snare_body));
+        }
+        let accent = if rng.below(3) == 0 { 0.25 } else { 0.12 };
+        add_at(&mut out, start, &hi_hat(&mut rng, accent));
+    }
+    out.into_iter().map(|s| s.clamp(-1.0, 1.0)).collect()
+}
+
+/// A sine that drops from three times `base_hz` to `base_hz` as it decays
+fn kick(base_hz: f32) -> Vec<f32> {
+    let mut phase = 0.0;
+    (0..samples_for(0.25))
+        .map(|i| {
+            let t = time(i);
+            phase += 2.0 * PI * base_hz * (1.0 + 2.0 * (-t * 30.0).exp()) / SAMPLE_RATE as f32;
+            0.9 * (-t * 12.0).exp() * phase.sin()
+        })
+        .collect()
+}
+
+/// A decaying noise burst over a tone at `body_hz`
+fn snare(rng: &mut Rng, body_hz: f32) -> Vec<f32> {
+    (0..samples_for(0.18))
+        .map(|i| {
+            let t = time(i);
+            let noise = 2.0 * rng.next_f32() - 1.0;
+            (-t * 25.0).exp() * (0.4 * noise + 0.3 * (2.0 * PI * body_hz * t).sin())
+        })
+        .collect()
+}
+
+/// A short burst of first-differenced (high-passed) noise
+fn hi_hat(rng: &mut Rng, amplitude: f32) -> Vec<f32> {
+    let mut last = 0.0;
+    (0..samples_for(0.05))
+        .map(|i| {
+            let noise = 2.0 * rng.next_f32() - 1.0;
+            let high = noise - last;
+            last = noise;
+            amplitude * (-time(i) * 80.0).exp() * high
+        })
+        .collect()
+}
+
+/// A seeded piece of music: a bass note and a melody note on every beat over
+/// chords changing every two beats, with drums. Notes are drawn at random rather
+/// than looped and the tuning and tempo vary, so different seeds share few hashes
+pub fn song(seed: u64, seconds: f32) -> Vec<f32> {
+    rendition(seed, seconds, &Rendition::default())
+}
+
+/// How `rendition` plays a song: the same notes, optionally transposed, at
+/// another tempo, with another timbre and drummer, like a cover or live version
+#[derive(Debug, Clone, Copy)]
+pub struct Rendition {
+    /// Semitones up from the original, or down if negative
+    pub transpose: i8,
+    /// Tempo relative to the original
+    pub tempo: f32,
+    /// Add the second and third harmonics to every note, an organ-like timbre
+    pub harmonics: bool,
+    /// Seed of the drum pattern, the song's own seed in the original
+    pub drum_seed: Option<u64>,
+}
+
+impl Default for Rendition {
+    fn default() -> Self {
+        Self {
+            transpose: 0,
+            tempo: 1.0,
+            harmonics: false,
+            drum_seed: None,
+        }
+    }
+}
+
+/// The song with `seed` as played by `rendition`; the default is `song` itself
+pub fn rendition(seed: u64, seconds: f32, rendition: &Rendition) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    let bpm = (90.0 + 60.0 * rng.next_f32()) * rendition.tempo;
+    // Detuned by up to half a semitone, so songs in the same key don't share bins
+    let tuning = 2f32.powf((rng.next_f32() - 0.5) / 12.0);
+    let beat = 60.0 / bpm;
+    let beats = (seconds / beat).ceil() as usize;
+    let note = |midi: u8| midi_to_hz((midi as i16 + rendition.transpose as i16) as u8) * tuning;
+    let voice = |frequency: f32, seconds: f32, amplitude: f32| {
+        if rendition.harmonics {
+            let partials = [1.0, 2.0, 3.0].map(|n| tone(n * frequency, seconds, amplitude));
+            mix(&[
+                (&partials[0], 0.6),
+                (&partials[1], 0.3),
+                (&partials[2], 0.2),
+            ])
+        } else {
+            tone(frequency, seconds, amplitude)
+        }
+    };
+
+    let mut music = silence(seconds);
+    for b in 0..beats {
+        let start = samples_for(b as f32 * beat);
+        // Bass around G1-G3, below the first peak band edge at ~195 Hz
+        let bass = 31 + rng.below(24) as u8;
+        add_at(&mut music, start, &envelope(voice(note(bass), beat, 0.35)));
+        // Major or minor triads around C4-C5
+        if b % 2 == 0 {
+            let root = 60 + rng.below(12) as u8;
+            let third = if rng.below(2) == 0 { 4 } else { 3 };
+            let frequencies = [note(root), note(root + third), note(root + 7)];
+            add_at(
+                &mut music,
+                start,
+                &envelope(chord(&frequencies, 2.0 * beat)),
+            );
+        }
+        // Melody around C5-C7
+        let melody = 72 + rng.below(24) as u8;
+        add_at(&mut music, start, &envelope(voice(note(melody), beat, 0.3)));
+    }
+
+    let drums = drum_pattern(bpm, seconds, rendition.drum_seed.unwrap_or(seed));
+    let mixed = mix(&[(&music, 0.6), (&drums, 0.4)]);
+    mixed.into_iter().map(|s| s.clamp(-1.0, 1.0)).collect()
+}
+
+/// Sum `layers`, each scaled by its gain, as long as the longest layer
+/// Nothing is clipped, so keep the gains below 1 in total
+pub fn mix(layers: &[(&[f32], f32)]) -> Vec<f32> {
+    let len = layers
+        .iter()
+        .map(|(layer, _)| layer.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = vec![0.0; len];
+    for (layer, gain) in layers {
+        for (o, &s) in out.iter_mut().zip(layer.iter()) {
+            *o += s * gain;
+        }
+    }
+    out
+}
+
+/// `seconds` of `samples` starting at `start` seconds, clamped to the end
+pub fn clip(samples: &[f32], start: f32, seconds: f32) -> &[f32] {
+    let first = samples_for(start).min(samples.len());
+    let last = (first + samples_for(seconds)).min(samples.len());
+    &samples[first..last]
+}
+
+/// A plucked envelope: a 10 ms attack, then an exponential decay with a fade out at the end,
+/// so each note's spectral peaks are strongest at its onset like a real instrument
+fn envelope(mut samples: Vec<f32>) -> Vec<f32> {
+    let fade = samples_for(0.01).min(samples.len() / 2).max(1);
+    let len = samples.len();
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let attack = (i as f32 / fade as f32).min(1.0);
+        let release = ((len - i) as f32 / fade as f32).min(1.0);
+        *sample *= attack * release * (-time(i) * 3.0).exp();
+    }
+    samples
+}
+
+fn add_at(out: &mut [f32], start: usize, sound: &[f32]) {
+    if start >= out.len() {
+        return;
+    }
+    for (o, &s) in out[start..].iter_mut().zip(sound) {
+        *o += s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generators_are_reproducible() {
+        assert_eq!(song(7, 5.0), song(7, 5.0));
+        assert_eq!(white_noise(1.0, 0.5, 3), white_noise(1.0, 0.5, 3));
+        assert_ne!(song(7, 5.0), song(8, 5.0));
+        assert_ne!(drum_pattern(120.0, 2.0, 1), drum_pattern(120.0, 2.0, 2));
+    }
+
+    #[test]
+    fn lengths_follow_the_sample_rate() {
+        assert_eq!(silence(2.0).len(), 2 * SAMPLE_RATE as usize);
+        assert_eq!(song(1, 3.5).len(), samples_for(3.5));
+        assert_eq!(clip(&silence(10.0), 8.0, 5.0).len(), samples_for(2.0));
+    }
+
+    #[test]
+    fn output_stays_in_range() {
+        for samples in [
+            song(3, 10.0),
+            drum_pattern(140.0, 4.0, 9),
+            sweep(50.0, 4000.0, 2.0),
+        ] {
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+            assert!(samples.iter().any(|&s| s.abs() > 0.1));
+        }
+    }
+
+    #[test]
+    fn midi_notes_map_to_concert_pitch() {
+        assert_eq!(midi_to_hz(69), 440.0);
+        assert!((midi_to_hz(60) - 261.63).abs() < 0.01);
+    }
+}
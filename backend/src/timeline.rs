@@ -0,0 +1,327 @@
+use crate::config::Config;
+use crate::error::{AppError, Result};
+use crate::fingerprint::{fingerprint, frames_to_ms, ms_to_samples, stream_audio, SAMPLE_RATE};
+use crate::matcher::{align, confidence};
+use crate::storage::Database;
+use crate::types::{Timeline, TimelineQuery, TimelineSegment};
+use rayon::prelude::*;
+
+pub const DEFAULT_WINDOW_MS: u64 = 10_000;
+pub const DEFAULT_HOP_MS: u64 = 5_000;
+pub const MIN_WINDOW_MS: u64 = 3_000;
+pub const MAX_WINDOW_MS: u64 = 60_000;
+pub const MIN_HOP_MS: u64 = 1_000;
+
+/// Hits on one song whose alignment moves by less than this belong to one segment
+/// Leaves room for the tempo drift of a DJ mix
+const MAX_ALIGNMENT_DRIFT_MS: i64 = 2_000;
+/// Windows without a hit that can be bridged inside a segment, e.g. a noisy transition
+const MAX_GAP_WINDOWS: usize = 1;
+/// Complete windows matched together in parallel; with the defaults the samples
+/// held at a time are about a minute and a half of audio
+const WINDOWS_PER_BATCH: usize = 16;
+
+pub struct TimelineOptions {
+    pub window_ms: u64,
+    pub hop_ms: u64,
+}
+
+impl TimelineOptions {
+    pub fn from_query(query: &TimelineQuery) -> Result<Self> {
+        let window_ms = query.window_ms.unwrap_or(DEFAULT_WINDOW_MS);
+        if !(MIN_WINDOW_MS..=MAX_WINDOW_MS).contains(&window_ms) {
+            return Err(AppError::InvalidRequest(format!(
+                "window_ms must be between {} and {}",
+                MIN_WINDOW_MS, MAX_WINDOW_MS
+            )));
+        }
+
+        let hop_ms = query.hop_ms.unwrap_or(DEFAULT_HOP_MS.min(window_ms));
+        if !(MIN_HOP_MS..=window_ms).contains(&hop_ms) {
+            return Err(AppError::InvalidRequest(format!(
+                "hop_ms must be between {} and window_ms",
+                MIN_HOP_MS
+            )));
+        }
+
+        Ok(Self { window_ms, hop_ms })
+    }
+}
+
+/// Best song of one window that passed the threshold
+struct WindowHit {
+    index: usize,
+    song_id: i64,
+    start_ms: u64,
+    end_ms: u64,
+    /// Position in the song at the start of the window
+    song_offset_ms: i64,
+    raw_score: i64,
+}
+
+impl WindowHit {
+    /// Recording time at which the song would have started; constant while one song plays
+    fn alignment_ms(&self) -> i64 {
+        self.start_ms as i64 - self.song_offset_ms
+    }
+}
+
+/// Slide a window across decoded 16kHz mono samples, match each window,
+/// and merge consecutive hits on the same song into segments
+pub fn recognize_timeline(
+    db: &Database,
+    config: &Config,
+    samples: &[f32],
+    options: &TimelineOptions,
+) -> Result<Timeline> {
+    let mut windows = WindowMatcher::new(db, config, options);
+    windows.push(samples)?;
+    windows.finish()
+}
+
+/// `recognize_timeline` of an audio file of any format, decoded block by block
+/// Only the samples of the windows being matched are held, not the whole recording
+pub fn recognize_timeline_file(
+    db: &Database,
+    config: &Config,
+    path: &str,
+    options: &TimelineOptions,
+) -> Result<Timeline> {
+    let mut windows = WindowMatcher::new(db, config, options);
+    stream_audio(path, |block| windows.push(block))?;
+    windows.finish()
+}
+
+/// Matches windows as samples arrive, a batch at a time, dropping samples
+/// once every window containing them has been matched
+struct WindowMatcher<'a> {
+    db: &'a Database,
+    config: &'a Config,
+    options: &'a TimelineOptions,
+    /// Samples from sample `buffer_start` of the recording on
+    buffer: Vec<f32>,
+    buffer_start: usize,
+    received: usize,
+    /// Windows complete but not matched yet, as (index, start_ms, end_ms)
+    pending: Vec<(usize, u64, u64)>,
+    next_index: usize,
+    hits: Vec<WindowHit>,
+}
+
+impl<'a> WindowMatcher<'a> {
+    fn new(db: &'a Database, config: &'a Config, options: &'a TimelineOptions) -> Self {
+        Self {
+            db,
+            config,
+            options,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            received: 0,
+            pending: Vec::new(),
+            next_index: 0,
+            hits: Vec::new(),
+        }
+    }
+
+    fn window_start_ms(&self, index: usize) -> u64 {
+        index as u64 * self.options.hop_ms
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Result<()> {
+        self.buffer.extend_from_slice(samples);
+        self.received += samples.len();
+
+        // A window ending short of the samples so far can't be the last, clipped one
+        let received_ms = self.received as u64 * 1000 / SAMPLE_RATE as u64;
+        loop {
+            let start_ms = self.window_start_ms(self.next_index);
+            let end_ms = start_ms + self.options.window_ms;
+            if end_ms >= received_ms {
+                break;
+            }
+            self.pending.push((self.next_index, start_ms, end_ms));
+            self.next_index += 1;
+        }
+
+        if self.pending.len() >= WINDOWS_PER_BATCH {
+            self.match_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Match the remaining windows, the last one clipped to the end, and build the timeline
+    fn finish(mut self) -> Result<Timeline> {
+        let duration_ms = self.received as u64 * 1000 / SAMPLE_RATE as u64;
+        loop {
+            let start_ms = self.window_start_ms(self.next_index);
+            let end_ms = (start_ms + self.options.window_ms).min(duration_ms);
+            self.pending.push((self.next_index, start_ms, end_ms));
+            self.next_index += 1;
+            if end_ms >= duration_ms {
+                break;
+            }
+        }
+        self.match_pending()?;
+        self.hits.sort_unstable_by_key(|hit| hit.index);
+
+        let mut segments = Vec::new();
+        for (song_id, hits) in merge_hits(self.hits) {
+            let Some(song) = self.db.get_song_metadata(song_id)? else {
+                continue;
+            };
+            let first = &hits[0];
+            let last = &hits[hits.len() - 1];
+            let raw_score = hits.iter().map(|h| h.raw_score).max().unwrap_or(0);
+
+            segments.push(TimelineSegment {
+                song_id,
+                title: song.title,
+                artist: song.artist,
+                start_ms: first.start_ms,
+                end_ms: last.end_ms,
+                offset_in_song_ms: first.song_offset_ms.max(0) as u64,
+                confidence: confidence(raw_score),
+                windows: hits.len(),
+            });
+        }
+
+        // Overlapping windows make neighbouring segments overlap; cut at the next start
+        for i in 1..segments.len() {
+            let next_start = segments[i].start_ms;
+            let previous = &mut segments[i - 1];
+            previous.end_ms = previous.end_ms.min(next_start).max(previous.start_ms);
+        }
+
+        Ok(Timeline {
+            duration_ms,
+            window_ms: self.options.window_ms,
+            hop_ms: self.options.hop_ms,
+            segments,
+        })
+    }
+
+    /// Match the pending windows in parallel, then drop the samples before the next window
+    fn match_pending(&mut self) -> Result<()> {
+        let (buffer, buffer_start) = (&self.buffer, self.buffer_start);
+        let (db, config) = (self.db, self.config);
+        // Windows are fingerprinted in parallel; lookups still share the database lock
+        let hits = self
+            .pending
+            .par_iter()
+            .map(|&(index, start_ms, end_ms)| {
+                let first = ms_to_samples(start_ms) - buffer_start;
+                let last = (ms_to_samples(end_ms) - buffer_start).min(buffer.len());
+                match_window(db, config, &buffer[first..last], index, start_ms, end_ms)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.hits.extend(hits.into_iter().flatten());
+        self.pending.clear();
+
+        let keep_from = ms_to_samples(self.window_start_ms(self.next_index)).min(self.received);
+        self.buffer.drain(..keep_from - self.buffer_start);
+        self.buffer_start = keep_from;
+        Ok(())
+    }
+}
+
+fn match_window(
+    db: &Database,
+    config: &Config,
+    window: &[f32],
+    index: usize,
+    start_ms: u64,
+    end_ms: u64,
+) -> Result<Option<WindowHit>> {
+    let settings = db.fingerprint_settings();
+    let fingerprints = fingerprint(window, &settings);
+    if fingerprints.is_empty() {
+        return Ok(None);
+    }
+
+    let matches = db
+        .find_matches(&fingerprints, &config.match_options())?
+        .matches;
+    let best = matches
+        .iter()
+        .map(|(&song_id, offsets)| (song_id, align(offsets, settings.scheme)))
+        .max_by_key(|&(song_id, alignment)| (alignment.score, std::cmp::Reverse(song_id)));
+
+    Ok(best
+        .filter(|(_, alignment)| alignment.score > config.match_threshold)
+        .map(|(song_id, alignment)| WindowHit {
+            index,
+            song_id,
+            start_ms,
+            end_ms,
+            song_offset_ms: frames_to_ms(alignment.offset),
+            raw_score: alignment.score,
+        }))
+}
+
+/// Group window hits, in window order, into runs on one song with a steady alignment
+fn merge_hits(hits: Vec<WindowHit>) -> Vec<(i64, Vec<WindowHit>)> {
+    let mut runs: Vec<(i64, Vec<WindowHit>)> = Vec::new();
+
+    for hit in hits {
+        if let Some((song_id, run)) = runs.last_mut() {
+            let last = &run[run.len() - 1];
+            let continues = *song_id == hit.song_id
+                && hit.index - last.index <= MAX_GAP_WINDOWS + 1
+                && (hit.alignment_ms() - last.alignment_ms()).abs() <= MAX_ALIGNMENT_DRIFT_MS;
+            if continues {
+                run.push(hit);
+                continue;
+            }
+        }
+        runs.push((hit.song_id, vec![hit]));
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::index_samples;
+    use crate::metadata::ExtractedMetadata;
+    use crate::test_support::{clip, song};
+
+    #[test]
+    fn streamed_blocks_give_the_same_timeline() {
+        let db = Database::open_in_memory().unwrap();
+        let config = Config::default();
+        let songs: Vec<Vec<f32>> = [51, 52].map(|seed| song(seed, 30.0)).into();
+        for (seed, samples) in [51, 52].iter().zip(&songs) {
+            let metadata = ExtractedMetadata {
+                title: format!("Song {}", seed),
+                artist: "Synthetic".to_string(),
+                details: Default::default(),
+                artwork: None,
+            };
+            index_samples(&db, &format!("synthetic/{}", seed), &metadata, samples).unwrap();
+        }
+        let recording = [clip(&songs[0], 5.0, 20.0), clip(&songs[1], 0.0, 20.0)].concat();
+        // More windows than one batch, so samples are dropped between batches
+        let options = TimelineOptions::from_query(&TimelineQuery {
+            window_ms: Some(4_000),
+            hop_ms: Some(1_000),
+        })
+        .unwrap();
+
+        let whole = recognize_timeline(&db, &config, &recording, &options).unwrap();
+        let mut windows = WindowMatcher::new(&db, &config, &options);
+        for block in recording.chunks(1_000) {
+            windows.push(block).unwrap();
+        }
+        let streamed = windows.finish().unwrap();
+
+        assert_eq!(whole.duration_ms, 40_000);
+        let songs: Vec<i64> = whole.segments.iter().map(|s| s.song_id).collect();
+        assert_eq!(songs.len(), 2);
+        assert_ne!(songs[0], songs[1]);
+        assert_eq!(
+            serde_json::to_value(&whole).unwrap(),
+            serde_json::to_value(&streamed).unwrap()
+        );
+    }
+}
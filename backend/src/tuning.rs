@@ -0,0 +1,228 @@
+use crate::types::{LabelledScore, ThresholdPoint, TuningReport};
+
+/// Below this many labelled queries a recommendation is mostly noise
+pub const MIN_LABELLED_SAMPLES: usize = 20;
+pub const DEFAULT_MIN_PRECISION: f64 = 0.95;
+
+/// Label the top candidate of a query whose song is `expected`, None if it isn't in the library
+/// A library clip without candidates is kept as a miss rather than dropped, which would
+/// overstate recall
+pub fn label_query(expected: Option<i64>, top_candidate: Option<(i64, i64)>) -> LabelledScore {
+    match top_candidate {
+        Some((song_id, raw_score)) => LabelledScore {
+            raw_score,
+            correct: expected == Some(song_id),
+        },
+        None => LabelledScore {
+            raw_score: 0,
+            correct: expected.is_some(),
+        },
+    }
+}
+
+/// Confusion counts at every raw-score threshold from 0 up to the highest score seen
+pub fn sweep_thresholds(samples: &[LabelledScore]) -> Vec<ThresholdPoint> {
+    let max_score = samples.iter().map(|s| s.raw_score).max().unwrap_or(0);
+
+    (0..=max_score)
+        .map(|threshold| threshold_point(samples, threshold))
+        .collect()
+}
+
+fn threshold_point(samples: &[LabelledScore], threshold: i64) -> ThresholdPoint {
+    let (mut tp, mut fp, mut tn, mut fn_) = (0u64, 0u64, 0u64, 0u64);
+    for sample in samples {
+        match (sample.raw_score > threshold, sample.correct) {
+            (true, true) => tp += 1,
+            (true, false) => fp += 1,
+            (false, false) => tn += 1,
+            (false, true) => fn_ += 1,
+        }
+    }
+
+    // No matches at all means no false ones, so precision is perfect
+    let precision = ratio(tp, tp + fp).unwrap_or(1.0);
+    let recall = ratio(tp, tp + fn_).unwrap_or(0.0);
+    let f1 = if precision + recall > 0.0 {
+        2.0 * precision * recall / (precision + recall)
+    } else {
+        0.0
+    };
+
+    ThresholdPoint {
+        threshold,
+        true_positives: tp,
+        false_positives: fp,
+        true_negatives: tn,
+        false_negatives: fn_,
+        precision,
+        recall,
+        false_positive_rate: ratio(fp, fp + tn).unwrap_or(0.0),
+        f1,
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+/// Area under the ROC curve traced by the sweep, by the trapezoid rule
+pub fn roc_auc(points: &[ThresholdPoint]) -> f64 {
+    // Raising the threshold only moves the curve towards (0, 0),
+    // so close it at (1, 1) (accept everything) and (0, 0) (accept nothing)
+    let mut curve: Vec<(f64, f64)> = vec![(1.0, 1.0)];
+    curve.extend(points.iter().map(|p| (p.false_positive_rate, p.recall)));
+    curve.push((0.0, 0.0));
+
+    curve
+        .windows(2)
+        .map(|w| (w[0].0 - w[1].0) * (w[0].1 + w[1].1) / 2.0)
+        .sum()
+}
+
+/// Threshold with the best recall whose precision meets `min_precision`,
+/// falling back to the best F1 when none does
+/// Ties go to the lower threshold, which accepts unseen scores in between
+pub fn recommend(points: &[ThresholdPoint], min_precision: f64) -> Option<&ThresholdPoint> {
+    let precise = points
+        .iter()
+        .filter(|p| p.true_positives > 0 && p.precision >= min_precision);
+
+    best_by(precise, |p| p.recall)
+        .or_else(|| best_by(points.iter().filter(|p| p.f1 > 0.0), |p| p.f1))
+}
+
+/// First point with the highest `key`; points are in threshold order
+fn best_by<'a>(
+    points: impl Iterator<Item = &'a ThresholdPoint>,
+    key: impl Fn(&ThresholdPoint) -> f64,
+) -> Option<&'a ThresholdPoint> {
+    points.fold(None, |best, p| match best {
+        Some(b) if key(b) >= key(p) => Some(b),
+        _ => Some(p),
+    })
+}
+
+/// Sweep, ROC and recommendation for a set of labelled queries
+pub fn tune(samples: &[LabelledScore], min_precision: f64) -> TuningReport {
+    let points = sweep_thresholds(samples);
+    let positives = samples.iter().filter(|s| s.correct).count();
+
+    TuningReport {
+        samples: samples.len(),
+        positives,
+        negatives: samples.len() - positives,
+        auc: roc_auc(&points),
+        min_precision,
+        recommended: recommend(&points, min_precision).cloned(),
+        points,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labelled(positives: &[i64], negatives: &[i64]) -> Vec<LabelledScore> {
+        let correct = positives.iter().map(|&raw_score| LabelledScore {
+            raw_score,
+            correct: true,
+        });
+        let wrong = negatives.iter().map(|&raw_score| LabelledScore {
+            raw_score,
+            correct: false,
+        });
+        correct.chain(wrong).collect()
+    }
+
+    #[test]
+    fn separable_scores_give_a_perfect_auc() {
+        let report = tune(&labelled(&[10, 12, 20], &[2, 3, 5]), 0.95);
+        assert_eq!(report.auc, 1.0);
+
+        // Every threshold from the top negative up to below the lowest positive separates them
+        let recommended = report.recommended.unwrap();
+        assert_eq!(recommended.threshold, 5);
+        assert_eq!(recommended.precision, 1.0);
+        assert_eq!(recommended.recall, 1.0);
+    }
+
+    #[test]
+    fn tied_scores_count_half_towards_the_auc() {
+        // Of the 4 positive/negative pairs, 3 are ranked right and 5 vs 5 is a tie: 3.5 / 4
+        let samples = labelled(&[5, 8], &[2, 5]);
+        let report = tune(&samples, 0.95);
+        assert!((report.auc - 0.875).abs() < 1e-9);
+
+        // A score only matches when it's above the threshold, so both 5s flip together
+        let at_4 = threshold_point(&samples, 4);
+        assert_eq!((at_4.true_positives, at_4.false_positives), (2, 1));
+        let at_5 = threshold_point(&samples, 5);
+        assert_eq!((at_5.true_positives, at_5.false_positives), (1, 0));
+    }
+
+    #[test]
+    fn a_missing_class_does_not_panic() {
+        let report = tune(&[], 0.95);
+        assert_eq!(report.samples, 0);
+        assert_eq!(report.points.len(), 1);
+        assert!(report.recommended.is_none());
+
+        let report = tune(&labelled(&[4, 6], &[]), 0.95);
+        assert_eq!(report.negatives, 0);
+        assert_eq!(report.auc, 1.0);
+        assert!(report.points.iter().all(|p| p.false_positive_rate == 0.0));
+        assert_eq!(report.recommended.unwrap().threshold, 0);
+
+        // Nothing can be a true positive, so nothing is worth recommending
+        let report = tune(&labelled(&[], &[3, 9]), 0.95);
+        assert_eq!(report.positives, 0);
+        assert!(report.points.iter().all(|p| p.recall == 0.0));
+        assert!(report.recommended.is_none());
+    }
+
+    #[test]
+    fn library_queries_without_candidates_are_missed_at_every_threshold() {
+        let missed = label_query(Some(3), None);
+        assert_eq!(missed.raw_score, 0);
+        let rejected = label_query(None, None);
+        assert!(!rejected.correct);
+        assert!(label_query(Some(3), Some((3, 12))).correct);
+        assert!(!label_query(Some(3), Some((4, 12))).correct);
+
+        // With the miss, recall can't reach 1 and precision is unchanged
+        let mut samples = labelled(&[10, 12], &[2]);
+        samples.extend([missed, rejected]);
+        let points = sweep_thresholds(&samples);
+        for point in &points {
+            assert!(point.false_negatives >= 1, "threshold {}", point.threshold);
+        }
+        let recommended = recommend(&points, 0.95).unwrap();
+        assert_eq!(recommended.threshold, 2);
+        assert_eq!(recommended.precision, 1.0);
+        assert!((recommended.recall - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn recommendation_meets_the_requested_precision() {
+        let samples = labelled(&[5, 6, 7, 8, 9, 10, 11, 12, 13, 14], &[2, 3, 4, 8, 10]);
+        let points = sweep_thresholds(&samples);
+
+        // Precision first reaches 0.9 at 10, where only 11-14 are accepted: 4 of 4, recall 0.4
+        let strict = recommend(&points, 0.9).unwrap();
+        assert_eq!(strict.threshold, 10);
+        assert_eq!(strict.precision, 1.0);
+        assert!((strict.recall - 0.4).abs() < 1e-9);
+
+        // At 4 all 10 positives and the negatives 8 and 10 are accepted: 10 / 12 > 0.8
+        let lenient = recommend(&points, 0.8).unwrap();
+        assert_eq!(lenient.threshold, 4);
+        assert!(lenient.precision >= 0.8);
+        assert_eq!(lenient.recall, 1.0);
+
+        // No threshold can reach a precision above 1, so it falls back to the best F1
+        let fallback = recommend(&points, 1.1).unwrap();
+        let best_f1 = points.iter().map(|p| p.f1).fold(0.0, f64::max);
+        assert_eq!(fallback.f1, best_f1);
+    }
+}
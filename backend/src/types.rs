@@ -1,9 +1,17 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
 
 #[derive(Debug, Serialize)]
 pub struct RecognitionResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#match: Option<MatchResult>,
+    /// History log id, used to send feedback on this result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recognition_id: Option<i64>,
+    /// The deadline passed before every hash was looked up; the result is the best so far
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deadline_exceeded: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -11,6 +19,19 @@ pub struct MatchResult {
     pub title: String,
     pub artist: String,
     pub score: f32,
+    pub artwork_url: Option<String>,
+    /// Semitones the query is above the song, for `mode=cover` matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transposition: Option<i8>,
+    #[serde(flatten)]
+    pub details: TrackDetails,
+}
+
+/// Query of `POST /recognize`
+#[derive(Debug, Default, Deserialize)]
+pub struct RecognizeQuery {
+    /// `landmark` (default) for the recording itself, `cover` for covers and live versions
+    pub mode: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -20,4 +41,579 @@ pub struct SongMetadata {
     pub artist: String,
     pub path: String,
     pub created_at: String,
+    #[serde(flatten)]
+    pub details: TrackDetails,
+}
+
+/// One page of `GET /songs`
+#[derive(Debug, Serialize)]
+pub struct SongPage {
+    pub songs: Vec<SongMetadata>,
+    /// Pass as `cursor` to fetch the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Query parameters of `GET /songs`
+#[derive(Debug, Default, Deserialize)]
+pub struct SongListQuery {
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub sort: SongSort,
+    pub order: Option<SortOrder>,
+    /// Exact artist match, case-insensitive
+    pub artist: Option<String>,
+    /// Full-text search over title, artist and album
+    pub q: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SongSort {
+    Title,
+    Artist,
+    #[default]
+    Date,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Optional tag data beyond title and artist
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrackDetails {
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub isrc: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SongMetadataUpdate {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// Also write the corrected tags back into the audio file
+    #[serde(default)]
+    pub write_tags: bool,
+}
+
+/// Embedded cover image of a song
+#[derive(Debug, Clone)]
+pub struct Artwork {
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// How an indexed file was recognised as a copy of an existing song
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Decoded audio is bit-identical
+    ContentHash,
+    /// Enough fingerprints align with an existing song
+    Fingerprint,
+}
+
+impl DuplicateKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DuplicateKind::ContentHash => "content_hash",
+            DuplicateKind::Fingerprint => "fingerprint",
+        }
+    }
+}
+
+/// A file that was linked to an existing song instead of being indexed
+#[derive(Debug, Serialize)]
+pub struct DuplicateSong {
+    pub path: String,
+    pub song_id: i64,
+    pub kind: String,
+    pub created_at: String,
+}
+
+/// Endpoint a logged recognition came through
+/// `mode=cover` recognitions aren't logged: their chroma similarity isn't a hash count,
+/// so it can't be compared with the other scores or tuned from feedback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecognitionSource {
+    /// `POST /recognize`
+    Http,
+    /// `/ws`
+    Ws,
+    /// `POST /recognize/fingerprints`
+    Fingerprints,
+    /// `POST /recognize/batch`, one entry per file
+    Batch,
+}
+
+impl RecognitionSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecognitionSource::Http => "http",
+            RecognitionSource::Ws => "ws",
+            RecognitionSource::Fingerprints => "fingerprints",
+            RecognitionSource::Batch => "batch",
+        }
+    }
+}
+
+/// A recognition attempt to be written to the history log
+#[derive(Debug)]
+pub struct NewRecognition<'a> {
+    pub source: RecognitionSource,
+    pub client_id: Option<&'a str>,
+    pub query_duration_ms: u64,
+    pub hash_count: usize,
+    pub top_song_id: Option<i64>,
+    pub raw_score: i64,
+    pub confidence: f32,
+    pub latency_ms: u64,
+    pub matched: bool,
+    /// The lookup stopped at the deadline, so the scores come from part of the query
+    pub deadline_exceeded: bool,
+    /// Best scoring candidates as (song_id, raw score), best first
+    pub candidates: &'a [(i64, i64)],
+}
+
+/// A logged recognition attempt
+#[derive(Debug, Serialize)]
+pub struct RecognitionRecord {
+    pub id: i64,
+    pub created_at: String,
+    pub source: String,
+    pub client_id: Option<String>,
+    pub query_duration_ms: u64,
+    pub hash_count: u64,
+    pub top_song_id: Option<i64>,
+    pub raw_score: i64,
+    pub confidence: f32,
+    pub latency_ms: u64,
+    pub matched: bool,
+    pub verdict: Option<Verdict>,
+    pub correct_song_id: Option<i64>,
+}
+
+/// One page of `GET /recognitions`, newest first
+#[derive(Debug, Serialize)]
+pub struct RecognitionPage {
+    pub recognitions: Vec<RecognitionRecord>,
+    pub next_cursor: Option<String>,
+}
+
+/// Query parameters of `GET /recognitions`
+#[derive(Debug, Default, Deserialize)]
+pub struct RecognitionListQuery {
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+    pub matched: Option<bool>,
+    pub client_id: Option<String>,
+    pub source: Option<RecognitionSource>,
+    /// Top candidate song, whether or not it passed the threshold
+    pub song_id: Option<i64>,
+    /// Inclusive lower bound on `created_at` (`YYYY-MM-DD HH:MM:SS`, UTC)
+    pub since: Option<String>,
+    /// Exclusive upper bound on `created_at`
+    pub until: Option<String>,
+}
+
+/// Query parameters of `GET /stats/recognitions`
+#[derive(Debug, Default, Deserialize)]
+pub struct RecognitionStatsQuery {
+    /// Inclusive lower bound on `created_at` (`YYYY-MM-DD HH:MM:SS`, UTC)
+    pub since: Option<String>,
+    /// Exclusive upper bound on `created_at`
+    pub until: Option<String>,
+    #[serde(default)]
+    pub bucket: StatsBucket,
+    /// Length of the top songs / failed sources lists
+    pub top: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsBucket {
+    Hour,
+    #[default]
+    Day,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecognitionStats {
+    pub total: u64,
+    pub matched: u64,
+    /// matched / total, 0 when there were no recognitions
+    pub match_rate: f64,
+    pub latency_ms: LatencyPercentiles,
+    pub buckets: Vec<TimeBucket>,
+    pub top_songs: Vec<SongCount>,
+    pub failed_sources: Vec<SourceFailures>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LatencyPercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeBucket {
+    pub start: String,
+    pub total: u64,
+    pub matched: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SongCount {
+    pub song_id: i64,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub count: u64,
+}
+
+/// Unmatched queries grouped by where they came from
+#[derive(Debug, Serialize)]
+pub struct SourceFailures {
+    pub source: String,
+    pub client_id: Option<String>,
+    pub failures: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibraryStats {
+    pub song_count: u64,
+    pub fingerprint_count: u64,
+    pub avg_hashes_per_song: f64,
+    pub db_size_bytes: u64,
+    /// Hashes found in the most songs, ties broken by hash value
+    pub hottest_hashes: Vec<HashCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashCount {
+    pub hash: u32,
+    /// Fingerprints with this hash
+    pub count: u64,
+    /// Distinct songs containing it
+    pub song_count: u64,
+}
+
+/// User feedback on a recognition result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// The returned match was right
+    Correct,
+    /// The returned match was wrong, or nothing was returned for a known song
+    Wrong,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Correct => "correct",
+            Verdict::Wrong => "wrong",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "correct" => Some(Verdict::Correct),
+            "wrong" => Some(Verdict::Wrong),
+            _ => None,
+        }
+    }
+}
+
+/// Body of `POST /recognitions/{id}/feedback`
+#[derive(Debug, Deserialize)]
+pub struct FeedbackRequest {
+    pub verdict: Verdict,
+    /// The song the query actually was, if known
+    pub correct_song_id: Option<i64>,
+}
+
+/// A song that feedback says was returned wrongly or missed
+#[derive(Debug, Serialize)]
+pub struct MisidentifiedSong {
+    pub song_id: i64,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// Times it was returned as the match but marked wrong
+    pub wrong_matches: u64,
+    /// Times it was named as the correct song of a wrong or missing result
+    pub missed: u64,
+}
+
+/// A query whose true song is known, from feedback or an evaluation set
+#[derive(Debug, Clone, Copy)]
+pub struct LabelledScore {
+    /// Raw score of the top candidate, 0 without candidates
+    pub raw_score: i64,
+    /// Whether the top candidate was the right song; a query of a library song that got no
+    /// candidates also counts, scored 0 so it's a miss at every threshold
+    pub correct: bool,
+}
+
+/// Confusion counts and derived rates at one raw-score threshold
+/// A query counts as matched when its top candidate scores above the threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdPoint {
+    pub threshold: i64,
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub true_negatives: u64,
+    pub false_negatives: u64,
+    pub precision: f64,
+    pub recall: f64,
+    /// False positive rate, the x axis of the ROC curve (recall is the y axis)
+    pub false_positive_rate: f64,
+    pub f1: f64,
+}
+
+/// Output of the `tune` command
+#[derive(Debug, Serialize)]
+pub struct TuningReport {
+    pub samples: usize,
+    pub positives: usize,
+    pub negatives: usize,
+    /// Area under the ROC curve
+    pub auc: f64,
+    pub min_precision: f64,
+    pub recommended: Option<ThresholdPoint>,
+    pub points: Vec<ThresholdPoint>,
+}
+
+/// Candidate songs from `find_matches`, each with its aligned hits as
+/// (db_offset, query_offset, weight)
+pub type Matches = HashMap<i64, Vec<(u32, u32, f32)>>;
+
+/// Hits found by `find_matches`
+pub struct Lookup {
+    pub matches: Matches,
+    /// The deadline passed before every query hash was looked up
+    pub truncated: bool,
+}
+
+/// How query hashes are filtered and weighted when looking up matches
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    /// Skip hashes found in more songs than this
+    pub max_hash_songs: Option<u64>,
+    /// Weight each hit by the inverse document frequency of its hash instead of 1
+    pub idf_weighting: bool,
+    /// Only align the songs with the most hash hits, up to this many
+    /// and never more than `MAX_CANDIDATES_LIMIT`
+    pub max_candidates: Option<usize>,
+    /// Stop looking up hashes at this point and score what was found so far
+    pub deadline: Option<Instant>,
+}
+
+/// Query of `POST /recognize/timeline`
+#[derive(Debug, Default, Deserialize)]
+pub struct TimelineQuery {
+    pub window_ms: Option<u64>,
+    pub hop_ms: Option<u64>,
+}
+
+/// A stretch of a long recording identified as one song
+#[derive(Debug, Serialize)]
+pub struct TimelineSegment {
+    pub song_id: i64,
+    pub title: String,
+    pub artist: String,
+    /// Position in the recording, accurate to about one hop
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// Position in the song at `start_ms`
+    pub offset_in_song_ms: u64,
+    /// Confidence of the best matching window (0-1)
+    pub confidence: f32,
+    /// Number of windows merged into the segment
+    pub windows: usize,
+}
+
+/// Songs found in a long recording, in order
+#[derive(Debug, Serialize)]
+pub struct Timeline {
+    pub duration_ms: u64,
+    pub window_ms: u64,
+    pub hop_ms: u64,
+    pub segments: Vec<TimelineSegment>,
+}
+
+/// Result for one file of `POST /recognize/batch`
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    /// Upload filename, or the path inside the archive it came from
+    pub name: String,
+    pub r#match: Option<MatchResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recognition_id: Option<i64>,
+    /// Why the file couldn't be recognized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub matched: usize,
+    pub unmatched: usize,
+    pub failed: usize,
+    /// In upload order
+    pub results: Vec<BatchResult>,
+}
+
+/// Fingerprints computed by a client, sent instead of audio
+/// Body of `POST /recognize/fingerprints` as JSON; see `wire` for the binary form
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FingerprintPayload {
+    /// `FINGERPRINT_VERSION` the client computed them with
+    pub version: u16,
+    /// Length of the recorded clip, estimated from the offsets if missing
+    pub duration_ms: Option<u64>,
+    /// (hash, offset) pairs as returned by `generate_fingerprints`
+    pub fingerprints: Vec<(u32, u32)>,
+}
+
+/// Why `POST /recognize/explain` did or didn't return a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchDecision {
+    /// No peaks strong enough to hash, e.g. silence or a clip shorter than one window
+    NoFingerprints,
+    /// None of the query's hashes are in the index (or all were too common to use)
+    NoCandidates,
+    /// The best candidate didn't reach the threshold
+    BelowThreshold,
+    Matched,
+}
+
+/// One distinct hash of the query and how often the index contains it
+#[derive(Debug, Serialize)]
+pub struct HashHits {
+    pub hash: u32,
+    /// Times the hash occurs in the query
+    pub occurrences: usize,
+    /// Fingerprints in the index with this hash
+    pub db_hits: u64,
+    /// Distinct songs containing it
+    pub song_count: u64,
+    /// Left out of matching for being in more than `max_hash_songs` songs
+    pub skipped: bool,
+}
+
+/// One bin of a candidate's offset histogram
+#[derive(Debug, Serialize)]
+pub struct HistogramBin {
+    /// Song frame lining up with the start of the query
+    pub relative_offset: i64,
+    /// The same offset in milliseconds into the song
+    pub offset_ms: i64,
+    /// Hashes agreeing on this offset, weighted when IDF weighting is on
+    pub votes: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CandidateExplanation {
+    pub song_id: i64,
+    pub title: String,
+    pub artist: String,
+    /// Largest histogram bin, the score compared with the threshold
+    pub raw_score: i64,
+    pub confidence: f32,
+    /// Query hashes found in this song at any offset
+    pub hash_hits: usize,
+    /// Song seconds per query second of the best alignment; always 1 unless the
+    /// index hashes triplets, e.g. 1.25 for a clip sped up by a quarter
+    pub time_scale: f64,
+    /// Largest bins at that time scale, largest first
+    pub histogram: Vec<HistogramBin>,
+}
+
+/// Response of `POST /recognize/explain`
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub query_duration_ms: u64,
+    pub peak_count: usize,
+    pub hash_count: usize,
+    pub distinct_hashes: usize,
+    /// Distinct hashes not in the index at all
+    pub hashes_without_hits: usize,
+    /// Distinct hashes dropped for being too common
+    pub hashes_skipped: usize,
+    /// Songs given a full offset histogram, at most `max_candidates`
+    pub aligned_candidates: usize,
+    pub threshold: i64,
+    pub best_score: i64,
+    pub decision: MatchDecision,
+    /// Best candidates, best first, including those below the threshold
+    pub candidates: Vec<CandidateExplanation>,
+    /// Every distinct query hash, most hits first
+    pub hashes: Vec<HashHits>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SpectrogramQuery {
+    /// `png` (default), `json`, `npy` or `npz`
+    pub format: Option<String>,
+    /// Song id whose aligned hashes are highlighted among the query peaks
+    pub reference: Option<i64>,
+    /// Highest frequency bin kept, 512 (about 2 kHz, the hashed range) by default
+    pub max_bin: Option<usize>,
+}
+
+/// One `find_peaks` peak of the constellation map
+#[derive(Debug, Serialize)]
+pub struct PeakPoint {
+    pub frame: usize,
+    pub bin: usize,
+    pub time_ms: i64,
+    pub freq_hz: f32,
+    /// Part of a hash that aligns with the reference track
+    pub matched: bool,
+}
+
+/// The reference track peaks were matched against
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceOverlay {
+    pub song_id: i64,
+    pub title: String,
+    pub artist: String,
+    /// Song frame lining up with the start of the query
+    pub relative_offset: i64,
+    /// Song frames per query frame, 1 unless the index hashes triplets
+    pub time_scale: f64,
+    /// Query hashes agreeing on that alignment
+    pub aligned_hashes: usize,
+}
+
+/// JSON export of a spectrogram and its constellation map
+#[derive(Debug, Serialize)]
+pub struct SpectrogramExport {
+    pub frames: usize,
+    pub bins: usize,
+    /// Milliseconds between frames
+    pub frame_ms: i64,
+    /// Hz between bins
+    pub bin_hz: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<ReferenceOverlay>,
+    pub peaks: Vec<PeakPoint>,
+    /// Magnitudes as [frame][bin]
+    pub spectrogram: Vec<Vec<f32>>,
 }
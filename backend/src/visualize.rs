@@ -0,0 +1,381 @@
+//! Spectrogram and constellation map export, for tuning the peak picker
+//!
+//! Renders the (preprocessed) `spectrogram` with the `find_peaks` peaks on top as PNG, or exports
+//! the raw data as JSON, NPY (the spectrogram) or NPZ (spectrogram and peaks).
+//! Given a reference song, the peaks of query hashes that align with it are marked.
+
+use crate::error::{AppError, Result};
+use crate::fingerprint::{
+    bin_to_hz, conditioned_spectrogram, find_peaks, frames_to_ms, hash_peak_pair, hash_peaks,
+    triplets, FingerprintSettings, HashScheme, FREQUENCY_BINS,
+};
+use crate::matcher::align;
+use crate::storage::Database;
+use crate::types::{PeakPoint, ReferenceOverlay, SpectrogramExport};
+use image::{ImageFormat, Rgb, RgbImage};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+/// Bins hashed by `hash_peaks`, about 2 kHz
+pub const DEFAULT_MAX_BIN: usize = 512;
+/// Frames are stretched up to this many pixels wide while the image stays under `MAX_PNG_WIDTH`
+const MAX_FRAME_WIDTH: u32 = 4;
+const MAX_PNG_WIDTH: u32 = 4096;
+/// Magnitudes this far below the loudest bin render black
+const DYNAMIC_RANGE_DB: f32 = 80.0;
+
+const PEAK_COLOR: Rgb<u8> = Rgb([0, 255, 255]);
+const MATCHED_PEAK_COLOR: Rgb<u8> = Rgb([0, 255, 0]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Json,
+    Npy,
+    Npz,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format.to_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "json" => Ok(Self::Json),
+            "npy" => Ok(Self::Npy),
+            "npz" => Ok(Self::Npz),
+            _ => Err(AppError::InvalidRequest(format!(
+                "Unknown format {}, expected png, json, npy or npz",
+                format
+            ))),
+        }
+    }
+
+    /// Format implied by a file extension, if it's one of ours
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = Path::new(path).extension()?.to_string_lossy();
+        Self::parse(&ext).ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Json => "json",
+            Self::Npy => "npy",
+            Self::Npz => "npz",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Json => "application/json",
+            Self::Npy | Self::Npz => "application/octet-stream",
+        }
+    }
+}
+
+/// Spectrogram of a clip with its peaks, and those matching a reference song
+pub struct Constellation {
+    pub spectrogram: Vec<Vec<f32>>,
+    pub peaks: Vec<(usize, usize)>,
+    pub matched: HashSet<(usize, usize)>,
+    pub reference: Option<ReferenceOverlay>,
+}
+
+/// Compute the spectrogram and peaks of decoded 16kHz mono samples, after the
+/// same preprocessing as the index so the picture shows what gets hashed
+/// With a reference song, mark the peaks of query hashes found in it at the best alignment
+pub fn constellation(
+    samples: &[f32],
+    settings: &FingerprintSettings,
+    reference: Option<(&Database, i64)>,
+) -> Result<Constellation> {
+    let spectrogram = conditioned_spectrogram(samples, &settings.preprocessing);
+    let peaks = find_peaks(&spectrogram);
+
+    let mut constellation = Constellation {
+        spectrogram,
+        peaks,
+        matched: HashSet::new(),
+        reference: None,
+    };
+    if let Some((db, song_id)) = reference {
+        mark_matched_peaks(db, song_id, settings.scheme, &mut constellation)?;
+    }
+    Ok(constellation)
+}
+
+fn mark_matched_peaks(
+    db: &Database,
+    song_id: i64,
+    scheme: HashScheme,
+    constellation: &mut Constellation,
+) -> Result<()> {
+    let song = db
+        .get_song_metadata(song_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Song {} not found", song_id)))?;
+
+    let fingerprints = hashes_with_peaks(constellation.peaks.clone(), scheme);
+    let hashes: Vec<u32> = fingerprints.iter().map(|hashed| hashed.hash).collect();
+    let song_offsets = db.song_hash_offsets(song_id, &hashes)?;
+
+    // Every (song offset, query offset) pair, remembering which query hash it came from
+    let mut pairs = Vec::new();
+    let mut sources = Vec::new();
+    for (index, hashed) in fingerprints.iter().enumerate() {
+        for &db_offset in song_offsets.get(&hashed.hash).into_iter().flatten() {
+            pairs.push((db_offset, hashed.offset, 1.0));
+            sources.push(index);
+        }
+    }
+
+    let alignment = align(&pairs, scheme);
+    let mut aligned_hashes = 0;
+    for (&(db_offset, query_offset, _), &index) in pairs.iter().zip(&sources) {
+        if alignment.contains(db_offset, query_offset) {
+            constellation
+                .matched
+                .extend(fingerprints[index].peaks.iter().copied());
+            aligned_hashes += 1;
+        }
+    }
+
+    constellation.reference = Some(ReferenceOverlay {
+        song_id,
+        title: song.title,
+        artist: song.artist,
+        relative_offset: alignment.offset,
+        time_scale: alignment.time_scale,
+        aligned_hashes,
+    });
+    Ok(())
+}
+
+/// A query hash with the peaks it was computed from
+struct HashedPeaks {
+    hash: u32,
+    offset: u32,
+    peaks: Vec<(usize, usize)>,
+}
+
+fn hashes_with_peaks(peaks: Vec<(usize, usize)>, scheme: HashScheme) -> Vec<HashedPeaks> {
+    match scheme {
+        HashScheme::Pairs => hash_peaks(peaks)
+            .into_iter()
+            .map(|(hash, offset)| {
+                let (first, second) = hash_peak_pair(hash, offset);
+                HashedPeaks {
+                    hash,
+                    offset,
+                    peaks: vec![first, second],
+                }
+            })
+            .collect(),
+        HashScheme::Triplets => triplets(peaks)
+            .into_iter()
+            .map(|triplet| HashedPeaks {
+                hash: triplet.hash,
+                offset: triplet.peaks[0].0 as u32,
+                peaks: triplet.peaks.to_vec(),
+            })
+            .collect(),
+    }
+}
+
+/// Check a requested bin limit and default it to the hashed range
+pub fn max_bin(requested: Option<usize>) -> Result<usize> {
+    let max_bin = requested.unwrap_or(DEFAULT_MAX_BIN);
+    if !(1..=FREQUENCY_BINS).contains(&max_bin) {
+        return Err(AppError::InvalidRequest(format!(
+            "max_bin must be between 1 and {}",
+            FREQUENCY_BINS
+        )));
+    }
+    Ok(max_bin)
+}
+
+/// Encode the constellation in `format`, keeping bins below `max_bin`
+pub fn export(
+    constellation: &Constellation,
+    format: ExportFormat,
+    max_bin: usize,
+) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Png => render_png(constellation, max_bin),
+        ExportFormat::Json => Ok(serde_json::to_vec(&to_json(constellation, max_bin))?),
+        ExportFormat::Npy => Ok(spectrogram_npy(&constellation.spectrogram, max_bin)),
+        ExportFormat::Npz => to_npz(constellation, max_bin),
+    }
+}
+
+fn visible_peaks(
+    constellation: &Constellation,
+    max_bin: usize,
+) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+    constellation
+        .peaks
+        .iter()
+        .filter(move |&&(_, bin)| bin < max_bin)
+        .map(|&peak| (peak.0, peak.1, constellation.matched.contains(&peak)))
+}
+
+fn to_json(constellation: &Constellation, max_bin: usize) -> SpectrogramExport {
+    let bins = max_bin.min(FREQUENCY_BINS);
+    SpectrogramExport {
+        frames: constellation.spectrogram.len(),
+        bins,
+        frame_ms: frames_to_ms(1),
+        bin_hz: bin_to_hz(1),
+        reference: constellation.reference.clone(),
+        peaks: visible_peaks(constellation, bins)
+            .map(|(frame, bin, matched)| PeakPoint {
+                frame,
+                bin,
+                time_ms: frames_to_ms(frame as i64),
+                freq_hz: bin_to_hz(bin),
+                matched,
+            })
+            .collect(),
+        spectrogram: constellation
+            .spectrogram
+            .iter()
+            .map(|frame| frame[..bins].to_vec())
+            .collect(),
+    }
+}
+
+/// Log-magnitude spectrogram, time left to right and low frequencies at the bottom,
+/// with peaks as crosses: cyan, or larger and green when matched
+fn render_png(constellation: &Constellation, max_bin: usize) -> Result<Vec<u8>> {
+    let spec = &constellation.spectrogram;
+    if spec.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Audio is too short for a spectrogram".to_string(),
+        ));
+    }
+    let bins = max_bin.min(FREQUENCY_BINS);
+    let frame_width = (MAX_PNG_WIDTH / spec.len() as u32).clamp(1, MAX_FRAME_WIDTH);
+    let width = spec.len() as u32 * frame_width;
+    let height = bins as u32;
+
+    let to_db = |magnitude: f32| 20.0 * (magnitude + 1e-6).log10();
+    let loudest = spec
+        .iter()
+        .flat_map(|frame| frame[..bins].iter())
+        .map(|&m| to_db(m))
+        .fold(f32::MIN, f32::max);
+
+    let mut image = RgbImage::new(width, height);
+    for (t, frame) in spec.iter().enumerate() {
+        for (bin, &magnitude) in frame[..bins].iter().enumerate() {
+            let level = ((to_db(magnitude) - loudest + DYNAMIC_RANGE_DB) / DYNAMIC_RANGE_DB)
+                .clamp(0.0, 1.0);
+            let color = colormap(level);
+            let y = height - 1 - bin as u32;
+            for dx in 0..frame_width {
+                image.put_pixel(t as u32 * frame_width + dx, y, color);
+            }
+        }
+    }
+
+    for (t, bin, matched) in visible_peaks(constellation, bins) {
+        let x = (t as u32 * frame_width + frame_width / 2) as i64;
+        let y = (height - 1 - bin as u32) as i64;
+        let (arm, color) = if matched {
+            (4, MATCHED_PEAK_COLOR)
+        } else {
+            (2, PEAK_COLOR)
+        };
+        for d in -arm..=arm {
+            put_pixel_clipped(&mut image, x + d, y, color);
+            put_pixel_clipped(&mut image, x, y + d, color);
+        }
+    }
+
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| AppError::Internal(format!("Failed to encode spectrogram: {}", e)))?;
+    Ok(bytes)
+}
+
+fn put_pixel_clipped(image: &mut RgbImage, x: i64, y: i64, color: Rgb<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+/// Black through purple and orange to pale yellow, for `level` in 0-1
+fn colormap(level: f32) -> Rgb<u8> {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 4.0],
+        [87.0, 16.0, 110.0],
+        [188.0, 55.0, 84.0],
+        [249.0, 142.0, 9.0],
+        [252.0, 255.0, 164.0],
+    ];
+    let position = level * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let fraction = position - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    Rgb([0, 1, 2].map(|c| (from[c] + (to[c] - from[c]) * fraction).round() as u8))
+}
+
+/// NPY v1.0 file of a little endian 2D array, `descr` being its numpy dtype
+fn npy(descr: &str, shape: (usize, usize), data: &[u8]) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, shape.0, shape.1
+    );
+    // Magic, version and length take 10 bytes; the header is padded so data starts 64-byte aligned
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + data.len());
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// float32 array of shape (frames, bins)
+fn spectrogram_npy(spec: &[Vec<f32>], max_bin: usize) -> Vec<u8> {
+    let bins = max_bin.min(FREQUENCY_BINS);
+    let data: Vec<u8> = spec
+        .iter()
+        .flat_map(|frame| frame[..bins].iter())
+        .flat_map(|m| m.to_le_bytes())
+        .collect();
+    npy("<f4", (spec.len(), bins), &data)
+}
+
+/// `spectrogram.npy` plus `peaks.npy`, an int32 array of (frame, bin, matched) rows
+fn to_npz(constellation: &Constellation, max_bin: usize) -> Result<Vec<u8>> {
+    let peaks: Vec<(usize, usize, bool)> = visible_peaks(constellation, max_bin).collect();
+    let peak_data: Vec<u8> = peaks
+        .iter()
+        .flat_map(|&(frame, bin, matched)| [frame as i32, bin as i32, matched as i32])
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    let files = [
+        (
+            "spectrogram.npy",
+            spectrogram_npy(&constellation.spectrogram, max_bin),
+        ),
+        ("peaks.npy", npy("<i4", (peaks.len(), 3), &peak_data)),
+    ];
+
+    let fail = |e: std::io::Error| AppError::Internal(format!("Failed to write npz: {}", e));
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files {
+        archive
+            .start_file(name, zip::write::SimpleFileOptions::default())
+            .map_err(|e| fail(e.into()))?;
+        archive.write_all(&data).map_err(fail)?;
+    }
+    let cursor = archive.finish().map_err(|e| fail(e.into()))?;
+    Ok(cursor.into_inner())
+}
@@ -1,4 +1,5 @@
 use crate::error::{AppError, Result};
+use crate::fingerprint::is_audio_file;
 use notify::{Watcher, RecommendedWatcher, RecursiveMode, Event, EventKind};
 use std::path::Path;
 use std::sync::Arc;
@@ -58,18 +59,6 @@ impl FileWatcher {
     }
 }
 
-fn is_audio_file(path: &Path) -> bool {
-    if let Some(ext) = path.extension() {
-        let ext_lower = ext.to_string_lossy().to_lowercase();
-        matches!(
-            ext_lower.as_str(),
-            "mp3" | "wav" | "flac" | "m4a" | "aac" | "ogg" | "opus" | "wma"
-        )
-    } else {
-        false
-    }
-}
-
 pub async fn start_watcher(
     watch_path: &str,
     handler: Arc<dyn Fn(String) + Send + Sync>,
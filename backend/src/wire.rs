@@ -0,0 +1,213 @@
+//! Binary encoding of client-computed fingerprints
+//!
+//! Little endian, a 16 byte header followed by 8 bytes per fingerprint:
+//!
+//! | bytes | field                                   |
+//! |-------|-----------------------------------------|
+//! | 0-3   | magic `SNFP`                            |
+//! | 4-5   | fingerprint version (u16)               |
+//! | 6-7   | reserved, 0                             |
+//! | 8-11  | clip duration in ms, 0 if unknown (u32) |
+//! | 12-15 | fingerprint count (u32)                 |
+//! | 16-   | (hash u32, offset u32) pairs            |
+
+use crate::error::{AppError, Result};
+use crate::fingerprint::{frames_to_ms, FingerprintSettings, FINGERPRINT_VERSION};
+use crate::types::FingerprintPayload;
+
+pub const MAGIC: &[u8; 4] = b"SNFP";
+const HEADER_LEN: usize = 16;
+const PAIR_LEN: usize = 8;
+
+/// Far more than any clip needs; a 10 second clip has a few thousand
+pub const MAX_CLIENT_FINGERPRINTS: usize = 200_000;
+
+pub fn encode(payload: &FingerprintPayload) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.fingerprints.len() * PAIR_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&payload.version.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    let duration_ms = payload.duration_ms.unwrap_or(0).min(u32::MAX as u64) as u32;
+    bytes.extend_from_slice(&duration_ms.to_le_bytes());
+    bytes.extend_from_slice(&(payload.fingerprints.len() as u32).to_le_bytes());
+    for &(hash, offset) in &payload.fingerprints {
+        bytes.extend_from_slice(&hash.to_le_bytes());
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<FingerprintPayload> {
+    if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+        return Err(AppError::InvalidRequest(
+            "Not a fingerprint payload (missing SNFP header)".to_string(),
+        ));
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    let duration_ms = read_u32(bytes, 8);
+    let count = read_u32(bytes, 12) as usize;
+
+    let body = &bytes[HEADER_LEN..];
+    if count > MAX_CLIENT_FINGERPRINTS || body.len() != count * PAIR_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "Fingerprint payload declares {} fingerprints but has {} bytes of them",
+            count,
+            body.len()
+        )));
+    }
+
+    let fingerprints = body
+        .chunks_exact(PAIR_LEN)
+        .map(|pair| (read_u32(pair, 0), read_u32(pair, 4)))
+        .collect();
+
+    Ok(FingerprintPayload {
+        version,
+        duration_ms: (duration_ms > 0).then_some(duration_ms as u64),
+        fingerprints,
+    })
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Check a decoded payload can be matched against this server's index,
+/// which was fingerprinted with `settings`
+pub fn validate(payload: &FingerprintPayload, settings: &FingerprintSettings) -> Result<()> {
+    if payload.version != FINGERPRINT_VERSION {
+        return Err(AppError::InvalidRequest(format!(
+            "Fingerprint version {} is not supported, this server indexes version {}",
+            payload.version, FINGERPRINT_VERSION
+        )));
+    }
+    // Clients fingerprint with the defaults, which won't line up with a preprocessed index
+    if *settings != FingerprintSettings::default() {
+        return Err(AppError::InvalidRequest(
+            "This index uses custom fingerprint settings, send the audio to /recognize instead"
+                .to_string(),
+        ));
+    }
+    if payload.fingerprints.len() > MAX_CLIENT_FINGERPRINTS {
+        return Err(AppError::InvalidRequest(format!(
+            "At most {} fingerprints are accepted",
+            MAX_CLIENT_FINGERPRINTS
+        )));
+    }
+    Ok(())
+}
+
+/// Clip duration from the payload, or estimated from the last offset
+pub fn query_duration_ms(payload: &FingerprintPayload) -> u64 {
+    payload.duration_ms.unwrap_or_else(|| {
+        payload
+            .fingerprints
+            .iter()
+            .map(|&(_, offset)| frames_to_ms(offset as i64 + 1) as u64)
+            .max()
+            .unwrap_or(0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> FingerprintPayload {
+        FingerprintPayload {
+            version: FINGERPRINT_VERSION,
+            duration_ms: Some(8000),
+            fingerprints: vec![(0xdead_beef, 0), (7, 12), (u32::MAX, 250)],
+        }
+    }
+
+    fn rejected(bytes: &[u8]) -> String {
+        match decode(bytes) {
+            Err(AppError::InvalidRequest(message)) => message,
+            other => panic!("expected an invalid request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encoded_payloads_decode_unchanged() {
+        let bytes = encode(&payload());
+        assert_eq!(bytes.len(), HEADER_LEN + 3 * PAIR_LEN);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.version, FINGERPRINT_VERSION);
+        assert_eq!(decoded.duration_ms, Some(8000));
+        assert_eq!(decoded.fingerprints, payload().fingerprints);
+
+        // A zero duration means unknown, estimated from the last offset
+        let unknown = decode(&encode(&FingerprintPayload {
+            duration_ms: None,
+            ..payload()
+        }))
+        .unwrap();
+        assert_eq!(unknown.duration_ms, None);
+        assert_eq!(query_duration_ms(&unknown), frames_to_ms(251) as u64);
+    }
+
+    #[test]
+    fn bad_magic_and_short_headers_are_rejected() {
+        let mut bytes = encode(&payload());
+        bytes[0..4].copy_from_slice(b"RIFF");
+        assert!(rejected(&bytes).contains("SNFP"));
+        assert!(rejected(&MAGIC[..]).contains("SNFP"));
+        assert!(rejected(&[]).contains("SNFP"));
+    }
+
+    #[test]
+    fn unsupported_versions_fail_validation() {
+        let bytes = encode(&FingerprintPayload {
+            version: FINGERPRINT_VERSION + 1,
+            ..payload()
+        });
+        let decoded = decode(&bytes).unwrap();
+        match validate(&decoded, &FingerprintSettings::default()) {
+            Err(AppError::InvalidRequest(message)) => assert!(message.contains("not supported")),
+            other => panic!("expected an invalid request, got {:?}", other),
+        }
+        assert!(validate(&payload(), &FingerprintSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn counts_must_match_the_body() {
+        let mut bytes = encode(&payload());
+        bytes[12..16].copy_from_slice(&4u32.to_le_bytes());
+        assert!(rejected(&bytes).contains("declares 4 fingerprints"));
+
+        // Cut mid-pair, and with trailing bytes after the last pair
+        let bytes = encode(&payload());
+        assert!(rejected(&bytes[..bytes.len() - 3]).contains("declares 3 fingerprints"));
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(rejected(&padded).contains("declares 3 fingerprints"));
+
+        // A huge count is refused before anything is allocated for it
+        let mut bytes = encode(&payload());
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(rejected(&bytes).contains("declares"));
+    }
+
+    #[test]
+    fn json_payloads_are_validated_the_same_way() {
+        let json = format!(
+            r#"{{"version": {}, "fingerprints": [[3735928559, 0], [7, 12]]}}"#,
+            FINGERPRINT_VERSION
+        );
+        let decoded: FingerprintPayload = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.duration_ms, None);
+        assert_eq!(decoded.fingerprints, vec![(0xdead_beef, 0), (7, 12)]);
+        assert!(validate(&decoded, &FingerprintSettings::default()).is_ok());
+
+        let too_many = FingerprintPayload {
+            fingerprints: vec![(1, 1); MAX_CLIENT_FINGERPRINTS + 1],
+            ..payload()
+        };
+        assert!(validate(&too_many, &FingerprintSettings::default()).is_err());
+
+        let malformed = r#"{"version": 1, "fingerprints": [[1]]}"#;
+        assert!(serde_json::from_str::<FingerprintPayload>(malformed).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! End-to-end index → recognize tests on synthetic audio and in-memory databases
+
+use sonica_backend::augment::Augmentation;
+use sonica_backend::config::Config;
+use sonica_backend::cover::recognize_cover;
+use sonica_backend::explain::explain_samples;
+use sonica_backend::fingerprint::{
+    fingerprint, FingerprintSettings, HashScheme, Normalization, Preprocessing, FINGERPRINT_VERSION,
+};
+use sonica_backend::indexer::{index_samples, IndexOutcome};
+use sonica_backend::metadata::ExtractedMetadata;
+use sonica_backend::recognizer::{recognize_fingerprints, recognize_samples};
+use sonica_backend::storage::Database;
+use sonica_backend::test_support::{clip, mix, rendition, silence, song, white_noise, Rendition};
+use sonica_backend::types::{
+    DuplicateKind, FingerprintPayload, MatchDecision, NewRecognition, RecognitionSource,
+    TrackDetails, Verdict,
+};
+use sonica_backend::visualize::constellation;
+use sonica_backend::wire;
+use std::time::{Duration, Instant};
+
+const SONG_SECONDS: f32 = 30.0;
+
+fn metadata(title: &str) -> ExtractedMetadata {
+    ExtractedMetadata {
+        title: title.to_string(),
+        artist: "Synthetic".to_string(),
+        details: TrackDetails::default(),
+        artwork: None,
+    }
+}
+
+/// An in-memory library of the songs with `seeds`, returning their ids in order
+fn library(seeds: &[u64]) -> (Database, Vec<i64>) {
+    library_with(&FingerprintSettings::default(), seeds)
+}
+
+/// `library`, fingerprinted with `settings`
+fn library_with(settings: &FingerprintSettings, seeds: &[u64]) -> (Database, Vec<i64>) {
+    let db = Database::open_in_memory().unwrap();
+    db.adopt_fingerprint_settings(settings).unwrap();
+    let ids = seeds
+        .iter()
+        .map(|&seed| {
+            let samples = song(seed, SONG_SECONDS);
+            let outcome = index_samples(
+                &db,
+                &format!("synthetic/{}", seed),
+                &metadata(&format!("Song {}", seed)),
+                &samples,
+            )
+            .unwrap();
+            match outcome {
+                IndexOutcome::Indexed { song_id, .. } => song_id,
+                IndexOutcome::Duplicate { .. } => panic!("song {} indexed as a duplicate", seed),
+            }
+        })
+        .collect();
+    (db, ids)
+}
+
+#[test]
+fn recognizes_a_clip_from_the_middle_of_a_song() {
+    let (db, ids) = library(&[1, 2, 3, 4, 5]);
+    let config = Config::default();
+
+    for (seed, id) in [1, 2, 3, 4, 5].into_iter().zip(&ids) {
+        let samples = song(seed, SONG_SECONDS);
+        let recognition = recognize_samples(&db, &config, clip(&samples, 11.0, 8.0), None).unwrap();
+
+        assert!(recognition.raw_score() > config.match_threshold);
+        assert_eq!(recognition.query_duration_ms, 8000);
+        let matched = recognition.song.expect("clip should match");
+        assert_eq!(matched.id, *id);
+        assert_eq!(matched.title, format!("Song {}", seed));
+    }
+}
+
+#[test]
+fn client_fingerprints_match_the_same_song_as_audio() {
+    let (db, ids) = library(&[1, 2, 3]);
+    let config = Config::default();
+    let samples = song(2, SONG_SECONDS);
+    let query = clip(&samples, 9.0, 8.0);
+
+    // What a client computes and sends, through the binary encoding
+    let payload = FingerprintPayload {
+        version: FINGERPRINT_VERSION,
+        duration_ms: Some(8000),
+        fingerprints: fingerprint(query, &FingerprintSettings::default()),
+    };
+    let received = wire::decode(&wire::encode(&payload)).unwrap();
+    wire::validate(&received, &db.fingerprint_settings()).unwrap();
+
+    let from_audio = recognize_samples(&db, &config, query, None).unwrap();
+    let from_client = recognize_fingerprints(
+        &db,
+        &config,
+        &received.fingerprints,
+        wire::query_duration_ms(&received),
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(from_audio.song.as_ref().map(|song| song.id), Some(ids[1]));
+    assert_eq!(from_client.song.as_ref().map(|song| song.id), Some(ids[1]));
+    assert_eq!(from_client.candidates, from_audio.candidates);
+    assert_eq!(from_client.query_duration_ms, from_audio.query_duration_ms);
+}
+
+#[test]
+fn recognizes_clips_through_noise_and_reverb() {
+    let (db, ids) = library(&[10, 11, 12]);
+    let config = Config::default();
+    let samples = song(11, SONG_SECONDS);
+    let query = clip(&samples, 5.0, 10.0);
+
+    for spec in [
+        "white:10",
+        "pink:5",
+        "lowpass:2000",
+        "gain:-20",
+        "reverb:1.0",
+    ] {
+        let degraded = Augmentation::parse(spec).unwrap().apply(query, 42).unwrap();
+        let recognition = recognize_samples(&db, &config, &degraded, None).unwrap();
+        assert_eq!(
+            recognition.song.map(|song| song.id),
+            Some(ids[1]),
+            "{} should still match",
+            spec
+        );
+    }
+}
+
+#[test]
+fn deadline_exceeded_only_when_matching_stops_early() {
+    let (db, ids) = library(&[6, 7]);
+    let config = Config::default();
+    let samples = song(7, SONG_SECONDS);
+    let query = clip(&samples, 5.0, 8.0);
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let full = recognize_samples(&db, &config, query, Some(deadline)).unwrap();
+    assert!(!full.deadline_exceeded);
+    assert_eq!(full.song.as_ref().map(|song| song.id), Some(ids[1]));
+
+    let cut = recognize_samples(&db, &config, query, Some(Instant::now())).unwrap();
+    assert!(cut.deadline_exceeded);
+    assert!(cut.candidates.is_empty());
+
+    // Only the complete recognition is labelled data for tuning
+    for recognition in [&full, &cut] {
+        let recognition_id = db
+            .insert_recognition(&NewRecognition {
+                source: RecognitionSource::Http,
+                client_id: None,
+                query_duration_ms: recognition.query_duration_ms,
+                hash_count: recognition.hash_count,
+                top_song_id: Some(ids[1]),
+                raw_score: recognition.raw_score(),
+                confidence: recognition.confidence,
+                latency_ms: 0,
+                matched: recognition.song.is_some(),
+                deadline_exceeded: recognition.deadline_exceeded,
+                candidates: &recognition.candidates,
+            })
+            .unwrap();
+        db.set_feedback(recognition_id, Verdict::Correct, Some(ids[1]))
+            .unwrap();
+    }
+    let labelled = db.labelled_scores().unwrap();
+    assert_eq!(labelled.len(), 1);
+    assert_eq!(labelled[0].raw_score, full.raw_score());
+}
+
+#[test]
+fn songs_outside_the_library_do_not_match() {
+    let (db, _) = library(&[20, 21, 22]);
+    let config = Config::default();
+
+    for seed in [23, 24, 25] {
+        let samples = song(seed, 10.0);
+        let recognition = recognize_samples(&db, &config, &samples, None).unwrap();
+        assert!(
+            recognition.song.is_none(),
+            "song {} matched with score {}",
+            seed,
+            recognition.raw_score()
+        );
+    }
+}
+
+#[test]
+fn idf_weighted_scores_stay_in_hit_count_units() {
+    let (db, ids) = library(&[40, 41, 42, 43]);
+    let unweighted = Config::default();
+    let weighted = Config {
+        idf_weighting: true,
+        ..Config::default()
+    };
+    let samples = song(42, SONG_SECONDS);
+    let query = clip(&samples, 8.0, 8.0);
+
+    let plain = recognize_samples(&db, &unweighted, query, None).unwrap();
+    let idf = recognize_samples(&db, &weighted, query, None).unwrap();
+    assert_eq!(idf.song.as_ref().map(|song| song.id), Some(ids[2]));
+    // The median hash counts as 1, so the score neither collapses nor balloons
+    let ratio = idf.raw_score() as f64 / plain.raw_score() as f64;
+    assert!((0.5..2.0).contains(&ratio), "weighted/unweighted {}", ratio);
+
+    let outside = recognize_samples(&db, &weighted, &song(44, 10.0), None).unwrap();
+    assert!(outside.song.is_none());
+}
+
+#[test]
+fn silence_and_noise_do_not_match() {
+    let (db, _) = library(&[30, 31]);
+    let config = Config::default();
+
+    let recognition = recognize_samples(&db, &config, &silence(5.0), None).unwrap();
+    assert_eq!(recognition.hash_count, 0);
+    assert!(recognition.candidates.is_empty());
+    assert!(recognition.song.is_none());
+
+    let recognition = recognize_samples(&db, &config, &white_noise(5.0, 0.5, 1), None).unwrap();
+    assert!(recognition.song.is_none());
+}
+
+#[test]
+fn identical_audio_is_linked_as_a_duplicate() {
+    let (db, ids) = library(&[40, 41]);
+    let samples = song(40, SONG_SECONDS);
+
+    let outcome = index_samples(&db, "copy/40", &metadata("Copy"), &samples).unwrap();
+    assert!(matches!(
+        outcome,
+        IndexOutcome::Duplicate { song_id, kind: DuplicateKind::ContentHash } if song_id == ids[0]
+    ));
+
+    // A re-encode changes the samples but not the fingerprints
+    let quieter = Augmentation::Gain { db: -3.0 }.apply(&samples, 0).unwrap();
+    let outcome = index_samples(&db, "reencode/40", &metadata("Re-encode"), &quieter).unwrap();
+    assert!(matches!(
+        outcome,
+        IndexOutcome::Duplicate { song_id, kind: DuplicateKind::Fingerprint } if song_id == ids[0]
+    ));
+
+    assert_eq!(db.get_all_songs().unwrap().len(), 2);
+    assert_eq!(db.get_duplicates().unwrap().len(), 2);
+}
+
+#[test]
+fn in_memory_databases_are_independent() {
+    let (first, _) = library(&[50]);
+    let second = Database::open_in_memory().unwrap();
+
+    assert_eq!(first.get_all_songs().unwrap().len(), 1);
+    assert!(second.get_all_songs().unwrap().is_empty());
+}
+
+#[test]
+fn explanation_agrees_with_recognition() {
+    let (db, ids) = library(&[60, 61, 62]);
+    let config = Config::default();
+    let samples = song(62, SONG_SECONDS);
+    let query = clip(&samples, 12.0, 6.0);
+
+    let explanation = explain_samples(&db, &config, query).unwrap();
+    let recognition = recognize_samples(&db, &config, query, None).unwrap();
+
+    assert_eq!(explanation.decision, MatchDecision::Matched);
+    assert_eq!(explanation.candidates[0].song_id, ids[2]);
+    assert_eq!(explanation.best_score, recognition.raw_score());
+    assert_eq!(explanation.hash_count, recognition.hash_count);
+}
+
+#[test]
+fn constellation_marks_peaks_aligned_with_the_reference() {
+    let (db, ids) = library(&[70, 71]);
+    let samples = song(70, SONG_SECONDS);
+    let background = white_noise(SONG_SECONDS, 0.05, 3);
+    let query = mix(&[(clip(&samples, 4.0, 8.0), 1.0), (&background, 1.0)]);
+
+    let matching =
+        constellation(&query, &FingerprintSettings::default(), Some((&db, ids[0]))).unwrap();
+    let other =
+        constellation(&query, &FingerprintSettings::default(), Some((&db, ids[1]))).unwrap();
+
+    let reference = matching.reference.as_ref().unwrap();
+    assert_eq!(reference.song_id, ids[0]);
+    assert!(!matching.matched.is_empty());
+    assert!(matching.matched.len() > other.matched.len());
+    assert!(matching
+        .matched
+        .iter()
+        .all(|peak| matching.peaks.contains(peak)));
+}
+
+fn noise_robust() -> FingerprintSettings {
+    FingerprintSettings {
+        preprocessing: Preprocessing {
+            high_pass_hz: Some(80.0),
+            pre_emphasis: Some(0.97),
+            noise_reduction: Some(1.0),
+            normalization: Some(Normalization::Whitening),
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn fingerprint_settings_are_locked_once_songs_are_indexed() {
+    let db = Database::open_in_memory().unwrap();
+    assert_eq!(db.fingerprint_settings(), FingerprintSettings::default());
+
+    let settings = noise_robust();
+    assert_eq!(db.adopt_fingerprint_settings(&settings).unwrap(), settings);
+    index_samples(&db, "synthetic/80", &metadata("Song 80"), &song(80, 10.0)).unwrap();
+
+    let in_effect = db
+        .adopt_fingerprint_settings(&FingerprintSettings::default())
+        .unwrap();
+    assert_eq!(in_effect, settings);
+    assert_eq!(db.fingerprint_settings(), settings);
+}
+
+#[test]
+fn preprocessed_index_recognizes_noisy_clips() {
+    let (db, ids) = library_with(&noise_robust(), &[90, 91, 92]);
+    let config = Config::default();
+    let samples = song(91, SONG_SECONDS);
+    let query = clip(&samples, 8.0, 10.0);
+
+    for spec in ["clean", "white:5", "pink:5", "gain:-20"] {
+        let degraded = Augmentation::parse(spec).unwrap().apply(query, 7).unwrap();
+        let recognition = recognize_samples(&db, &config, &degraded, None).unwrap();
+        assert_eq!(
+            recognition.song.map(|song| song.id),
+            Some(ids[1]),
+            "{} should match with preprocessing",
+            spec
+        );
+    }
+
+    let recognition = recognize_samples(&db, &config, &song(93, 10.0), None).unwrap();
+    assert!(recognition.song.is_none());
+}
+
+#[test]
+fn triplet_index_recognizes_sped_up_clips() {
+    let settings = FingerprintSettings {
+        scheme: HashScheme::Triplets,
+        ..Default::default()
+    };
+    let (db, ids) = library_with(&settings, &[100, 101, 102]);
+    let config = Config::default();
+    let samples = song(101, SONG_SECONDS);
+    let query = clip(&samples, 6.0, 10.0);
+
+    // Slowed down, untouched and sped up, nightcore style: pitch and tempo change together
+    for factor in [0.8, 0.9, 1.0, 1.1, 1.25] {
+        let degraded = Augmentation::Speed { factor }.apply(query, 0).unwrap();
+        let explanation = explain_samples(&db, &config, &degraded).unwrap();
+        assert_eq!(
+            explanation.decision,
+            MatchDecision::Matched,
+            "speed {}",
+            factor
+        );
+        let best = &explanation.candidates[0];
+        assert_eq!(best.song_id, ids[1]);
+        assert!(
+            (best.time_scale - factor as f64).abs() < 0.03,
+            "speed {} estimated as {}",
+            factor,
+            best.time_scale
+        );
+    }
+
+    for seed in [103, 104, 105] {
+        let recognition = recognize_samples(&db, &config, &song(seed, 10.0), None).unwrap();
+        assert!(recognition.song.is_none(), "song {} matched", seed);
+    }
+}
+
+#[test]
+fn triplet_index_does_not_match_silence_or_noise() {
+    // Triplets align within a frame either way, so chance alignments are likelier than
+    // with pairs; they share the default threshold, which has to hold for both
+    let settings = FingerprintSettings {
+        scheme: HashScheme::Triplets,
+        ..Default::default()
+    };
+    let (db, _) = library_with(&settings, &[30, 31, 32]);
+    let config = Config::default();
+
+    let recognition = recognize_samples(&db, &config, &silence(5.0), None).unwrap();
+    assert_eq!(recognition.hash_count, 0);
+    assert!(recognition.song.is_none());
+
+    for seed in 1..=5 {
+        let noise = white_noise(10.0, 0.5, seed);
+        let recognition = recognize_samples(&db, &config, &noise, None).unwrap();
+        assert!(recognition.song.is_none(), "noise {} matched", seed);
+        assert!(
+            recognition.raw_score() <= config.match_threshold / 2,
+            "noise {} scored {}",
+            seed,
+            recognition.raw_score()
+        );
+    }
+}
+
+#[test]
+fn pair_index_misses_sped_up_clips() {
+    let (db, _) = library(&[100, 101, 102]);
+    let config = Config::default();
+    let samples = song(101, SONG_SECONDS);
+    let sped_up = Augmentation::Speed { factor: 1.1 }
+        .apply(clip(&samples, 6.0, 10.0), 0)
+        .unwrap();
+
+    let recognition = recognize_samples(&db, &config, &sped_up, None).unwrap();
+    assert!(recognition.song.is_none());
+}
+
+#[test]
+fn covers_are_identified_across_key_tempo_and_timbre() {
+    let (db, ids) = library(&[110, 111, 112, 113]);
+    let config = Config::default();
+
+    for (index, seed, cover) in [
+        (1, 111, Rendition::default()),
+        (
+            1,
+            111,
+            Rendition {
+                transpose: 3,
+                tempo: 0.9,
+                harmonics: true,
+                drum_seed: Some(1),
+            },
+        ),
+        (
+            2,
+            112,
+            Rendition {
+                transpose: -2,
+                tempo: 1.1,
+                harmonics: true,
+                drum_seed: Some(2),
+            },
+        ),
+        (
+            3,
+            113,
+            Rendition {
+                transpose: 5,
+                drum_seed: Some(3),
+                ..Default::default()
+            },
+        ),
+    ] {
+        let samples = rendition(seed, SONG_SECONDS, &cover);
+        let recognition = recognize_cover(&db, &config, clip(&samples, 10.0, 15.0)).unwrap();
+        let transposition = recognition.top_candidate().unwrap().transposition;
+        assert_eq!(transposition, cover.transpose, "{:?}", cover);
+        assert_eq!(recognition.song.map(|song| song.id), Some(ids[index]));
+    }
+}
+
+#[test]
+fn unrelated_songs_are_not_covers() {
+    let (db, _) = library(&[120, 121, 122]);
+    let config = Config::default();
+
+    for seed in [123, 124] {
+        let cover = Rendition {
+            transpose: 2,
+            harmonics: true,
+            ..Default::default()
+        };
+        let samples = rendition(seed, 15.0, &cover);
+        let recognition = recognize_cover(&db, &config, &samples).unwrap();
+        assert!(recognition.song.is_none());
+        assert!(recognition.candidates.len() == 3);
+    }
+
+    let recognition = recognize_cover(&db, &config, &silence(10.0)).unwrap();
+    assert!(recognition.song.is_none());
+    let recognition = recognize_cover(&db, &config, &silence(2.0)).unwrap();
+    assert!(recognition.candidates.is_empty());
+}